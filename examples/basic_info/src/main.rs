@@ -1,5 +1,4 @@
 use chatex_sdk_rust;
-use hyper_tls;
 use simple_log;
 use dotenv;
 
@@ -7,17 +6,8 @@ use dotenv;
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     simple_log::quick().ok();
     dotenv::dotenv().ok();
-    let base_url = std::env::var("BASE_URL")
-        .expect("Failed to get BASE_URL variable")
-        .parse::<url::Url>()
-        .expect("Failed to parse url");
-    let secret = std::env::var("API_KEY")
-        .expect("Failed to get API_KEY variable");
-    let https = hyper_tls::HttpsConnector::new();
-    let chatex = chatex_sdk_rust::ChatexClient::new(
-        https,
-        base_url,
-        secret);
+    let config = chatex_sdk_rust::config::Config::load(None, None)?;
+    let chatex = chatex_sdk_rust::ChatexClient::https_from_config(&config)?;
     let basic_info = chatex.profile().get_account_information().await;
     println!("Basic info: {:?}", basic_info);
     Ok(())
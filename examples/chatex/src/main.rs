@@ -0,0 +1,635 @@
+//! `chatex` command-line tool built on `ChatexClient`.
+use chatex_sdk_rust::{bulk, coin, config, models, tls};
+use clap::Parser;
+
+mod output;
+
+type Client = chatex_sdk_rust::ChatexClient<tls::HttpsBackend>;
+type CliResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(clap::Parser, Debug)]
+#[command(name = "chatex", about = "Chatex API from the shell")]
+struct Cli {
+    /// API base url, e.g. https://api.chatex.com/v1/. Overrides CHATEX_BASE_URL.
+    #[arg(long, global = true)]
+    base_url: Option<String>,
+    /// API key used to obtain access tokens. Overrides CHATEX_API_KEY.
+    #[arg(long, global = true)]
+    api_key: Option<String>,
+    /// Profile from the config file, defaults to CHATEX_PROFILE or "default".
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Config file, defaults to CHATEX_CONFIG or ~/.config/chatex/config.toml.
+    #[arg(long, global = true)]
+    config: Option<std::path::PathBuf>,
+    #[arg(long, short, value_enum, default_value = "table", global = true)]
+    output: output::Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Account information.
+    Me,
+    /// Balance summary.
+    Balance,
+    /// Available coins.
+    Coins,
+    #[command(subcommand)]
+    Orders(Orders),
+    /// Trades of the account.
+    Trades {
+        #[arg(long)]
+        order_id: Option<u32>,
+        #[command(flatten)]
+        page: Page,
+    },
+    #[command(subcommand)]
+    Invoices(Invoices),
+    #[command(subcommand)]
+    PaymentSystems(PaymentSystems),
+}
+
+#[derive(clap::Args, Debug)]
+struct Page {
+    #[arg(long)]
+    offset: Option<u32>,
+    #[arg(long)]
+    limit: Option<u32>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Orders {
+    /// Order book of the pair, e.g. btc/usdt_erc20.
+    List {
+        #[arg(value_parser = parse_pair)]
+        pair: coin::CoinPair,
+        #[command(flatten)]
+        page: Page,
+    },
+    /// Orders of the account.
+    My {
+        #[arg(long, value_parser = parse_pair)]
+        pair: Option<coin::CoinPair>,
+        #[arg(long)]
+        status: Option<String>,
+        #[command(flatten)]
+        page: Page,
+    },
+    Get {
+        id: String,
+    },
+    Create {
+        #[arg(value_parser = parse_pair)]
+        pair: coin::CoinPair,
+        amount: String,
+        rate: String,
+    },
+    Update {
+        id: String,
+        amount: String,
+        rate: String,
+    },
+    Delete {
+        id: String,
+    },
+    Activate {
+        id: String,
+    },
+    Deactivate {
+        id: String,
+    },
+    /// Deletes every open order of the account.
+    CancelAll {
+        #[arg(long, value_parser = parse_pair)]
+        pair: Option<coin::CoinPair>,
+        #[arg(long, default_value_t = bulk::DEFAULT_CONCURRENCY)]
+        concurrency: usize,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Invoices {
+    List {
+        #[arg(long = "coin")]
+        coins: Vec<String>,
+        #[arg(long = "status", value_enum)]
+        statuses: Vec<InvoiceStatus>,
+        #[command(flatten)]
+        page: Page,
+    },
+    Get {
+        id: String,
+    },
+    Create {
+        #[arg(long)]
+        amount: String,
+        #[arg(long)]
+        coin: String,
+        #[arg(long)]
+        fiat: String,
+        #[arg(long)]
+        country_code: String,
+        #[arg(long)]
+        lang_id: String,
+        #[arg(long)]
+        payment_system_id: String,
+        #[arg(long, default_value = "")]
+        callback_url: String,
+        #[arg(long, default_value = "")]
+        redirect_url: String,
+        #[arg(long, default_value = "")]
+        data: String,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum InvoiceStatus {
+    Unassigned,
+    Active,
+    Completed,
+    Canceled,
+}
+
+impl From<InvoiceStatus> for models::InvoiceStatus {
+    fn from(status: InvoiceStatus) -> models::InvoiceStatus {
+        match status {
+            InvoiceStatus::Unassigned => models::InvoiceStatus::Unassigned,
+            InvoiceStatus::Active => models::InvoiceStatus::Active,
+            InvoiceStatus::Completed => models::InvoiceStatus::Completed,
+            InvoiceStatus::Canceled => models::InvoiceStatus::Canceled,
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum PaymentSystems {
+    /// Fiat amounts the coin amount is worth in every payment system.
+    Estimate {
+        coin: String,
+        amount: String,
+    },
+    Get {
+        id: models::PaymentSystemId,
+    },
+}
+
+fn parse_pair(pair: &str) -> Result<coin::CoinPair, String> {
+    coin::CoinPair::parse(pair)
+        .ok_or_else(|| format!("Expected pair like btc/usdt_erc20, got {}", pair))
+}
+
+fn orders_table(orders: &[models::Order]) -> output::Table {
+    let mut table = output::Table::new(vec![
+        "ID", "PAIR", "AMOUNT", "RATE", "STATUS", "CREATED", "UPDATED",
+    ]);
+    for order in orders.iter() {
+        table.row(vec![
+            order.id.to_string(),
+            order.pair.clone(),
+            order.amount.clone(),
+            order.rate.clone(),
+            order.status.clone(),
+            order.created_at.clone(),
+            order.updated_at.clone(),
+        ]);
+    }
+    table
+}
+
+fn bulk_table(report: &bulk::BulkReport<u32, models::Order>) -> output::Table {
+    let mut table = output::Table::new(vec!["ID", "RESULT"]);
+    for entry in report.entries.iter() {
+        let result = match &entry.result {
+            Ok(order) => order.status.clone(),
+            Err(error) => format!("error: {}", error),
+        };
+        table.row(vec![entry.key.to_string(), result]);
+    }
+    table
+}
+
+fn trades_table(trades: &[models::Trade]) -> output::Table {
+    let mut table = output::Table::new(vec![
+        "ID", "ORDER", "PAIR", "AMOUNT", "RECEIVED", "FEE", "CREATED",
+    ]);
+    for trade in trades.iter() {
+        table.row(vec![
+            trade.id.to_string(),
+            trade.order.id.to_string(),
+            trade.order.pair.clone(),
+            trade.amount.clone(),
+            trade.received_amount.clone(),
+            trade.fee.clone(),
+            trade.created_at.clone(),
+        ]);
+    }
+    table
+}
+
+fn invoices_table(invoices: &[models::Invoice]) -> output::Table {
+    let mut table = output::Table::new(vec![
+        "ID",
+        "COIN",
+        "AMOUNT",
+        "FIAT",
+        "PAYMENT SYSTEM",
+        "STATUS",
+        "PAYMENT URL",
+    ]);
+    for invoice in invoices.iter() {
+        table.row(vec![
+            invoice.id.clone(),
+            invoice.coin.clone(),
+            invoice.amount.to_string(),
+            invoice.fiat.clone(),
+            invoice.payment_system_id.to_string(),
+            invoice.status.clone(),
+            invoice.payment_url.clone(),
+        ]);
+    }
+    table
+}
+
+async fn run(client: Client, command: Command, format: output::Format) -> CliResult {
+    match command {
+        Command::Me => {
+            let info = client.profile().get_account_information().await?;
+            output::print(format, &info, |info| {
+                let mut table = output::Table::new(vec!["FIELD", "VALUE"]);
+                let profile = &info.profile;
+                let limits = &profile.limits;
+                table
+                    .row(vec!["id".into(), info.id.to_string()])
+                    .row(vec!["username".into(), profile.username.clone()])
+                    .row(vec![
+                        "email".into(),
+                        profile.email.clone().unwrap_or_default(),
+                    ])
+                    .row(vec!["country".into(), profile.country_code.clone()])
+                    .row(vec![
+                        "verification".into(),
+                        profile.verification.current_level.clone(),
+                    ])
+                    .row(vec![
+                        "finance blocked".into(),
+                        profile.is_finance_blocked.to_string(),
+                    ])
+                    .row(vec![
+                        "turnover".into(),
+                        format!(
+                            "{} / {}",
+                            limits.current_turnover, limits.turnover_limit
+                        ),
+                    ])
+                    .row(vec![
+                        "withdraw".into(),
+                        format!(
+                            "{} / {}",
+                            limits.current_withdraw, limits.withdraw_limit
+                        ),
+                    ]);
+                table
+            })?;
+        }
+        Command::Balance => {
+            let balance = client.profile().get_balance_summary().await?;
+            output::print(format, &balance, |balance| {
+                let mut table = output::Table::new(vec!["COIN", "AMOUNT", "HELD"]);
+                for currency in balance.iter() {
+                    table.row(vec![
+                        currency.coin.clone(),
+                        currency.amount.clone(),
+                        currency.held.clone(),
+                    ]);
+                }
+                table
+            })?;
+        }
+        Command::Coins => {
+            let coins = client.coin().get_available_coins().await?;
+            output::print(format, &coins, |coins| {
+                let mut table = output::Table::new(vec!["NAME", "FULL NAME", "DECIMALS"]);
+                for coin in coins.iter() {
+                    table.row(vec![
+                        coin.name.clone(),
+                        coin.full_name.clone(),
+                        coin.decimals.to_string(),
+                    ]);
+                }
+                table
+            })?;
+        }
+        Command::Orders(orders) => {
+            let exchange = client.exchange();
+            match orders {
+                Orders::List { pair, page } => {
+                    let orders = exchange
+                        .get_all_orders(pair, page.offset, page.limit)
+                        .await?;
+                    output::print(format, &orders, |orders: &models::Orders| {
+                        orders_table(orders)
+                    })?;
+                }
+                Orders::My { pair, status, page } => {
+                    let orders = exchange
+                        .get_my_orders(pair, status, page.offset, page.limit)
+                        .await?;
+                    output::print(format, &orders, |orders: &models::Orders| {
+                        orders_table(orders)
+                    })?;
+                }
+                Orders::Get { id } => {
+                    let order = exchange.get_order_by_id(&id).await?;
+                    output::print(format, &order, |order| {
+                        orders_table(std::slice::from_ref(order))
+                    })?;
+                }
+                Orders::Create { pair, amount, rate } => {
+                    let order = exchange.create_order_raw(pair, &amount, &rate).await?;
+                    output::print(format, &order, |order| {
+                        orders_table(std::slice::from_ref(order))
+                    })?;
+                }
+                Orders::Update { id, amount, rate } => {
+                    let update = models::UpdateOrder { amount, rate };
+                    let order = exchange.update_order_by_id(&id, &update).await?;
+                    output::print(format, &order, |order| {
+                        orders_table(std::slice::from_ref(order))
+                    })?;
+                }
+                Orders::Delete { id } => {
+                    let order = exchange.delete_order_by_id(&id).await?;
+                    output::print(format, &order, |order| {
+                        orders_table(std::slice::from_ref(order))
+                    })?;
+                }
+                Orders::Activate { id } => {
+                    let order = exchange.activate_order_by_id(&id).await?;
+                    output::print(format, &order, |order| {
+                        orders_table(std::slice::from_ref(order))
+                    })?;
+                }
+                Orders::Deactivate { id } => {
+                    let order = exchange.deactivate_order_by_id(&id).await?;
+                    output::print(format, &order, |order| {
+                        orders_table(std::slice::from_ref(order))
+                    })?;
+                }
+                Orders::CancelAll { pair, concurrency } => {
+                    let report = exchange.cancel_all_orders(pair, concurrency).await?;
+                    let results: Vec<serde_json::Value> = report
+                        .entries
+                        .iter()
+                        .map(|entry| match &entry.result {
+                            Ok(order) => serde_json::json!({
+                                "id": entry.key,
+                                "status": order.status,
+                            }),
+                            Err(error) => serde_json::json!({
+                                "id": entry.key,
+                                "error": error.to_string(),
+                            }),
+                        })
+                        .collect();
+                    output::print(format, &results, |_| bulk_table(&report))?;
+                }
+            }
+        }
+        Command::Trades { order_id, page } => {
+            let trades = client
+                .exchange()
+                .get_trades(order_id, page.offset, page.limit)
+                .await?;
+            output::print(format, &trades, |trades: &models::Trades| {
+                trades_table(trades)
+            })?;
+        }
+        Command::Invoices(invoices) => {
+            let invoice = client.invoice();
+            match invoices {
+                Invoices::List {
+                    coins,
+                    statuses,
+                    page,
+                } => {
+                    let coins: Vec<coin::Coin> =
+                        coins.iter().map(|coin| coin.as_str().into()).collect();
+                    let statuses: Vec<models::InvoiceStatus> =
+                        statuses.into_iter().map(Into::into).collect();
+                    let invoices = invoice
+                        .get_invoices(
+                            Some(&coins)
+                                .filter(|coins| !coins.is_empty())
+                                .map(Vec::as_slice),
+                            None,
+                            None,
+                            None,
+                            None,
+                            Some(&statuses)
+                                .filter(|statuses| !statuses.is_empty())
+                                .map(Vec::as_slice),
+                            page.offset,
+                            page.limit,
+                            None,
+                            None,
+                        )
+                        .await?;
+                    output::print(format, &invoices, |invoices| {
+                        invoices_table(invoices)
+                    })?;
+                }
+                Invoices::Get { id } => {
+                    let found = invoice.get_invoice_by_id(&id).await?;
+                    output::print(format, &found, |found| {
+                        invoices_table(std::slice::from_ref(found))
+                    })?;
+                }
+                Invoices::Create {
+                    amount,
+                    coin,
+                    fiat,
+                    country_code,
+                    lang_id,
+                    payment_system_id,
+                    callback_url,
+                    redirect_url,
+                    data,
+                } => {
+                    let created = invoice
+                        .create_invoice(models::CreateInvoice {
+                            amount,
+                            callback_url,
+                            coin,
+                            country_code,
+                            data,
+                            fiat,
+                            lang_id,
+                            payment_system_id,
+                            redirect_url,
+                        })
+                        .await?;
+                    output::print(format, &created, |created: &models::Invoices| {
+                        invoices_table(created)
+                    })?;
+                }
+            }
+        }
+        Command::PaymentSystems(payment_systems) => {
+            let payment_system = client.payment_system();
+            match payment_systems {
+                PaymentSystems::Estimate { coin, amount } => {
+                    let estimations = payment_system
+                        .get_list_of_estimated_payment_systems(models::Estimate {
+                            amount,
+                            coin,
+                        })
+                        .await?;
+                    output::print(format, &estimations, |estimations| {
+                        let mut table = output::Table::new(vec![
+                            "FIAT",
+                            "PAYMENT SYSTEM ID",
+                            "PAYMENT SYSTEM",
+                            "ESTIMATED AMOUNT",
+                        ]);
+                        for estimation in estimations.iter() {
+                            for system in estimation.estimations.iter() {
+                                table.row(vec![
+                                    estimation.fiat.name.clone(),
+                                    system.payment_system.id.to_string(),
+                                    system.payment_system.name.clone(),
+                                    system.estimated_fiat_amount.to_string(),
+                                ]);
+                            }
+                        }
+                        table
+                    })?;
+                }
+                PaymentSystems::Get { id } => {
+                    let system = payment_system.get_payment_system_by_id(id).await?;
+                    output::print(format, &system, |system| {
+                        let mut table = output::Table::new(vec!["ID", "NAME"]);
+                        table.row(vec![system.id.to_string(), system.name.clone()]);
+                        table
+                    })?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Flags win over the environment and the config file.
+fn load_config(cli: &Cli) -> Result<config::Config, config::ConfigError> {
+    let mut profile =
+        config::Profile::load(cli.config.as_deref(), cli.profile.as_deref())?;
+    if let Some(base_url) = cli.base_url.clone() {
+        profile.base_url = Some(base_url);
+    }
+    if let Some(api_key) = cli.api_key.clone() {
+        profile.api_key = Some(api_key.into());
+    }
+    profile.resolve()
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let config = match load_config(&cli) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("chatex: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let client = match chatex_sdk_rust::ChatexClient::https_from_config(&config) {
+        Ok(client) => client,
+        Err(error) => {
+            eprintln!("chatex: {}", error);
+            std::process::exit(2);
+        }
+    };
+    if let Err(error) = run(client, cli.command, cli.output).await {
+        eprintln!("chatex: {}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("chatex").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn command_is_valid() {
+        <Cli as clap::CommandFactory>::command().debug_assert();
+    }
+
+    #[test]
+    fn parses_global_flags_after_subcommand() {
+        let cli = parse(&["balance", "--output", "json", "--profile", "test"]).unwrap();
+        assert!(matches!(cli.command, Command::Balance));
+        assert_eq!(cli.output, output::Format::Json);
+        assert_eq!(cli.profile.as_deref(), Some("test"));
+        assert_eq!(parse(&["me"]).unwrap().output, output::Format::Table);
+    }
+
+    #[test]
+    fn parses_order_commands() {
+        let cli = parse(&["orders", "list", "btc/usdt_erc20", "--limit", "5"]).unwrap();
+        match cli.command {
+            Command::Orders(Orders::List { pair, page }) => {
+                assert_eq!(pair, coin::CoinPair::new(coin::Coin::BTC, coin::Coin::USDT));
+                assert_eq!(page.limit, Some(5));
+                assert_eq!(page.offset, None);
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+        let cli = parse(&["orders", "cancel-all"]).unwrap();
+        match cli.command {
+            Command::Orders(Orders::CancelAll { pair, concurrency }) => {
+                assert!(pair.is_none());
+                assert_eq!(concurrency, bulk::DEFAULT_CONCURRENCY);
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+        assert!(parse(&["orders", "list", "btc"]).is_err());
+        assert!(parse(&["orders", "create", "btc/usdt_erc20", "1"]).is_err());
+    }
+
+    #[test]
+    fn parses_repeated_invoice_filters() {
+        let cli = parse(&[
+            "invoices", "list", "--coin", "btc", "--coin", "eth", "--status", "active",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Invoices(Invoices::List {
+                coins, statuses, ..
+            }) => {
+                assert_eq!(coins, vec!["btc", "eth"]);
+                assert!(matches!(statuses.as_slice(), [InvoiceStatus::Active]));
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+        assert!(parse(&["invoices", "list", "--status", "paid"]).is_err());
+    }
+
+    #[test]
+    fn orders_table_has_a_row_per_order() {
+        let order = models::Order {
+            id: 7,
+            pair: "btc/usdt_erc20".to_owned(),
+            ..models::Order::default()
+        };
+        let table = orders_table(&[order]).to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("ID  PAIR"));
+        assert!(lines[1].starts_with("7   btc/usdt_erc20"));
+    }
+}
@@ -0,0 +1,120 @@
+//! Renders command results either as an aligned text table or as JSON.
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: Vec<&'static str>) -> Table {
+        Table {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(&mut self, row: Vec<String>) -> &mut Table {
+        self.rows.push(row);
+        self
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> =
+            self.headers.iter().map(|header| header.len()).collect();
+        for row in self.rows.iter() {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        widths
+    }
+}
+
+impl std::fmt::Display for Table {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let widths = self.widths();
+        let write_row = |formatter: &mut std::fmt::Formatter<'_>,
+                         cells: &mut dyn Iterator<Item = &str>|
+         -> std::fmt::Result {
+            let line = cells
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(formatter, "{}", line.trim_end())
+        };
+        write_row(formatter, &mut self.headers.iter().copied())?;
+        for row in self.rows.iter() {
+            write_row(formatter, &mut row.iter().map(String::as_str))?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders `value` in the requested format. `table` is only invoked for
+/// the table format.
+pub fn render<T, F>(
+    format: Format,
+    value: &T,
+    table: F,
+) -> Result<String, serde_json::Error>
+where
+    T: serde::Serialize,
+    F: FnOnce(&T) -> Table,
+{
+    match format {
+        Format::Json => Ok(format!("{}\n", serde_json::to_string_pretty(value)?)),
+        Format::Table => Ok(table(value).to_string()),
+    }
+}
+
+/// Prints `value` rendered by `render`.
+pub fn print<T, F>(format: Format, value: &T, table: F) -> Result<(), serde_json::Error>
+where
+    T: serde::Serialize,
+    F: FnOnce(&T) -> Table,
+{
+    print!("{}", render(format, value, table)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn create_table() -> Table {
+        let mut table = Table::new(vec!["ID", "NAME", "NOTE"]);
+        table
+            .row(vec!["1".into(), "bitcoin".into(), "".into()])
+            .row(vec!["42".into(), "eth".into(), "ünïcode".into()]);
+        table
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        assert_eq!(
+            create_table().to_string(),
+            "ID  NAME     NOTE\n\
+             1   bitcoin\n\
+             42  eth      ünïcode\n"
+        );
+    }
+
+    #[test]
+    fn renders_table_or_json() {
+        let value = serde_json::json!({"id": 1});
+        let table = render(Format::Table, &value, |_| create_table()).unwrap();
+        assert_eq!(table, create_table().to_string());
+        let json = render(Format::Json, &value, |_| -> Table {
+            panic!("Table is not rendered for JSON")
+        })
+        .unwrap();
+        assert_eq!(json, "{\n  \"id\": 1\n}\n");
+    }
+}
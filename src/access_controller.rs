@@ -1,30 +1,72 @@
+use super::backend;
 use super::context;
 use super::endpoint;
 use super::error;
 use super::extractor;
-use hyper;
+use super::token_store;
+use super::transport;
+use chrono;
 
 pub struct AccessController {
     access_context: std::sync::RwLock<Option<context::AccessContext>>,
     profile: std::sync::Arc<endpoint::Profile>,
+    token_store: std::sync::Arc<dyn token_store::TokenStore>,
+    tolerance: chrono::Duration,
 }
 
 impl AccessController {
     pub fn new(profile: std::sync::Arc<endpoint::Profile>) -> AccessController {
+        AccessController::with_token_store(
+            profile,
+            std::sync::Arc::new(token_store::MemoryTokenStore::new()),
+        )
+    }
+
+    /// Creates a controller which loads the token from the `token_store`
+    /// before requesting a new one and persists every refreshed token.
+    pub fn with_token_store(
+        profile: std::sync::Arc<endpoint::Profile>,
+        token_store: std::sync::Arc<dyn token_store::TokenStore>,
+    ) -> AccessController {
         AccessController {
             access_context: std::sync::RwLock::new(None),
             profile,
+            token_store,
+            tolerance: chrono::Duration::seconds(
+                context::AccessContext::TIME_EXPIRATION_TOLERANCE,
+            ),
         }
     }
 
-    pub async fn get_access_token<TConnector>(
+    /// Sets how long before its expiration a token is treated as expired.
+    pub fn expiration_tolerance(mut self, tolerance: chrono::Duration) -> AccessController {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// When the current token should be renewed, `None` if there is no token yet.
+    pub fn next_refresh_at(
+        &self,
+        lifetime_fraction: f64,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.access_context
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|access_context| access_context.refresh_at(lifetime_fraction))
+    }
+
+    pub async fn get_access_token<TBackend>(
         &self,
         api_context: &context::ApiContext,
-        client: &hyper::Client<TConnector>,
-    ) -> Result<String, error::Error>
+        transport: &transport::Transport<TBackend>,
+    ) -> Result<context::AccessToken, error::Error>
     where
-        TConnector: hyper::client::connect::Connect + Send + Sync + Clone + 'static,
+        TBackend: backend::HttpBackend,
     {
+        if self.access_context.read().unwrap().is_none() {
+            self.load_stored(api_context, transport);
+        }
         if self.access_context.read().unwrap().is_none()
             || self
                 .access_context
@@ -35,36 +77,105 @@ impl AccessController {
                 .expired()
         {
             log::debug!("Requesting new access token!");
-            let auth_request = self
-                .profile
-                .get_access_token(api_context)
-                .expect("Failed to create access_token request!");
-            let auth_response = match client.request(auth_request).await {
-                Ok(response) => response,
-                Err(_) => return Err(error::Error::InternalServerError), 
-            };
-            if error::Error::is_error_code(auth_response.status()) {
-                let error = error::Error::to_error(
-                    auth_response.status(),
-                    auth_response.into_body(),
-                )
-                .await;
-                return Err(error);
-            } else {
-                let auth_body = auth_response.into_body();
-                let access_token = extractor::extract_access_token(auth_body)
-                    .await
-                    .expect("Failed to read the body of access token!");
-                let mut context = self.access_context.write().unwrap();
-                *context = Some(context::AccessContext::new(
-                    api_context.base.clone(),
-                    access_token,
-                ));
-            }
+            self.refresh_access_token(api_context, transport).await?;
         }
         self.access_context.read().unwrap().as_ref().map_or_else(
             || Err(error::Error::InternalServerError),
             |access_context| Ok(access_context.access_token.access_token.clone()),
         )
     }
+
+    /// Requests a new access token regardless of the current one.
+    pub async fn refresh_access_token<TBackend>(
+        &self,
+        api_context: &context::ApiContext,
+        transport: &transport::Transport<TBackend>,
+    ) -> Result<(), error::Error>
+    where
+        TBackend: backend::HttpBackend,
+    {
+        #[cfg(feature = "metrics")]
+        let started_at = std::time::Instant::now();
+        let refresh = self.refresh(api_context, transport);
+        #[cfg(feature = "tracing")]
+        let refresh = tracing::Instrument::instrument(
+            refresh,
+            tracing::info_span!(
+                "chatex.token_refresh",
+                endpoint = "profile.get_access_token",
+                method = tracing::field::Empty,
+                path = tracing::field::Empty,
+                status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                retries = 0u32,
+            ),
+        );
+        let result = refresh.await;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = transport.metrics.as_ref() {
+            metrics.record_token_refresh(started_at.elapsed(), result.as_ref().err());
+        }
+        result
+    }
+
+    async fn refresh<TBackend>(
+        &self,
+        api_context: &context::ApiContext,
+        transport: &transport::Transport<TBackend>,
+    ) -> Result<(), error::Error>
+    where
+        TBackend: backend::HttpBackend,
+    {
+        let auth_request = self
+            .profile
+            .get_access_token(api_context)
+            .expect("Failed to create access_token request!");
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("method", tracing::field::display(auth_request.method()));
+            span.record("path", auth_request.uri().path());
+        }
+        let (_header, auth_body) = transport.send(auth_request).await?;
+        let access_token = extractor::extract_access_token(auth_body)
+            .await
+            .expect("Failed to read the body of access token!");
+        let key = token_store::key_for(&api_context.api_key);
+        if let Err(error) = self.token_store.store(&key, &access_token) {
+            log::warn!("Failed to store the access token: {}", error);
+        }
+        let mut context = self.access_context.write().unwrap();
+        *context = Some(context::AccessContext::with_clock(
+            api_context.base.clone(),
+            access_token,
+            self.tolerance,
+            transport.clock.clone(),
+        ));
+        Ok(())
+    }
+
+    fn load_stored<TBackend>(
+        &self,
+        api_context: &context::ApiContext,
+        transport: &transport::Transport<TBackend>,
+    ) {
+        let key = token_store::key_for(&api_context.api_key);
+        let access_token = match self.token_store.load(&key) {
+            Some(access_token) => access_token,
+            None => return,
+        };
+        let access_context = context::AccessContext::with_clock(
+            api_context.base.clone(),
+            access_token,
+            self.tolerance,
+            transport.clock.clone(),
+        );
+        if access_context.not_expired() {
+            log::debug!("Using the stored access token.");
+            let mut context = self.access_context.write().unwrap();
+            if context.is_none() {
+                *context = Some(access_context);
+            }
+        }
+    }
 }
@@ -0,0 +1,293 @@
+use super::{backend, chatex_client, coin, error, models, secret};
+use futures;
+use std::convert::TryFrom;
+
+/// Result of a fan-out call tagged with the name of the account.
+#[derive(Debug)]
+pub struct AccountResult<TResult> {
+    pub account: String,
+    pub result: Result<TResult, error::Error>,
+}
+
+/// Balances of all the accounts summed by coin.
+#[derive(Debug)]
+pub struct CombinedBalance {
+    pub total: Vec<models::typed::TypedCurrency>,
+    pub accounts: Vec<AccountResult<models::Balance>>,
+    /// Fetched balances which couldn't be parsed and aren't part of `total`.
+    pub invalid: Vec<InvalidBalance>,
+}
+
+/// Balance of an account with a malformed currency.
+#[derive(Debug)]
+pub struct InvalidBalance {
+    pub account: String,
+    pub error: models::typed::ModelError,
+}
+
+impl CombinedBalance {
+    /// Accounts whose balance couldn't be fetched and isn't part of `total`.
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &error::Error)> {
+        self.accounts
+            .iter()
+            .filter_map(|account| match &account.result {
+                Ok(_) => None,
+                Err(error) => Some((account.account.as_str(), error)),
+            })
+    }
+}
+
+/// Named Chatex accounts sharing one connection pool.
+///
+/// Every account has its own API key and `AccessController`.
+pub struct ChatexAccounts<TBackend> {
+    backend: std::sync::Arc<TBackend>,
+    base_url: url::Url,
+    accounts: std::collections::BTreeMap<String, chatex_client::ChatexClient<TBackend>>,
+}
+
+impl<TBackend> ChatexAccounts<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new(backend: TBackend, base_url: url::Url) -> ChatexAccounts<TBackend> {
+        ChatexAccounts {
+            backend: std::sync::Arc::new(backend),
+            base_url,
+            accounts: Default::default(),
+        }
+    }
+
+    /// Builder of a client sharing the connection pool of these accounts.
+    ///
+    /// Use it with `insert` when an account needs non-default settings.
+    pub fn builder<TSecret: Into<secret::Secret>>(
+        &self,
+        secret: TSecret,
+    ) -> chatex_client::ChatexClientBuilder<TBackend> {
+        chatex_client::ChatexClientBuilder::with_backend(
+            self.backend.clone(),
+            self.base_url.clone(),
+            secret,
+        )
+    }
+
+    pub fn add_account<TName, TSecret>(&mut self, name: TName, secret: TSecret)
+    where
+        TName: Into<String>,
+        TSecret: Into<secret::Secret>,
+    {
+        let client = self.builder(secret).build();
+        self.insert(name, client);
+    }
+
+    /// Adds the account, replacing the one with the same name.
+    pub fn insert<TName: Into<String>>(
+        &mut self,
+        name: TName,
+        client: chatex_client::ChatexClient<TBackend>,
+    ) -> Option<chatex_client::ChatexClient<TBackend>> {
+        self.accounts.insert(name.into(), client)
+    }
+
+    pub fn remove(
+        &mut self,
+        name: &str,
+    ) -> Option<chatex_client::ChatexClient<TBackend>> {
+        self.accounts.remove(name)
+    }
+
+    pub fn account(
+        &self,
+        name: &str,
+    ) -> Option<&chatex_client::ChatexClient<TBackend>> {
+        self.accounts.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.accounts.keys().map(|name| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Balance summary of every account, ordered by account name.
+    pub async fn get_balance_summaries(&self) -> Vec<AccountResult<models::Balance>> {
+        let requests = self.accounts.iter().map(|(name, client)| async move {
+            AccountResult {
+                account: name.clone(),
+                result: client.profile().get_balance_summary().await,
+            }
+        });
+        futures::future::join_all(requests).await
+    }
+
+    /// Balances of all the accounts summed by coin.
+    ///
+    /// Accounts which failed are reported in `CombinedBalance::accounts`,
+    /// accounts with a malformed balance in `CombinedBalance::invalid`.
+    /// Both are left out of the total.
+    pub async fn get_combined_balance(&self) -> CombinedBalance {
+        let accounts = self.get_balance_summaries().await;
+        let mut total: Vec<models::typed::TypedCurrency> = Vec::new();
+        let mut invalid = Vec::new();
+        for account in accounts.iter() {
+            let balance = match &account.result {
+                Ok(balance) => balance,
+                Err(_) => continue,
+            };
+            let balance: Result<Vec<_>, _> = balance
+                .iter()
+                .cloned()
+                .map(models::typed::TypedCurrency::try_from)
+                .collect();
+            let balance = match balance {
+                Ok(balance) => balance,
+                Err(error) => {
+                    invalid.push(InvalidBalance {
+                        account: account.account.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            for currency in balance {
+                match total.iter_mut().find(|summed| summed.coin == currency.coin) {
+                    Some(summed) => {
+                        summed.amount += currency.amount;
+                        summed.held += currency.held;
+                    }
+                    None => total.push(currency),
+                }
+            }
+        }
+        CombinedBalance {
+            total,
+            accounts,
+            invalid,
+        }
+    }
+
+    /// `ExchangeClient::get_my_orders` of every account, ordered by account name.
+    pub async fn get_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        status: Option<String>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<AccountResult<models::Orders>> {
+        let requests = self.accounts.iter().map(|(name, client)| {
+            let pair = pair.clone();
+            let status = status.clone();
+            async move {
+                AccountResult {
+                    account: name.clone(),
+                    result: client
+                        .exchange()
+                        .get_my_orders(pair, status, offset, limit)
+                        .await,
+                }
+            }
+        });
+        futures::future::join_all(requests).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    fn create_currency(coin: &str, amount: &str, held: &str) -> models::Currency {
+        models::Currency {
+            amount: amount.to_owned(),
+            coin: coin.to_owned(),
+            held: held.to_owned(),
+        }
+    }
+
+    fn mock_account<'a>(
+        server: &'a httpmock::MockServer,
+        name: &str,
+        status: u16,
+        balance: &models::Balance,
+    ) -> (httpmock::MockRef<'a>, httpmock::MockRef<'a>) {
+        let access_token = models::AccessToken {
+            access_token: format!("TOKEN_{}", name).into(),
+            expires_at: chrono::Utc::now().timestamp() + 3600,
+        };
+        let access_token = serde_json::to_string(&access_token).expect(SERDE_ERROR);
+        let access_token_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST)
+                .header("Authorization", &format!("Bearer SECRET_{}", name))
+                .path("/auth/access-token");
+            default_then_content_type(then)
+                .status(200)
+                .body(access_token);
+        });
+        let balance = serde_json::to_string(balance).expect(SERDE_ERROR);
+        let balance_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::GET)
+                .header("Authorization", &format!("Bearer TOKEN_{}", name))
+                .path("/me/balance");
+            default_then_content_type(then).status(status).body(balance);
+        });
+        (access_token_mock, balance_mock)
+    }
+
+    #[test]
+    fn combined_balance() {
+        let server = httpmock::MockServer::start();
+        let first = vec![
+            create_currency("btc", "1.5", "0.5"),
+            create_currency("eth", "2", "0"),
+        ];
+        let second = vec![create_currency("btc", "0.25", "0.25")];
+        let fourth = vec![
+            create_currency("btc", "1", "0"),
+            create_currency("eth", "1,5", "0"),
+        ];
+        let mocks = [
+            mock_account(&server, "first", 200, &first),
+            mock_account(&server, "second", 200, &second),
+            mock_account(&server, "third", 403, &Vec::new()),
+            mock_account(&server, "fourth", 200, &fourth),
+        ];
+        let mut accounts = ChatexAccounts::new(
+            crate::backend::HyperBackend::new(hyper::client::HttpConnector::new()),
+            url::Url::parse(&server.base_url()).unwrap(),
+        );
+        accounts.add_account("second", "SECRET_second".to_owned());
+        accounts.add_account("first", "SECRET_first".to_owned());
+        accounts.add_account("third", "SECRET_third".to_owned());
+        accounts.add_account("fourth", "SECRET_fourth".to_owned());
+        let combined = tokio_test::block_on(accounts.get_combined_balance());
+        let names: Vec<&str> = combined
+            .accounts
+            .iter()
+            .map(|account| account.account.as_str())
+            .collect();
+        assert_eq!(names, vec!["first", "fourth", "second", "third"]);
+        let failed: Vec<&str> = combined.failed().map(|(name, _)| name).collect();
+        assert_eq!(failed, vec!["third"]);
+        assert_eq!(combined.invalid.len(), 1);
+        assert_eq!(combined.invalid[0].account, "fourth");
+        assert_eq!(combined.invalid[0].error.field, "amount");
+        assert_eq!(combined.invalid[0].error.value, "1,5");
+        assert_eq!(combined.total.len(), 2);
+        let btc = &combined.total[0];
+        assert_eq!(btc.coin, crate::coin::Coin::BTC);
+        assert_eq!(btc.amount, rust_decimal::Decimal::new(175, 2));
+        assert_eq!(btc.held, rust_decimal::Decimal::new(75, 2));
+        assert_eq!(combined.total[1].amount, rust_decimal::Decimal::new(2, 0));
+        for (access_token_mock, balance_mock) in mocks.iter() {
+            access_token_mock.assert();
+            balance_mock.assert();
+        }
+    }
+}
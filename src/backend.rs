@@ -0,0 +1,213 @@
+//! HTTP stack the SDK sends its requests through.
+//!
+//! Requests and responses are plain `http` types with fully buffered
+//! bodies, so a backend can be built on any client and any runtime. The
+//! hyper backend is enabled by the default `hyper` feature, the reqwest
+//! one by the `reqwest` feature.
+
+/// Body of requests and responses.
+pub type Body = bytes::Bytes;
+
+/// Transport failure of a backend, e.g. a connection or TLS error.
+///
+/// Responses with error status codes are not backend errors.
+#[derive(Debug)]
+pub struct BackendError {
+    source: Box<dyn std::error::Error + Send + Sync>,
+    retryable: bool,
+}
+
+impl BackendError {
+    pub fn new<TError>(source: TError) -> BackendError
+    where
+        TError: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        BackendError {
+            source: source.into(),
+            retryable: true,
+        }
+    }
+
+    /// Error which sending the request again won't fix.
+    pub fn permanent<TError>(source: TError) -> BackendError
+    where
+        TError: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        BackendError {
+            retryable: false,
+            ..BackendError::new(source)
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(formatter)
+    }
+}
+
+impl std::error::Error for BackendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+pub type BackendFuture<'a> =
+    futures::future::BoxFuture<'a, Result<http::Response<Body>, BackendError>>;
+
+/// Sends a single HTTP request.
+///
+/// Implementations must not retry, follow redirects or inspect the status
+/// code; the SDK does all of that on top of the backend.
+pub trait HttpBackend: Send + Sync + 'static {
+    fn send(&self, request: http::Request<Body>) -> BackendFuture<'_>;
+}
+
+impl<TBackend> HttpBackend for std::sync::Arc<TBackend>
+where
+    TBackend: HttpBackend + ?Sized,
+{
+    fn send(&self, request: http::Request<Body>) -> BackendFuture<'_> {
+        (**self).send(request)
+    }
+}
+
+impl HttpBackend for Box<dyn HttpBackend> {
+    fn send(&self, request: http::Request<Body>) -> BackendFuture<'_> {
+        (**self).send(request)
+    }
+}
+
+/// Backend on top of `hyper::Client`.
+#[cfg(feature = "hyper")]
+#[derive(Clone, Debug)]
+pub struct HyperBackend<TConnector> {
+    client: hyper::Client<TConnector>,
+}
+
+#[cfg(feature = "hyper")]
+impl<TConnector> HyperBackend<TConnector>
+where
+    TConnector: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+{
+    pub fn new(connector: TConnector) -> HyperBackend<TConnector> {
+        HyperBackend::with_client(hyper::Client::builder().build(connector))
+    }
+
+    pub fn with_client(client: hyper::Client<TConnector>) -> HyperBackend<TConnector> {
+        HyperBackend { client }
+    }
+}
+
+#[cfg(feature = "hyper")]
+impl<TConnector> HttpBackend for HyperBackend<TConnector>
+where
+    TConnector: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+{
+    fn send(&self, request: http::Request<Body>) -> BackendFuture<'_> {
+        let request = request.map(hyper::Body::from);
+        Box::pin(async move {
+            let response = self
+                .client
+                .request(request)
+                .await
+                .map_err(BackendError::new)?;
+            let (parts, body) = response.into_parts();
+            let body = hyper::body::to_bytes(body)
+                .await
+                .map_err(BackendError::new)?;
+            Ok(http::Response::from_parts(parts, body))
+        })
+    }
+}
+
+/// Backend on top of an existing `reqwest::Client`.
+#[cfg(feature = "reqwest")]
+#[derive(Clone, Debug)]
+pub struct ReqwestBackend {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestBackend {
+    pub fn new(client: reqwest::Client) -> ReqwestBackend {
+        ReqwestBackend { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpBackend for ReqwestBackend {
+    fn send(&self, request: http::Request<Body>) -> BackendFuture<'_> {
+        Box::pin(async move {
+            let request =
+                <reqwest::Request as std::convert::TryFrom<_>>::try_from(request)
+                    .map_err(BackendError::new)?;
+            let response = self
+                .client
+                .execute(request)
+                .await
+                .map_err(BackendError::new)?;
+            let mut builder = http::Response::builder()
+                .status(response.status())
+                .version(response.version());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = response.headers().clone();
+            }
+            let body = response.bytes().await.map_err(BackendError::new)?;
+            builder.body(body).map_err(BackendError::new)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    /// Serves canned JSON bodies by path, without any network.
+    struct Fake;
+
+    impl HttpBackend for Fake {
+        fn send(&self, request: http::Request<Body>) -> BackendFuture<'_> {
+            let body = match request.uri().path() {
+                "/auth/access-token" => serde_json::to_vec(&crate::models::AccessToken {
+                    access_token: "TOKEN".into(),
+                    expires_at: chrono::Utc::now().timestamp() + 3600,
+                }),
+                "/me/balance" => serde_json::to_vec(&vec![crate::models::Currency {
+                    amount: "2".to_owned(),
+                    coin: "eth".to_owned(),
+                    held: "1".to_owned(),
+                }]),
+                _ => {
+                    return Box::pin(futures::future::ready(Err(BackendError::new(
+                        "unknown",
+                    ))))
+                }
+            };
+            let response = http::Response::new(Body::from(body.expect(SERDE_ERROR)));
+            Box::pin(futures::future::ready(Ok(response)))
+        }
+    }
+
+    #[test]
+    fn custom_backend() {
+        let client = crate::ChatexClient::new(
+            Fake,
+            url::Url::parse("http://fake/").unwrap(),
+            SECRET.to_owned(),
+        );
+        let balance =
+            tokio_test::block_on(client.profile().get_balance_summary()).unwrap();
+        assert_eq!(balance[0].coin, "eth");
+        let result = tokio_test::block_on(client.profile().get_account_information());
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::InternalServerError)
+        ));
+    }
+}
@@ -0,0 +1,358 @@
+//! Stream of balance changes made by polling the balance summary.
+//!
+//! Requests go through the client transport like every other call, so the
+//! watcher is subject to the same `RateLimiter` and retry policy.
+use super::backend;
+use super::coin;
+use super::error;
+use super::models;
+use super::profile_client;
+use rust_decimal::Decimal;
+use std::convert::TryFrom;
+
+/// Balance of a single coin.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Holdings {
+    pub amount: Decimal,
+    pub held: Decimal,
+}
+
+impl std::ops::Sub for Holdings {
+    type Output = Holdings;
+
+    fn sub(self, other: Holdings) -> Holdings {
+        Holdings {
+            amount: self.amount - other.amount,
+            held: self.held - other.held,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BalanceChanged {
+    pub coin: coin::Coin,
+    /// Balance at the previous event of the coin, zero for a new coin.
+    pub old: Holdings,
+    /// Current balance, zero for a coin which disappeared from the summary.
+    pub new: Holdings,
+    pub delta: Holdings,
+}
+
+/// Smallest changes worth an event.
+///
+/// Smaller changes are not lost, they add up until they cross the threshold.
+#[derive(Clone, Debug, Default)]
+pub struct Thresholds {
+    pub default: Decimal,
+    pub coins: std::collections::HashMap<coin::Coin, Decimal>,
+}
+
+impl Thresholds {
+    pub fn get(&self, coin: &coin::Coin) -> Decimal {
+        self.coins.get(coin).copied().unwrap_or(self.default)
+    }
+
+    fn is_significant(&self, coin: &coin::Coin, delta: &Holdings) -> bool {
+        let threshold = self.get(coin);
+        let changed = |value: Decimal| !value.is_zero() && value.abs() >= threshold;
+        changed(delta.amount) || changed(delta.held)
+    }
+}
+
+pub type Balances = std::collections::HashMap<coin::Coin, Holdings>;
+
+/// Parses the balance summary like `models::typed::TypedCurrency`.
+/// Currencies with invalid amounts are skipped.
+pub fn to_balances(balance: &models::Balance) -> Balances {
+    parse_balances(balance).0
+}
+
+/// Valid balances and the coins whose amounts are invalid.
+fn parse_balances(balance: &models::Balance) -> (Balances, Vec<coin::Coin>) {
+    let mut balances = Balances::new();
+    let mut invalid = Vec::new();
+    for currency in balance.iter() {
+        match models::typed::TypedCurrency::try_from(currency.clone()) {
+            Ok(currency) => {
+                balances.insert(
+                    currency.coin,
+                    Holdings {
+                        amount: currency.amount,
+                        held: currency.held,
+                    },
+                );
+            }
+            Err(error) => {
+                log::warn!("Invalid balance of {}: {}", currency.coin, error);
+                invalid.push(coin::Coin::from(currency.coin.as_str()));
+            }
+        }
+    }
+    (balances, invalid)
+}
+
+/// `diff` with the balance summary. Coins with invalid amounts keep their
+/// baseline, so they are neither reported as gone nor as new later.
+pub fn diff_summary(
+    baseline: &mut Balances,
+    summary: &models::Balance,
+    thresholds: &Thresholds,
+) -> Vec<BalanceChanged> {
+    let (mut current, invalid) = parse_balances(summary);
+    for coin in invalid.into_iter() {
+        if let Some(holdings) = baseline.get(&coin) {
+            current.insert(coin, *holdings);
+        }
+    }
+    diff(baseline, &current, thresholds)
+}
+
+/// Events for every coin whose balance moved past its threshold since the
+/// baseline. The baseline of those coins is moved to the current balance.
+pub fn diff(
+    baseline: &mut Balances,
+    current: &Balances,
+    thresholds: &Thresholds,
+) -> Vec<BalanceChanged> {
+    let mut coins: Vec<&coin::Coin> = baseline.keys().chain(current.keys()).collect();
+    coins.sort_by(|left, right| left.get_name().cmp(right.get_name()));
+    coins.dedup();
+    let changes: Vec<BalanceChanged> = coins
+        .into_iter()
+        .filter_map(|coin| {
+            let old = baseline.get(coin).copied().unwrap_or_default();
+            let new = current.get(coin).copied().unwrap_or_default();
+            let delta = new - old;
+            if thresholds.is_significant(coin, &delta) {
+                Some(BalanceChanged {
+                    coin: coin.clone(),
+                    old,
+                    new,
+                    delta,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+    for change in changes.iter() {
+        if current.contains_key(&change.coin) {
+            baseline.insert(change.coin.clone(), change.new);
+        } else {
+            baseline.remove(&change.coin);
+        }
+    }
+    changes
+}
+
+pub struct BalanceWatcher<TBackend> {
+    profile: profile_client::ProfileClient<TBackend>,
+    interval: std::time::Duration,
+    thresholds: Thresholds,
+    baseline: Option<Balances>,
+}
+
+struct State<TBackend> {
+    watcher: BalanceWatcher<TBackend>,
+    interval: Option<tokio::time::Interval>,
+    pending: std::collections::VecDeque<BalanceChanged>,
+}
+
+impl<TBackend> BalanceWatcher<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new(
+        profile: profile_client::ProfileClient<TBackend>,
+        interval: std::time::Duration,
+    ) -> BalanceWatcher<TBackend> {
+        BalanceWatcher {
+            profile,
+            interval,
+            thresholds: Default::default(),
+            baseline: None,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: Decimal) -> Self {
+        self.thresholds.default = threshold;
+        self
+    }
+
+    pub fn coin_threshold(mut self, coin: coin::Coin, threshold: Decimal) -> Self {
+        self.thresholds.coins.insert(coin, threshold);
+        self
+    }
+
+    /// Balance the first poll is compared with. Without it the first poll
+    /// only sets the baseline.
+    pub fn baseline(mut self, baseline: Balances) -> Self {
+        self.baseline = Some(baseline);
+        self
+    }
+
+    /// Polls the balance every `interval`, starting immediately.
+    ///
+    /// Failed polls are yielded as errors and polling goes on. Must be
+    /// consumed within a Tokio runtime.
+    pub fn watch(
+        self,
+    ) -> impl futures::Stream<Item = Result<BalanceChanged, error::Error>> {
+        let state = State {
+            watcher: self,
+            interval: None,
+            pending: Default::default(),
+        };
+        futures::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(change) = state.pending.pop_front() {
+                    return Some((Ok(change), state));
+                }
+                let period = state.watcher.interval;
+                let interval = state.interval.get_or_insert_with(|| {
+                    let mut interval = tokio::time::interval(period);
+                    interval
+                        .set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                    interval
+                });
+                interval.tick().await;
+                let balance = match state.watcher.profile.get_balance_summary().await {
+                    Ok(balance) => balance,
+                    Err(error) => return Some((Err(error), state)),
+                };
+                let watcher = &mut state.watcher;
+                match watcher.baseline.as_mut() {
+                    Some(baseline) => {
+                        state.pending =
+                            diff_summary(baseline, &balance, &watcher.thresholds).into();
+                    }
+                    None => watcher.baseline = Some(to_balances(&balance)),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    fn holdings(amount: i64, held: i64) -> Holdings {
+        Holdings {
+            amount: Decimal::new(amount, 2),
+            held: Decimal::new(held, 2),
+        }
+    }
+
+    #[test]
+    fn dust_adds_up() {
+        let thresholds = Thresholds {
+            default: Decimal::new(10, 2),
+            coins: vec![(coin::Coin::USDT, Decimal::from(1))]
+                .into_iter()
+                .collect(),
+        };
+        let mut baseline: Balances = vec![(coin::Coin::BTC, holdings(100, 0))]
+            .into_iter()
+            .collect();
+        let current: Balances = vec![
+            (coin::Coin::BTC, holdings(105, 0)),
+            (coin::Coin::USDT, holdings(50, 0)),
+        ]
+        .into_iter()
+        .collect();
+        assert!(diff(&mut baseline, &current, &thresholds).is_empty());
+        let current: Balances = vec![
+            (coin::Coin::BTC, holdings(112, 3)),
+            (coin::Coin::USDT, holdings(150, 0)),
+        ]
+        .into_iter()
+        .collect();
+        let changes = diff(&mut baseline, &current, &thresholds);
+        assert_eq!(
+            changes,
+            vec![
+                BalanceChanged {
+                    coin: coin::Coin::BTC,
+                    old: holdings(100, 0),
+                    new: holdings(112, 3),
+                    delta: holdings(12, 3),
+                },
+                BalanceChanged {
+                    coin: coin::Coin::USDT,
+                    old: holdings(0, 0),
+                    new: holdings(150, 0),
+                    delta: holdings(150, 0),
+                },
+            ]
+        );
+        assert!(diff(&mut baseline, &current, &thresholds).is_empty());
+    }
+
+    #[test]
+    fn invalid_amount_keeps_baseline() {
+        let currency = |coin: &str, amount: &str| models::Currency {
+            amount: amount.to_owned(),
+            coin: coin.to_owned(),
+            held: "0".to_owned(),
+        };
+        let thresholds = Thresholds::default();
+        let mut baseline: Balances = vec![
+            (coin::Coin::BTC, holdings(100, 0)),
+            (coin::Coin::ETH, holdings(200, 0)),
+        ]
+        .into_iter()
+        .collect();
+        let summary = vec![currency("btc", "1.5E0"), currency("eth", "2,0")];
+        let changes = diff_summary(&mut baseline, &summary, &thresholds);
+        assert_eq!(
+            changes,
+            vec![BalanceChanged {
+                coin: coin::Coin::BTC,
+                old: holdings(100, 0),
+                new: holdings(150, 0),
+                delta: holdings(50, 0),
+            }]
+        );
+        assert_eq!(baseline.get(&coin::Coin::ETH), Some(&holdings(200, 0)));
+        let summary = vec![currency("btc", "1.5"), currency("eth", "2")];
+        assert!(diff_summary(&mut baseline, &summary, &thresholds).is_empty());
+    }
+
+    #[test]
+    fn watch() {
+        use futures::StreamExt;
+
+        let case = TestCase::new();
+        let access_token_mock = case.mock_access_token();
+        let balance = vec![models::Currency {
+            amount: "1.5".to_owned(),
+            coin: "btc".to_owned(),
+            held: "0.5".to_owned(),
+        }];
+        let balance = serde_json::to_string(&balance).expect(SERDE_ERROR);
+        let balance_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/me/balance");
+            default_then_content_type(then)
+                .status(200)
+                .body(balance.clone());
+        });
+        let profile = profile_client::ProfileClient::new(
+            case.client_base.clone(),
+            std::sync::Arc::new(crate::endpoint::Profile::new(&case.base_context)),
+        );
+        let baseline = vec![(coin::Coin::BTC, holdings(100, 0))]
+            .into_iter()
+            .collect();
+        let changes = BalanceWatcher::new(profile, std::time::Duration::from_secs(60))
+            .baseline(baseline)
+            .watch();
+        futures::pin_mut!(changes);
+        let change = tokio_test::block_on(changes.next()).unwrap().unwrap();
+        assert_eq!(change.coin, coin::Coin::BTC);
+        assert_eq!(change.delta, holdings(50, 50));
+        access_token_mock.assert();
+        balance_mock.assert();
+    }
+}
@@ -0,0 +1,404 @@
+//! Synchronous facade over `ChatexClient` for code without an async runtime.
+//!
+//! Every call is executed on a Tokio runtime owned by the client. The
+//! client and its sub-clients can be shared between threads, but must not
+//! be used from inside another async runtime.
+use super::{backend, bulk, chatex_client, coin, error, models, secret};
+use chrono;
+use iso_currency;
+use isocountry;
+use isolanguage_1;
+
+type Runtime = std::sync::Arc<tokio::runtime::Runtime>;
+
+pub struct ChatexClient<TBackend> {
+    inner: chatex_client::ChatexClient<TBackend>,
+    runtime: Runtime,
+}
+
+impl<TBackend> ChatexClient<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new<TSecret: Into<secret::Secret>>(
+        backend: TBackend,
+        base_url: url::Url,
+        secret: TSecret,
+    ) -> Result<ChatexClient<TBackend>, std::io::Error> {
+        ChatexClient::from_builder(chatex_client::ChatexClient::builder(
+            backend, base_url, secret,
+        ))
+    }
+
+    /// Builds the async client inside the owned runtime, so options which
+    /// spawn tasks, like `background_refresh`, work as well.
+    pub fn from_builder(
+        builder: chatex_client::ChatexClientBuilder<TBackend>,
+    ) -> Result<ChatexClient<TBackend>, std::io::Error> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("chatex-blocking")
+            .enable_all()
+            .build()?;
+        let inner = {
+            let _guard = runtime.enter();
+            builder.build()
+        };
+        Ok(ChatexClient {
+            inner,
+            runtime: std::sync::Arc::new(runtime),
+        })
+    }
+
+    pub fn profile(&self) -> ProfileClient<TBackend> {
+        ProfileClient {
+            inner: self.inner.profile(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    pub fn coin(&self) -> CoinClient<TBackend> {
+        CoinClient {
+            inner: self.inner.coin(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    pub fn exchange(&self) -> ExchangeClient<TBackend> {
+        ExchangeClient {
+            inner: self.inner.exchange(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    pub fn invoice(&self) -> InvoiceClient<TBackend> {
+        InvoiceClient {
+            inner: self.inner.invoice(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    pub fn payment_system(&self) -> PaymentSystemClient<TBackend> {
+        PaymentSystemClient {
+            inner: self.inner.payment_system(),
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+pub struct ProfileClient<TBackend> {
+    inner: super::ProfileClient<TBackend>,
+    runtime: Runtime,
+}
+
+impl<TBackend> ProfileClient<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn create_access_token(&self) -> Result<models::AccessToken, error::Error> {
+        self.runtime.block_on(self.inner.create_access_token())
+    }
+
+    pub fn get_account_information(&self) -> Result<models::BasicInfo, error::Error> {
+        self.runtime.block_on(self.inner.get_account_information())
+    }
+
+    pub fn get_balance_summary(&self) -> Result<models::Balance, error::Error> {
+        self.runtime.block_on(self.inner.get_balance_summary())
+    }
+}
+
+pub struct CoinClient<TBackend> {
+    inner: super::CoinClient<TBackend>,
+    runtime: Runtime,
+}
+
+impl<TBackend> CoinClient<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn get_available_coins(&self) -> Result<models::Coins, error::Error> {
+        self.runtime.block_on(self.inner.get_available_coins())
+    }
+
+    pub fn get_coin(&self, coin: coin::Coin) -> Result<models::Coin, error::Error> {
+        self.runtime.block_on(self.inner.get_coin(coin))
+    }
+}
+
+pub struct ExchangeClient<TBackend> {
+    inner: super::ExchangeClient<TBackend>,
+    runtime: Runtime,
+}
+
+impl<TBackend> ExchangeClient<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn get_all_orders(
+        &self,
+        pair: coin::CoinPair,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<models::Orders, error::Error> {
+        self.runtime
+            .block_on(self.inner.get_all_orders(pair, offset, limit))
+    }
+
+    pub fn create_order_raw(
+        &self,
+        pair: coin::CoinPair,
+        amount: &str,
+        rate: &str,
+    ) -> Result<models::Order, error::Error> {
+        self.runtime
+            .block_on(self.inner.create_order_raw(pair, amount, rate))
+    }
+
+    pub fn create_order(
+        &self,
+        pair: coin::CoinPair,
+        amount: f64,
+        rate: f64,
+    ) -> Result<models::Order, error::Error> {
+        self.runtime
+            .block_on(self.inner.create_order(pair, amount, rate))
+    }
+
+    pub fn get_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        status: Option<String>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<models::Orders, error::Error> {
+        self.runtime
+            .block_on(self.inner.get_my_orders(pair, status, offset, limit))
+    }
+
+    pub fn get_trades(
+        &self,
+        order_id: Option<u32>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<models::Trades, error::Error> {
+        self.runtime
+            .block_on(self.inner.get_trades(order_id, offset, limit))
+    }
+
+    pub fn get_trade_by_id(&self, id: &str) -> Result<models::Trade, error::Error> {
+        self.runtime.block_on(self.inner.get_trade_by_id(id))
+    }
+
+    pub fn get_order_by_id(&self, id: &str) -> Result<models::Order, error::Error> {
+        self.runtime.block_on(self.inner.get_order_by_id(id))
+    }
+
+    pub fn update_order_by_id(
+        &self,
+        id: &str,
+        order: &models::UpdateOrder,
+    ) -> Result<models::Order, error::Error> {
+        self.runtime
+            .block_on(self.inner.update_order_by_id(id, order))
+    }
+
+    pub fn delete_order_by_id(&self, id: &str) -> Result<models::Order, error::Error> {
+        self.runtime.block_on(self.inner.delete_order_by_id(id))
+    }
+
+    pub fn activate_order_by_id(&self, id: &str) -> Result<models::Order, error::Error> {
+        self.runtime.block_on(self.inner.activate_order_by_id(id))
+    }
+
+    pub fn deactivate_order_by_id(
+        &self,
+        id: &str,
+    ) -> Result<models::Order, error::Error> {
+        self.runtime.block_on(self.inner.deactivate_order_by_id(id))
+    }
+
+    pub fn create_trade_for_order(
+        &self,
+        id: &str,
+        trade: &models::CreateTradeRequest,
+    ) -> Result<models::Trade, error::Error> {
+        self.runtime
+            .block_on(self.inner.create_trade_for_order(id, trade))
+    }
+
+    pub fn get_order_book(
+        &self,
+        pair: coin::CoinPair,
+    ) -> Result<models::Orders, error::Error> {
+        self.runtime.block_on(self.inner.get_order_book(pair))
+    }
+
+    pub fn get_all_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+    ) -> Result<models::Orders, error::Error> {
+        self.runtime.block_on(self.inner.get_all_my_orders(pair))
+    }
+
+    pub fn cancel_all_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        concurrency: usize,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error> {
+        self.runtime
+            .block_on(self.inner.cancel_all_orders(pair, concurrency))
+    }
+
+    pub fn deactivate_all_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        concurrency: usize,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error> {
+        self.runtime
+            .block_on(self.inner.deactivate_all_orders(pair, concurrency))
+    }
+
+    pub fn activate_all_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        concurrency: usize,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error> {
+        self.runtime
+            .block_on(self.inner.activate_all_orders(pair, concurrency))
+    }
+
+    pub fn create_orders(
+        &self,
+        orders: Vec<models::OrderRequest>,
+        concurrency: usize,
+    ) -> bulk::BulkReport<models::OrderRequest, models::Order> {
+        self.runtime
+            .block_on(self.inner.create_orders(orders, concurrency))
+    }
+}
+
+pub struct InvoiceClient<TBackend> {
+    inner: super::InvoiceClient<TBackend>,
+    runtime: Runtime,
+}
+
+impl<TBackend> InvoiceClient<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_invoices(
+        &self,
+        coins: Option<&[coin::Coin]>,
+        fiat: Option<&[iso_currency::Currency]>,
+        country_code: Option<&[isocountry::CountryCode]>,
+        payment_system_id: Option<&[models::PaymentSystemId]>,
+        lang_id: Option<&[isolanguage_1::LanguageCode]>,
+        status: Option<&[models::InvoiceStatus]>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+        date_start: Option<chrono::DateTime<chrono::Utc>>,
+        date_end: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<models::Invoices, error::Error> {
+        self.runtime.block_on(self.inner.get_invoices(
+            coins,
+            fiat,
+            country_code,
+            payment_system_id,
+            lang_id,
+            status,
+            offset,
+            limit,
+            date_start,
+            date_end,
+        ))
+    }
+
+    pub fn create_invoice(
+        &self,
+        create_invoice: models::CreateInvoice,
+    ) -> Result<models::Invoices, error::Error> {
+        self.runtime
+            .block_on(self.inner.create_invoice(create_invoice))
+    }
+
+    pub fn get_invoice_by_id(&self, id: &str) -> Result<models::Invoice, error::Error> {
+        self.runtime.block_on(self.inner.get_invoice_by_id(id))
+    }
+}
+
+pub struct PaymentSystemClient<TBackend> {
+    inner: super::PaymentSystemClient<TBackend>,
+    runtime: Runtime,
+}
+
+impl<TBackend> PaymentSystemClient<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn get_list_of_estimated_payment_systems(
+        &self,
+        estimate: models::Estimate,
+    ) -> Result<models::FiatEstimations, error::Error> {
+        self.runtime
+            .block_on(self.inner.get_list_of_estimated_payment_systems(estimate))
+    }
+
+    pub fn get_payment_system_by_id(
+        &self,
+        id: models::PaymentSystemId,
+    ) -> Result<models::PaymentSystem, error::Error> {
+        self.runtime
+            .block_on(self.inner.get_payment_system_by_id(id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn balance_from_many_threads() {
+        let server = httpmock::MockServer::start();
+        let access_token = models::AccessToken {
+            access_token: "TOKEN".into(),
+            expires_at: chrono::Utc::now().timestamp() + 3600,
+        };
+        let access_token = serde_json::to_string(&access_token).expect(SERDE_ERROR);
+        let access_token_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST)
+                .path("/auth/access-token");
+            default_then_content_type(then)
+                .status(200)
+                .body(access_token);
+        });
+        let balance: models::Balance = vec![Default::default()];
+        let balance = serde_json::to_string(&balance).expect(SERDE_ERROR);
+        let balance_mock = server.mock(|when, then| {
+            default_get_when(when).path("/me/balance");
+            default_then_content_type(then).status(200).body(balance);
+        });
+        let client = ChatexClient::new(
+            crate::backend::HyperBackend::new(hyper::client::HttpConnector::new()),
+            url::Url::parse(&server.base_url()).unwrap(),
+            SECRET.to_owned(),
+        )
+        .unwrap();
+        client.profile().create_access_token().unwrap();
+        let client = std::sync::Arc::new(client);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let client = client.clone();
+                std::thread::spawn(move || client.profile().get_balance_summary())
+            })
+            .collect();
+        for thread in threads {
+            let balance = thread.join().unwrap().unwrap();
+            assert_eq!(balance.len(), 1);
+        }
+        balance_mock.assert_hits(4);
+        assert!(access_token_mock.hits() >= 2);
+    }
+}
@@ -0,0 +1,116 @@
+use super::error;
+
+/// Number of requests a bulk operation keeps in flight by default.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Outcome of a single item of a bulk operation.
+#[derive(Debug)]
+pub struct BulkEntry<TKey, TValue> {
+    pub key: TKey,
+    pub result: Result<TValue, error::Error>,
+}
+
+/// Per-item results of a bulk operation, in the order the items were given.
+#[derive(Debug)]
+pub struct BulkReport<TKey, TValue> {
+    pub entries: Vec<BulkEntry<TKey, TValue>>,
+}
+
+impl<TKey, TValue> BulkReport<TKey, TValue> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = (&TKey, &TValue)> {
+        self.entries.iter().filter_map(|entry| match &entry.result {
+            Ok(value) => Some((&entry.key, value)),
+            Err(_) => None,
+        })
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (&TKey, &error::Error)> {
+        self.entries.iter().filter_map(|entry| match &entry.result {
+            Ok(_) => None,
+            Err(error) => Some((&entry.key, error)),
+        })
+    }
+
+    /// True when every item succeeded.
+    pub fn is_success(&self) -> bool {
+        self.entries.iter().all(|entry| entry.result.is_ok())
+    }
+}
+
+/// Runs `operation` for every key with at most `concurrency` of them in flight.
+///
+/// Rate limits are respected by the transport, so a bulk operation never sends
+/// faster than the configured `RateLimiter` allows.
+pub async fn run<TKey, TValue, F, TFuture>(
+    keys: Vec<TKey>,
+    concurrency: usize,
+    operation: F,
+) -> BulkReport<TKey, TValue>
+where
+    F: Fn(&TKey) -> TFuture,
+    TFuture: futures::Future<Output = Result<TValue, error::Error>>,
+{
+    use futures::StreamExt;
+
+    let operation = &operation;
+    let mut entries: Vec<(usize, BulkEntry<TKey, TValue>)> =
+        futures::stream::iter(keys.into_iter().enumerate())
+            .map(|(index, key)| async move {
+                let result = operation(&key).await;
+                (index, BulkEntry { key, result })
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+    entries.sort_by_key(|(index, _)| *index);
+    BulkReport {
+        entries: entries.into_iter().map(|(_, entry)| entry).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_order_and_bounds_concurrency() {
+        let in_flight = std::sync::atomic::AtomicUsize::new(0);
+        let max_in_flight = std::sync::atomic::AtomicUsize::new(0);
+        let report = tokio_test::block_on(run(
+            (0..10u64).collect(),
+            3,
+            |key: &u64| {
+                let key = *key;
+                let in_flight = &in_flight;
+                let max_in_flight = &max_in_flight;
+                async move {
+                    use std::sync::atomic::Ordering;
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(10 - key)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    if [0, 4, 8].contains(&key) {
+                        Err(error::Error::NotFoundError)
+                    } else {
+                        Ok(key * 2)
+                    }
+                }
+            },
+        ));
+        assert_eq!(max_in_flight.into_inner(), 3);
+        let keys: Vec<u64> = report.entries.iter().map(|entry| entry.key).collect();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+        let failed: Vec<u64> = report.failed().map(|(key, _)| *key).collect();
+        assert_eq!(failed, vec![0, 4, 8]);
+        assert_eq!(report.succeeded().count(), 7);
+        assert!(!report.is_success());
+    }
+}
@@ -0,0 +1,36 @@
+use super::coin;
+
+/// Describes a single API call for instrumentation.
+#[derive(Clone, Debug)]
+pub struct Call {
+    pub endpoint: &'static str,
+    pub pair: Option<String>,
+    pub order_id: Option<String>,
+}
+
+impl Call {
+    pub fn new(endpoint: &'static str) -> Call {
+        Call {
+            endpoint,
+            pair: None,
+            order_id: None,
+        }
+    }
+
+    pub fn pair(mut self, pair: &coin::CoinPair) -> Call {
+        self.pair = Some(String::from(pair));
+        self
+    }
+
+    pub fn maybe_pair(self, pair: Option<&coin::CoinPair>) -> Call {
+        match pair {
+            Some(pair) => self.pair(pair),
+            None => self,
+        }
+    }
+
+    pub fn order_id<TId: std::fmt::Display>(mut self, order_id: TId) -> Call {
+        self.order_id = Some(order_id.to_string());
+        self
+    }
+}
@@ -0,0 +1,387 @@
+//! Record-and-replay of HTTP interactions for deterministic tests.
+//!
+//! `CassetteBackend` wraps any other backend. In record mode every request
+//! is forwarded to the real server and the request/response pair is
+//! appended to a JSON cassette. In replay mode responses are served from
+//! the cassette and nothing leaves the process:
+//!
+//! ```no_run
+//! # use chatex_sdk_rust::cassette;
+//! let backend = cassette::CassetteBackend::replay("tests/balance.json").unwrap();
+//! let client = chatex_sdk_rust::ChatexClient::new(
+//!     backend,
+//!     "https://api.chatex.com/v1/".parse().unwrap(),
+//!     "KEY".to_owned(),
+//! );
+//! ```
+//!
+//! Authorization headers are never stored and `access_token` fields of
+//! JSON bodies are replaced with `[REDACTED]`.
+use super::backend;
+use super::secret;
+
+const ACCESS_TOKEN_FIELD: &str = "access_token";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub body: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Cassette {
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Cassette> {
+        let content = std::fs::read(path)?;
+        serde_json::from_slice(&content)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let content = serde_json::to_vec_pretty(self).map_err(|error| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+        })?;
+        std::fs::write(path, content)
+    }
+}
+
+impl RecordedRequest {
+    fn new(request: &http::Request<backend::Body>) -> RecordedRequest {
+        RecordedRequest {
+            method: request.method().to_string(),
+            path: request.uri().path().to_owned(),
+            query: request.uri().query().map(str::to_owned),
+            body: redact_body(request.body()),
+        }
+    }
+}
+
+impl RecordedResponse {
+    fn new(response: &http::Response<backend::Body>) -> RecordedResponse {
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, value)| {
+                !value.is_sensitive()
+                    && *name != http::header::SET_COOKIE
+                    && *name != http::header::CONTENT_LENGTH
+                    && *name != http::header::TRANSFER_ENCODING
+            })
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_owned()))
+            })
+            .collect();
+        RecordedResponse {
+            status: response.status().as_u16(),
+            headers,
+            body: redact_body(response.body()),
+        }
+    }
+
+    fn to_response(
+        &self,
+    ) -> Result<http::Response<backend::Body>, backend::BackendError> {
+        let mut response = http::Response::builder().status(self.status);
+        for (name, value) in self.headers.iter() {
+            response = response.header(name.as_str(), value.as_str());
+        }
+        response
+            .body(backend::Body::from(self.body.clone()))
+            .map_err(|error| {
+                backend::BackendError::permanent(format!(
+                    "invalid recorded response: {}",
+                    error
+                ))
+            })
+    }
+}
+
+/// Bodies are stored as text. JSON bodies are normalized, so formatting
+/// differences do not break matching, and tokens are redacted.
+fn redact_body(body: &[u8]) -> String {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+fn redact_value(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key == ACCESS_TOKEN_FIELD {
+                    *value =
+                        serde_json::Value::String(secret::Secret::REDACTED.to_owned());
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+enum Mode {
+    Record {
+        backend: Box<dyn backend::HttpBackend>,
+        path: std::path::PathBuf,
+    },
+    Replay,
+}
+
+/// Backend which records interactions to or replays them from a cassette.
+///
+/// Wraps any other backend, so it works with every HTTP stack.
+pub struct CassetteBackend {
+    mode: Mode,
+    cassette: std::sync::Mutex<Cassette>,
+    /// Replay only. Interactions served so far.
+    used: std::sync::Mutex<Vec<bool>>,
+    unmatched: std::sync::Mutex<Vec<RecordedRequest>>,
+}
+
+impl CassetteBackend {
+    /// Forwards requests to `backend` and saves every interaction to
+    /// `path`, overwriting the previous cassette.
+    pub fn record<TBackend, TPath>(backend: TBackend, path: TPath) -> CassetteBackend
+    where
+        TBackend: backend::HttpBackend,
+        TPath: Into<std::path::PathBuf>,
+    {
+        CassetteBackend::new(
+            Mode::Record {
+                backend: Box::new(backend),
+                path: path.into(),
+            },
+            Cassette::default(),
+        )
+    }
+
+    /// Serves responses from the cassette at `path`.
+    ///
+    /// Interactions with the same request are served in recorded order,
+    /// the last one is repeated once all of them are used. A request
+    /// without an interaction fails with a permanent `BackendError`, is
+    /// logged as an error and makes `verify` fail.
+    pub fn replay<TPath: AsRef<std::path::Path>>(
+        path: TPath,
+    ) -> std::io::Result<CassetteBackend> {
+        Ok(CassetteBackend::from_cassette(Cassette::load(path)?))
+    }
+
+    pub fn from_cassette(cassette: Cassette) -> CassetteBackend {
+        CassetteBackend::new(Mode::Replay, cassette)
+    }
+
+    fn new(mode: Mode, cassette: Cassette) -> CassetteBackend {
+        let used = vec![false; cassette.interactions.len()];
+        CassetteBackend {
+            mode,
+            cassette: std::sync::Mutex::new(cassette),
+            used: std::sync::Mutex::new(used),
+            unmatched: Default::default(),
+        }
+    }
+
+    /// Copy of the interactions recorded or loaded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+
+    /// Requests which had no interaction in the cassette.
+    pub fn unmatched(&self) -> Vec<RecordedRequest> {
+        self.unmatched.lock().unwrap().clone()
+    }
+
+    /// Fails if any request was unmatched or, when replaying, any
+    /// interaction was not served.
+    pub fn verify(&self) -> Result<(), String> {
+        let unmatched = self.unmatched();
+        if !unmatched.is_empty() {
+            return Err(format!("Unmatched requests: {:?}", unmatched));
+        }
+        if let Mode::Record { .. } = self.mode {
+            return Ok(());
+        }
+        let used = self.used.lock().unwrap();
+        let cassette = self.cassette.lock().unwrap();
+        let unused: Vec<&RecordedRequest> = cassette
+            .interactions
+            .iter()
+            .zip(used.iter())
+            .filter(|(_, used)| !**used)
+            .map(|(interaction, _)| &interaction.request)
+            .collect();
+        if unused.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Interactions not replayed: {:?}", unused))
+        }
+    }
+
+    fn replay_request(
+        &self,
+        request: RecordedRequest,
+    ) -> Result<http::Response<backend::Body>, backend::BackendError> {
+        let cassette = self.cassette.lock().unwrap();
+        let mut used = self.used.lock().unwrap();
+        let matching: Vec<usize> = cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| interaction.request == request)
+            .map(|(index, _)| index)
+            .collect();
+        let index = matching
+            .iter()
+            .copied()
+            .find(|index| !used[*index])
+            .or_else(|| matching.last().copied());
+        match index {
+            Some(index) => {
+                used[index] = true;
+                cassette.interactions[index].response.to_response()
+            }
+            None => {
+                let message = format!(
+                    "no cassette interaction for {} {}{}",
+                    request.method,
+                    request.path,
+                    request
+                        .query
+                        .as_ref()
+                        .map(|query| format!("?{}", query))
+                        .unwrap_or_default()
+                );
+                log::error!("{}", message);
+                self.unmatched.lock().unwrap().push(request);
+                Err(backend::BackendError::permanent(message))
+            }
+        }
+    }
+}
+
+impl backend::HttpBackend for CassetteBackend {
+    fn send(&self, request: http::Request<backend::Body>) -> backend::BackendFuture<'_> {
+        let recorded = RecordedRequest::new(&request);
+        Box::pin(async move {
+            match &self.mode {
+                Mode::Record { backend, path } => {
+                    let response = backend.send(request).await?;
+                    let mut cassette = self.cassette.lock().unwrap();
+                    cassette.interactions.push(Interaction {
+                        request: recorded,
+                        response: RecordedResponse::new(&response),
+                    });
+                    if let Err(error) = cassette.save(path) {
+                        log::error!(
+                            "Failed to save cassette {}: {}",
+                            path.display(),
+                            error
+                        );
+                    }
+                    Ok(response)
+                }
+                Mode::Replay => self.replay_request(recorded),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn record_then_replay() {
+        let path = std::env::temp_dir()
+            .join(format!("chatex-cassette-{}.json", std::process::id()));
+        let balance: crate::models::Balance = vec![crate::models::Currency {
+            amount: "1.5".to_owned(),
+            coin: "btc".to_owned(),
+            held: "0".to_owned(),
+        }];
+        tokio_test::block_on(async {
+            let server = httpmock::MockServer::start();
+            let access_token = serde_json::to_string(&crate::models::AccessToken {
+                access_token: "TOKEN".into(),
+                expires_at: chrono::Utc::now().timestamp() + 3600,
+            })
+            .expect(SERDE_ERROR);
+            server.mock(|when, then| {
+                when.method(httpmock::Method::POST)
+                    .path("/auth/access-token");
+                default_then_content_type(then)
+                    .status(200)
+                    .body(access_token);
+            });
+            let balance = serde_json::to_string(&balance).expect(SERDE_ERROR);
+            server.mock(|when, then| {
+                default_get_when(when).path("/me/balance");
+                default_then_content_type(then).status(200).body(balance);
+            });
+            let backend = std::sync::Arc::new(CassetteBackend::record(
+                crate::backend::HyperBackend::new(hyper::client::HttpConnector::new()),
+                &path,
+            ));
+            let client = crate::ChatexClient::new(
+                backend.clone(),
+                url::Url::parse(&server.base_url()).unwrap(),
+                SECRET.to_owned(),
+            );
+            let recorded = client.profile().get_balance_summary().await.unwrap();
+            assert_eq!(recorded[0].amount, "1.5");
+            assert_eq!(backend.cassette().interactions.len(), 2);
+        });
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("TOKEN"));
+        assert!(!content.contains(SECRET));
+        tokio_test::block_on(async {
+            let backend = std::sync::Arc::new(CassetteBackend::replay(&path).unwrap());
+            let client = crate::ChatexClient::new(
+                backend.clone(),
+                url::Url::parse("http://127.0.0.1:9/").unwrap(),
+                SECRET.to_owned(),
+            );
+            let replayed = client.profile().get_balance_summary().await.unwrap();
+            assert_eq!(replayed[0].coin, "btc");
+            assert_eq!(replayed[0].amount, "1.5");
+            assert!(backend.verify().is_ok());
+            let result = client.profile().get_account_information().await;
+            assert!(matches!(
+                result,
+                Err(crate::error::Error::InternalServerError)
+            ));
+            assert_eq!(backend.unmatched().len(), 1);
+            assert_eq!(backend.unmatched()[0].path, "/me");
+            assert!(backend.verify().is_err());
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,33 +1,265 @@
 use super::{
-    access_controller, client_base, coin_client, context, endpoint, exchange_client,
-    invoice_client, payment_system_client, profile_client,
+    access_controller, backend, client_base, clock, coin_client, config, context, endpoint,
+    exchange_client, invoice_client, middleware, payment_system_client, profile_client,
+    rate_limiter, retry, secret, token_refresher, token_store, transport,
 };
-use hyper;
+#[cfg(feature = "journal")]
+use super::journal;
+#[cfg(feature = "metrics")]
+use super::metrics;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use super::tls;
+use chrono;
 
-pub struct ChatexClient<TConnector> {
-    base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+pub struct ChatexClient<TBackend> {
+    base: std::sync::Arc<client_base::ClientBase<TBackend>>,
     profile: std::sync::Arc<endpoint::Profile>,
     coin: std::sync::Arc<endpoint::Coin>,
     exchange: std::sync::Arc<endpoint::Exchange>,
     invoice: std::sync::Arc<endpoint::Invoice>,
     payment_system: std::sync::Arc<endpoint::PaymentSystem>,
+    _refresher: Option<token_refresher::RefresherHandle>,
 }
 
-impl<TConnector> ChatexClient<TConnector>
+impl<TBackend> ChatexClient<TBackend>
 where
-    TConnector: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+    TBackend: backend::HttpBackend,
 {
-    pub fn new(
-        connector: TConnector,
+    pub fn new<TSecret: Into<secret::Secret>>(
+        backend: TBackend,
         base_url: url::Url,
-        secret: String,
-    ) -> ChatexClient<TConnector>
+        secret: TSecret,
+    ) -> ChatexClient<TBackend> {
+        ChatexClient::builder(backend, base_url, secret).build()
+    }
+
+    /// Builds a client with the settings of a loaded `config::Config`.
+    pub fn from_config(backend: TBackend, config: &config::Config) -> ChatexClient<TBackend> {
+        config.builder(backend).build()
+    }
+
+    pub fn builder<TSecret: Into<secret::Secret>>(
+        backend: TBackend,
+        base_url: url::Url,
+        secret: TSecret,
+    ) -> ChatexClientBuilder<TBackend> {
+        ChatexClientBuilder::new(backend, base_url, secret)
+    }
+
+    pub fn profile(&self) -> profile_client::ProfileClient<TBackend> {
+        profile_client::ProfileClient::new(self.base.clone(), self.profile.clone())
+    }
+
+    pub fn coin(&self) -> coin_client::CoinClient<TBackend> {
+        coin_client::CoinClient::new(self.base.clone(), self.coin.clone())
+    }
+
+    pub fn exchange(&self) -> exchange_client::ExchangeClient<TBackend> {
+        exchange_client::ExchangeClient::new(self.base.clone(), self.exchange.clone())
+    }
+
+    pub fn invoice(&self) -> invoice_client::InvoiceClient<TBackend> {
+        invoice_client::InvoiceClient::new(self.base.clone(), self.invoice.clone())
+    }
+
+    pub fn payment_system(
+        &self,
+    ) -> payment_system_client::PaymentSystemClient<TBackend> {
+        payment_system_client::PaymentSystemClient::new(
+            self.base.clone(),
+            self.payment_system.clone(),
+        )
+    }
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl ChatexClient<tls::HttpsBackend> {
+    /// Client over HTTPS with the TLS implementation enabled by features.
+    pub fn https<TSecret: Into<secret::Secret>>(
+        base_url: url::Url,
+        secret: TSecret,
+    ) -> Result<Self, tls::TlsError> {
+        ChatexClient::https_with_options(base_url, secret, &tls::TlsOptions::new())
+    }
+
+    pub fn https_with_options<TSecret: Into<secret::Secret>>(
+        base_url: url::Url,
+        secret: TSecret,
+        options: &tls::TlsOptions,
+    ) -> Result<Self, tls::TlsError> {
+        Ok(ChatexClient::new(tls::https_backend(options)?, base_url, secret))
+    }
+
+    /// Like `from_config`, also trusting the root certificate of the config.
+    pub fn https_from_config(config: &config::Config) -> Result<Self, tls::TlsError> {
+        let mut options = tls::TlsOptions::new();
+        if let Some(path) = config.root_certificate.as_ref() {
+            options = options.root_certificate_file(path).map_err(|error| {
+                tls::TlsError::Certificate(format!("{}: {}", path.display(), error))
+            })?;
+        }
+        Ok(ChatexClient::from_config(tls::https_backend(&options)?, config))
+    }
+}
+
+pub struct ChatexClientBuilder<TBackend> {
+    backend: std::sync::Arc<TBackend>,
+    base_url: url::Url,
+    secret: secret::Secret,
+    middleware: middleware::MiddlewareChain,
+    token_store: std::sync::Arc<dyn token_store::TokenStore>,
+    expiration_tolerance: chrono::Duration,
+    refresh: Option<token_refresher::RefreshConfig>,
+    clock: std::sync::Arc<dyn clock::Clock>,
+    retry: retry::RetryPolicy,
+    rate_limiter: Option<std::sync::Arc<rate_limiter::RateLimiter>>,
+    timeout: Option<std::time::Duration>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<std::sync::Arc<dyn metrics::Recorder>>,
+    #[cfg(feature = "journal")]
+    journal: Option<std::sync::Arc<journal::Journal>>,
+}
+
+impl<TBackend> ChatexClientBuilder<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new<TSecret: Into<secret::Secret>>(
+        backend: TBackend,
+        base_url: url::Url,
+        secret: TSecret,
+    ) -> ChatexClientBuilder<TBackend> {
+        ChatexClientBuilder::with_backend(std::sync::Arc::new(backend), base_url, secret)
+    }
+
+    /// Uses a shared backend, so e.g. its connection pool is shared with
+    /// every other client built from it.
+    pub fn with_backend<TSecret: Into<secret::Secret>>(
+        backend: std::sync::Arc<TBackend>,
+        base_url: url::Url,
+        secret: TSecret,
+    ) -> ChatexClientBuilder<TBackend> {
+        ChatexClientBuilder {
+            backend,
+            base_url,
+            secret: secret.into(),
+            middleware: middleware::MiddlewareChain::new(),
+            token_store: std::sync::Arc::new(token_store::MemoryTokenStore::new()),
+            expiration_tolerance: chrono::Duration::seconds(
+                context::AccessContext::TIME_EXPIRATION_TOLERANCE,
+            ),
+            refresh: None,
+            clock: std::sync::Arc::new(clock::SystemClock),
+            retry: retry::RetryPolicy::none(),
+            rate_limiter: None,
+            timeout: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "journal")]
+            journal: None,
+        }
+    }
+
+    /// Appends a middleware to the chain shared by every sub-client.
+    pub fn middleware<TMiddleware>(mut self, middleware: TMiddleware) -> Self
     where
-        TConnector: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+        TMiddleware: middleware::Middleware + 'static,
     {
-        let client = hyper::Client::builder().build::<TConnector, hyper::Body>(connector);
-        let base_context = context::BaseContext::new(base_url);
-        let api_context = context::ApiContext::new(base_context.clone(), secret);
+        self.middleware.push(std::sync::Arc::new(middleware));
+        self
+    }
+
+    /// Sets where access tokens are loaded from and persisted to.
+    ///
+    /// Use `token_store::FileTokenStore` to reuse tokens across process restarts.
+    pub fn token_store(
+        mut self,
+        token_store: std::sync::Arc<dyn token_store::TokenStore>,
+    ) -> Self {
+        self.token_store = token_store;
+        self
+    }
+
+    /// Sets how long before its expiration a token is treated as expired.
+    pub fn expiration_tolerance(mut self, tolerance: chrono::Duration) -> Self {
+        self.expiration_tolerance = tolerance;
+        self
+    }
+
+    /// Sets the local clock used for token expiry and other time-based logic.
+    ///
+    /// It is still corrected by the server time skew from `Date` headers.
+    pub fn clock(mut self, clock: std::sync::Arc<dyn clock::Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets how failed requests are retried. Nothing is retried by default.
+    pub fn retry_policy(mut self, retry: retry::RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Limits the request rate. The limiter may be shared between clients
+    /// which use the same API key.
+    pub fn rate_limiter(
+        mut self,
+        rate_limiter: std::sync::Arc<rate_limiter::RateLimiter>,
+    ) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Fails an attempt whose response, body included, took longer than
+    /// `timeout`. Waiting for the rate limiter doesn't count.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Renews the access token in a background task instead of on the next call.
+    ///
+    /// The task is spawned by `build`, which then must be called within a
+    /// Tokio runtime. It stops when the `ChatexClient` is dropped.
+    pub fn background_refresh(mut self, config: token_refresher::RefreshConfig) -> Self {
+        self.refresh = Some(config);
+        self
+    }
+
+    /// Sets the recorder which receives request, error and token metrics.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, recorder: std::sync::Arc<dyn metrics::Recorder>) -> Self {
+        self.metrics = Some(recorder);
+        self
+    }
+
+    /// Records mutating exchange and invoice calls and every order, trade and
+    /// invoice seen into the journal.
+    ///
+    /// The SQLite writes run on Tokio's blocking thread pool, the call
+    /// returns once they are done.
+    #[cfg(feature = "journal")]
+    pub fn journal(mut self, journal: std::sync::Arc<journal::Journal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn build(self) -> ChatexClient<TBackend> {
+        let mut transport = transport::Transport::new(self.backend, self.middleware);
+        transport.clock = std::sync::Arc::new(clock::ServerClock::new(self.clock));
+        transport.retry = self.retry;
+        transport.rate_limiter = self.rate_limiter;
+        transport.timeout = self.timeout;
+        #[cfg(feature = "metrics")]
+        {
+            transport.metrics = self.metrics;
+        }
+        #[cfg(feature = "journal")]
+        {
+            transport.journal = self.journal;
+        }
+        let base_context = context::BaseContext::new(self.base_url);
+        let api_context = context::ApiContext::new(base_context.clone(), self.secret);
         let profile = endpoint::Profile::new(&base_context);
         let profile = std::sync::Arc::new(profile);
         let coin = endpoint::Coin::new(&base_context);
@@ -38,9 +270,16 @@ where
         let invoice = std::sync::Arc::new(invoice);
         let payment_system = endpoint::PaymentSystem::new(&base_context);
         let payment_system = std::sync::Arc::new(payment_system);
-        let access_controller = access_controller::AccessController::new(profile.clone());
-        let base = client_base::ClientBase::new(client, api_context, access_controller);
+        let access_controller = access_controller::AccessController::with_token_store(
+            profile.clone(),
+            self.token_store,
+        )
+        .expiration_tolerance(self.expiration_tolerance);
+        let base = client_base::ClientBase::new(transport, api_context, access_controller);
         let base = std::sync::Arc::new(base);
+        let refresher = self
+            .refresh
+            .map(|config| token_refresher::spawn(std::sync::Arc::downgrade(&base), config));
         ChatexClient {
             base,
             profile,
@@ -48,31 +287,7 @@ where
             exchange,
             invoice,
             payment_system,
+            _refresher: refresher,
         }
     }
-
-    pub fn profile(&self) -> profile_client::ProfileClient<TConnector> {
-        profile_client::ProfileClient::new(self.base.clone(), self.profile.clone())
-    }
-
-    pub fn coin(&self) -> coin_client::CoinClient<TConnector> {
-        coin_client::CoinClient::new(self.base.clone(), self.coin.clone())
-    }
-
-    pub fn exchange(&self) -> exchange_client::ExchangeClient<TConnector> {
-        exchange_client::ExchangeClient::new(self.base.clone(), self.exchange.clone())
-    }
-
-    pub fn invoice(&self) -> invoice_client::InvoiceClient<TConnector> {
-        invoice_client::InvoiceClient::new(self.base.clone(), self.invoice.clone())
-    }
-
-    pub fn payment_system(
-        &self,
-    ) -> payment_system_client::PaymentSystemClient<TConnector> {
-        payment_system_client::PaymentSystemClient::new(
-            self.base.clone(),
-            self.payment_system.clone(),
-        )
-    }
 }
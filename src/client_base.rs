@@ -1,27 +1,29 @@
 use super::access_controller;
+use super::backend;
+use super::call;
 use super::context;
 use super::error;
-use hyper;
+use super::transport;
 use http;
 use futures;
 
-pub struct ClientBase<TConnector> {
-    pub client: hyper::Client<TConnector>,
+pub struct ClientBase<TBackend> {
+    pub transport: transport::Transport<TBackend>,
     pub api_context: context::ApiContext,
     access_controller: access_controller::AccessController,
 }
 
-impl<TConnector> ClientBase<TConnector>
+impl<TBackend> ClientBase<TBackend>
 where
-    TConnector: hyper::client::connect::Connect + Send + Sync + Clone + 'static,
+    TBackend: backend::HttpBackend,
 {
     pub fn new(
-        client: hyper::Client<TConnector>,
+        transport: transport::Transport<TBackend>,
         api_context: context::ApiContext,
         access_controller: access_controller::AccessController,
-    ) -> ClientBase<TConnector> {
+    ) -> ClientBase<TBackend> {
         ClientBase {
-            client,
+            transport,
             api_context,
             access_controller,
         }
@@ -29,17 +31,31 @@ where
 
     pub async fn get_access_token(&self) -> Result<context::AccessToken, error::Error> {
         self.access_controller
-            .get_access_token(&self.api_context, &self.client)
+            .get_access_token(&self.api_context, &self.transport)
             .await
     }
 
+    /// Requests a new access token regardless of the current one.
+    pub async fn refresh_access_token(&self) -> Result<(), error::Error> {
+        self.access_controller
+            .refresh_access_token(&self.api_context, &self.transport)
+            .await
+    }
+
+    pub fn next_token_refresh_at(
+        &self,
+        lifetime_fraction: f64,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.access_controller.next_refresh_at(lifetime_fraction)
+    }
+
     pub async fn create_request<Endpoint, CreateRequest>(
         &self,
         endpoint: &Endpoint,
         create_request: CreateRequest,
-    ) -> Result<http::Request<hyper::Body>, error::Error> 
+    ) -> Result<http::Request<backend::Body>, error::Error> 
     where
-        CreateRequest: Fn(context::AccessToken, &Endpoint) -> http::Request<hyper::Body>,
+        CreateRequest: Fn(context::AccessToken, &Endpoint) -> http::Request<backend::Body>,
     {
         let access_token = match self.get_access_token().await {
             Ok(access_token) => access_token,
@@ -50,27 +66,76 @@ where
 
     pub async fn call_to_endpoint<F, ProcessResponse, TResult>(
         &self,
-        request: http::Request<hyper::Body>,
+        call: call::Call,
+        request: http::Request<backend::Body>,
         process_response: ProcessResponse,
     ) -> Result<TResult, error::Error> 
     where 
         F: futures::Future<Output=Option<TResult>>,
-        ProcessResponse: 'static + Fn(hyper::Body) -> F,
+        ProcessResponse: 'static + Fn(backend::Body) -> F,
     {
-        let (header, body) = match self.client.request(request).await {
-            Ok(response) => {
-                response.into_parts()
-            },
-            Err(error) => {
-                log::error!("{}", error);
-                return Err(error::Error::InternalServerError);
-            },
-        };
-        if error::Error::is_error_code(header.status) {
-            let error = error::Error::to_error(header.status, body).await;
-            Err(error)
-        } else {
-            Ok(process_response(body).await.unwrap())
+        log::debug!("Calling {} ({})", call.endpoint, request.uri().path());
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "chatex.call",
+            endpoint = call.endpoint,
+            method = %request.method(),
+            path = request.uri().path(),
+            pair = tracing::field::Empty,
+            order_id = tracing::field::Empty,
+            status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+            retries = 0u32,
+        );
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(pair) = call.pair.as_deref() {
+                span.record("pair", pair);
+            }
+            if let Some(order_id) = call.order_id.as_deref() {
+                span.record("order_id", order_id);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        let started_at = std::time::Instant::now();
+        #[cfg(feature = "journal")]
+        let journaled = (
+            request.method().clone(),
+            request.uri().path().to_owned(),
+            request.body().clone(),
+        );
+        let send = self.transport.send(request);
+        #[cfg(feature = "tracing")]
+        let send = tracing::Instrument::instrument(send, span);
+        let result = send.await;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.transport.metrics.as_ref() {
+            let error = result.as_ref().err();
+            metrics.record_request(call.endpoint, started_at.elapsed(), error);
+            if let Some(error::Error::RateLimitedError { .. }) = error {
+                metrics.record_rate_limited(call.endpoint);
+            }
+        }
+        #[cfg(feature = "journal")]
+        if let Some(journal) = self.transport.journal.clone() {
+            let (method, path, request_body) = journaled;
+            let response = match result.as_ref() {
+                Ok((_header, body)) => Ok(body.clone()),
+                Err(error) => Err(error.to_string()),
+            };
+            let endpoint = call.endpoint;
+            let observed = tokio::task::spawn_blocking(move || {
+                let response = match response.as_ref() {
+                    Ok(body) => Ok(body.as_ref()),
+                    Err(error) => Err(error.as_str()),
+                };
+                journal.observe(&call, &method, &path, &request_body, response);
+            });
+            if let Err(error) = observed.await {
+                log::error!("Failed to journal {}: {}", endpoint, error);
+            }
         }
+        let (_header, body) = result?;
+        Ok(process_response(body).await.unwrap())
     }
 }
@@ -0,0 +1,145 @@
+use chrono;
+
+/// Source of the current time for expiry checks and other time-based logic.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> chrono::DateTime<chrono::Utc>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now()
+    }
+}
+
+/// Clock which only moves when told to. Meant for tests.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: std::sync::Mutex<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: chrono::DateTime<chrono::Utc>) -> ManualClock {
+        ManualClock {
+            now: std::sync::Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: chrono::DateTime<chrono::Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Local clock corrected by the skew observed in `Date` response headers.
+///
+/// Token expiration times come from the server, so they are compared with
+/// the server time rather than with the local one.
+pub struct ServerClock {
+    local: std::sync::Arc<dyn Clock>,
+    skew_milliseconds: std::sync::atomic::AtomicI64,
+}
+
+impl ServerClock {
+    pub fn new(local: std::sync::Arc<dyn Clock>) -> ServerClock {
+        ServerClock {
+            local,
+            skew_milliseconds: std::sync::atomic::AtomicI64::new(0),
+        }
+    }
+
+    /// Server time minus local time as seen in the last `Date` header.
+    pub fn skew(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds(
+            self.skew_milliseconds
+                .load(std::sync::atomic::Ordering::Relaxed),
+        )
+    }
+
+    /// Updates the skew from the `Date` header, if there is a valid one.
+    ///
+    /// The header has a one second resolution, so differences below a
+    /// second are ignored.
+    pub fn observe(&self, headers: &http::HeaderMap) {
+        let server_time = match headers
+            .get(http::header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| chrono::DateTime::parse_from_rfc2822(date).ok())
+        {
+            Some(server_time) => server_time.with_timezone(&chrono::Utc),
+            None => return,
+        };
+        let skew = server_time.signed_duration_since(self.local.now());
+        let skew = if skew.num_seconds() == 0 {
+            0
+        } else {
+            skew.num_milliseconds()
+        };
+        self.skew_milliseconds
+            .store(skew, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+impl Default for ServerClock {
+    fn default() -> Self {
+        ServerClock::new(std::sync::Arc::new(SystemClock))
+    }
+}
+
+impl Clock for ServerClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        self.local.now() + self.skew()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn create_time(timestamp: i64) -> chrono::DateTime<chrono::Utc> {
+        chrono::TimeZone::timestamp_opt(&chrono::Utc, timestamp, 0).unwrap()
+    }
+
+    #[test]
+    fn manual_clock_advances() {
+        let clock = ManualClock::new(create_time(1000));
+        clock.advance(chrono::Duration::seconds(5));
+        assert_eq!(clock.now(), create_time(1005));
+        clock.set(create_time(10));
+        assert_eq!(clock.now(), create_time(10));
+    }
+
+    #[test]
+    fn server_clock_follows_date_header() {
+        let local = std::sync::Arc::new(ManualClock::new(create_time(784_887_271)));
+        let clock = ServerClock::new(local.clone());
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::DATE,
+            http::HeaderValue::from_static("Tue, 15 Nov 1994 08:12:31 GMT"),
+        );
+        clock.observe(&headers);
+        assert_eq!(clock.skew(), chrono::Duration::seconds(-120));
+        assert_eq!(clock.now(), create_time(784_887_151));
+        local.advance(chrono::Duration::seconds(10));
+        assert_eq!(clock.now(), create_time(784_887_161));
+        headers.insert(
+            http::header::DATE,
+            http::HeaderValue::from_static("garbage"),
+        );
+        clock.observe(&headers);
+        assert_eq!(clock.skew(), chrono::Duration::seconds(-120));
+    }
+}
@@ -54,7 +54,7 @@ impl std::fmt::Display for Coin {
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Clone)]
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
 pub struct CoinPair {
     pub left: Coin,
     pub right: Coin,
@@ -68,6 +68,17 @@ impl CoinPair {
     pub fn reversed(&self) -> CoinPair {
         CoinPair::new(self.right.clone(), self.left.clone())
     }
+
+    /// Parses the `left/right` form used by the exchange, e.g. `btc/usdt_erc20`.
+    pub fn parse(pair: &str) -> Option<CoinPair> {
+        let mut coins = pair.split('/');
+        match (coins.next(), coins.next(), coins.next()) {
+            (Some(left), Some(right), None) if !left.is_empty() && !right.is_empty() => {
+                Some(CoinPair::new(Coin::from(left), Coin::from(right)))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl From<&CoinPair> for String {
@@ -1,24 +1,25 @@
+use super::backend;
+use super::call;
 use super::client_base;
 use super::coin;
 use super::endpoint;
 use super::error;
 use super::extractor;
 use super::models;
-use hyper;
 
-pub struct CoinClient<TConnector> {
-    base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+pub struct CoinClient<TBackend> {
+    base: std::sync::Arc<client_base::ClientBase<TBackend>>,
     coin: std::sync::Arc<endpoint::Coin>,
 }
 
-impl<TConnector> CoinClient<TConnector>
+impl<TBackend> CoinClient<TBackend>
 where
-    TConnector: hyper::client::connect::Connect + Send + Sync + Clone + 'static,
+    TBackend: backend::HttpBackend,
 {
     pub fn new(
-        base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+        base: std::sync::Arc<client_base::ClientBase<TBackend>>,
         coin: std::sync::Arc<endpoint::Coin>,
-    ) -> CoinClient<TConnector> {
+    ) -> CoinClient<TBackend> {
         CoinClient { base, coin }
     }
 
@@ -29,10 +30,11 @@ where
                     coin.coins(&access_token)
                         .expect("Failed to build /coins request!")
                 });
+        let call = call::Call::new("coin.get_available_coins");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_coins(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_coins(body))
                     .await
             }
             Err(error) => Err(error),
@@ -48,10 +50,11 @@ where
                     .expect("Failed to build /coins/name request!")
             },
         );
+        let call = call::Call::new("coin.get_coin");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_coin(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_coin(body))
                     .await
             }
             Err(error) => Err(error),
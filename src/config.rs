@@ -0,0 +1,432 @@
+//! Client settings loaded from a TOML file with named profiles and from
+//! environment variables.
+//!
+//! Values are resolved in this order, later ones win:
+//!
+//! 1. defaults,
+//! 2. the selected profile of the file,
+//! 3. `CHATEX_*` environment variables.
+//!
+//! ```toml
+//! [profiles.prod]
+//! base_url = "https://api.chatex.com/v1/"
+//! api_key_file = "/run/secrets/chatex"
+//! timeout_ms = 10000
+//!
+//! [profiles.prod.retry]
+//! max_retries = 3
+//! initial_backoff_ms = 200
+//! max_backoff_ms = 5000
+//!
+//! [profiles.prod.rate_limit]
+//! requests_per_second = 5.0
+//! burst = 10
+//!
+//! [profiles.local]
+//! base_url = "https://127.0.0.1:8443/"
+//! api_key = "fake"
+//! root_certificate = "fake-server.pem"
+//! ```
+use super::{backend, chatex_client, rate_limiter, retry, secret};
+
+/// Environment variable with the path of the config file.
+pub const CONFIG_ENV: &str = "CHATEX_CONFIG";
+/// Environment variable with the name of the profile.
+pub const PROFILE_ENV: &str = "CHATEX_PROFILE";
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::path::PathBuf, std::io::Error),
+    Parse(String),
+    UnknownProfile(String),
+    Missing(&'static str),
+    Invalid { key: &'static str, value: String },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => {
+                write!(formatter, "Failed to read {}: {}", path.display(), error)
+            }
+            ConfigError::Parse(error) => {
+                write!(formatter, "Failed to parse config: {}", error)
+            }
+            ConfigError::UnknownProfile(name) => {
+                write!(formatter, "Unknown profile {}", name)
+            }
+            ConfigError::Missing(key) => write!(formatter, "{} is not set", key),
+            ConfigError::Invalid { key, value } => {
+                write!(formatter, "Invalid value of {}: {}", key, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Contents of the config file.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub profiles: std::collections::BTreeMap<String, Profile>,
+}
+
+/// Settings of one profile. Every field is optional, so a profile can be
+/// completed by the environment.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub base_url: Option<String>,
+    pub api_key: Option<secret::Secret>,
+    /// File with the API key. `api_key` wins if both are set in one place.
+    pub api_key_file: Option<std::path::PathBuf>,
+    pub timeout_ms: Option<u64>,
+    /// PEM file with an additional trusted root certificate, used by
+    /// `ChatexClient::https_from_config`.
+    pub root_certificate: Option<std::path::PathBuf>,
+    pub retry: Option<RetrySettings>,
+    pub rate_limit: Option<RateLimitSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RetrySettings {
+    pub max_retries: Option<u32>,
+    pub initial_backoff_ms: Option<u64>,
+    pub max_backoff_ms: Option<u64>,
+    pub retry_non_idempotent: Option<bool>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub requests_per_second: f64,
+    /// Requests allowed at once, 1 if not set.
+    pub burst: Option<u32>,
+}
+
+/// Fully resolved settings, ready to build a client.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub base_url: url::Url,
+    pub api_key: secret::Secret,
+    pub timeout: Option<std::time::Duration>,
+    pub root_certificate: Option<std::path::PathBuf>,
+    pub retry: retry::RetryPolicy,
+    pub rate_limit: Option<RateLimitSettings>,
+}
+
+impl ConfigFile {
+    pub fn parse(content: &str) -> Result<ConfigFile, ConfigError> {
+        toml::from_str(content).map_err(|error| ConfigError::Parse(error.to_string()))
+    }
+
+    pub fn read(path: &std::path::Path) -> Result<ConfigFile, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| ConfigError::Io(path.to_owned(), error))?;
+        ConfigFile::parse(&content)
+    }
+
+    /// `$CHATEX_CONFIG` or `~/.config/chatex/config.toml`.
+    pub fn default_path() -> Option<std::path::PathBuf> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return Some(path.into());
+        }
+        let home = std::env::var_os("HOME")?;
+        Some(
+            std::path::PathBuf::from(home)
+                .join(".config")
+                .join("chatex")
+                .join("config.toml"),
+        )
+    }
+
+    pub fn profile(&self, name: &str) -> Result<Profile, ConfigError> {
+        self.profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ConfigError::UnknownProfile(name.to_owned()))
+    }
+}
+
+impl Profile {
+    /// Overrides the fields with `CHATEX_*` variables found by `lookup`:
+    /// `CHATEX_BASE_URL`, `CHATEX_API_KEY`, `CHATEX_API_KEY_FILE`,
+    /// `CHATEX_TIMEOUT_MS`, `CHATEX_ROOT_CERTIFICATE`, `CHATEX_MAX_RETRIES`,
+    /// `CHATEX_RATE_LIMIT` and `CHATEX_RATE_LIMIT_BURST`.
+    pub fn merge_env<Lookup>(mut self, lookup: Lookup) -> Result<Profile, ConfigError>
+    where
+        Lookup: Fn(&str) -> Option<String>,
+    {
+        if let Some(base_url) = lookup("CHATEX_BASE_URL") {
+            self.base_url = Some(base_url);
+        }
+        if let Some(api_key_file) = lookup("CHATEX_API_KEY_FILE") {
+            self.api_key = None;
+            self.api_key_file = Some(api_key_file.into());
+        }
+        if let Some(api_key) = lookup("CHATEX_API_KEY") {
+            self.api_key = Some(api_key.into());
+        }
+        if let Some(timeout_ms) = lookup("CHATEX_TIMEOUT_MS") {
+            self.timeout_ms = Some(parse("CHATEX_TIMEOUT_MS", timeout_ms)?);
+        }
+        if let Some(root_certificate) = lookup("CHATEX_ROOT_CERTIFICATE") {
+            self.root_certificate = Some(root_certificate.into());
+        }
+        if let Some(max_retries) = lookup("CHATEX_MAX_RETRIES") {
+            self.retry.get_or_insert_with(Default::default).max_retries =
+                Some(parse("CHATEX_MAX_RETRIES", max_retries)?);
+        }
+        if let Some(requests_per_second) = lookup("CHATEX_RATE_LIMIT") {
+            let requests_per_second = parse("CHATEX_RATE_LIMIT", requests_per_second)?;
+            match self.rate_limit.as_mut() {
+                Some(rate_limit) => rate_limit.requests_per_second = requests_per_second,
+                None => {
+                    self.rate_limit = Some(RateLimitSettings {
+                        requests_per_second,
+                        burst: None,
+                    })
+                }
+            }
+        }
+        if let Some(burst) = lookup("CHATEX_RATE_LIMIT_BURST") {
+            let burst = parse("CHATEX_RATE_LIMIT_BURST", burst)?;
+            match self.rate_limit.as_mut() {
+                Some(rate_limit) => rate_limit.burst = Some(burst),
+                None => return Err(ConfigError::Missing("CHATEX_RATE_LIMIT")),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Loads the profile `profile`, or `$CHATEX_PROFILE`, or `default`,
+    /// with the environment merged in.
+    ///
+    /// The file is `path`, or the default path if it exists. Without a file
+    /// everything has to come from the environment.
+    pub fn load(
+        path: Option<&std::path::Path>,
+        profile: Option<&str>,
+    ) -> Result<Profile, ConfigError> {
+        let env_profile = std::env::var(PROFILE_ENV).ok();
+        let name = profile.or(env_profile.as_deref());
+        let file = match path {
+            Some(path) => Some(ConfigFile::read(path)?),
+            None => match ConfigFile::default_path() {
+                Some(path) if path.exists() => Some(ConfigFile::read(&path)?),
+                _ => None,
+            },
+        };
+        let profile = match (file, name) {
+            (Some(file), Some(name)) => file.profile(name)?,
+            (Some(file), None) => file
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .cloned()
+                .unwrap_or_default(),
+            (None, Some(name)) => {
+                return Err(ConfigError::UnknownProfile(name.to_owned()))
+            }
+            (None, None) => Profile::default(),
+        };
+        profile.merge_env(|key| std::env::var(key).ok())
+    }
+
+    pub fn resolve(self) -> Result<Config, ConfigError> {
+        let base_url = self.base_url.ok_or(ConfigError::Missing("base_url"))?;
+        let base_url =
+            base_url
+                .parse::<url::Url>()
+                .map_err(|_| ConfigError::Invalid {
+                    key: "base_url",
+                    value: base_url,
+                })?;
+        let api_key = match (self.api_key, self.api_key_file) {
+            (Some(api_key), _) => api_key,
+            (None, Some(path)) => std::fs::read_to_string(&path)
+                .map(|api_key| api_key.trim().into())
+                .map_err(|error| ConfigError::Io(path, error))?,
+            (None, None) => return Err(ConfigError::Missing("api_key")),
+        };
+        let mut retry = retry::RetryPolicy::none();
+        if let Some(settings) = self.retry {
+            let defaults = retry::RetryPolicy::default();
+            retry = retry::RetryPolicy {
+                max_retries: settings.max_retries.unwrap_or(defaults.max_retries),
+                initial_backoff: settings
+                    .initial_backoff_ms
+                    .map_or(defaults.initial_backoff, std::time::Duration::from_millis),
+                max_backoff: settings
+                    .max_backoff_ms
+                    .map_or(defaults.max_backoff, std::time::Duration::from_millis),
+                retry_non_idempotent: settings
+                    .retry_non_idempotent
+                    .unwrap_or(defaults.retry_non_idempotent),
+            };
+        }
+        if let Some(rate_limit) = self.rate_limit.as_ref() {
+            if rate_limit.requests_per_second.is_nan()
+                || rate_limit.requests_per_second <= 0.0
+            {
+                return Err(ConfigError::Invalid {
+                    key: "requests_per_second",
+                    value: rate_limit.requests_per_second.to_string(),
+                });
+            }
+        }
+        Ok(Config {
+            base_url,
+            api_key,
+            timeout: self.timeout_ms.map(std::time::Duration::from_millis),
+            root_certificate: self.root_certificate,
+            retry,
+            rate_limit: self.rate_limit,
+        })
+    }
+}
+
+impl Config {
+    /// Loads and resolves the profile, see `Profile::load`.
+    pub fn load(
+        path: Option<&std::path::Path>,
+        profile: Option<&str>,
+    ) -> Result<Config, ConfigError> {
+        Profile::load(path, profile)?.resolve()
+    }
+
+    /// Builder with every setting of the config applied, for adding
+    /// middleware or other options before `build`.
+    pub fn builder<TBackend>(
+        &self,
+        backend: TBackend,
+    ) -> chatex_client::ChatexClientBuilder<TBackend>
+    where
+        TBackend: backend::HttpBackend,
+    {
+        let mut builder = chatex_client::ChatexClient::builder(
+            backend,
+            self.base_url.clone(),
+            self.api_key.clone(),
+        )
+        .retry_policy(self.retry.clone());
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(rate_limit) = self.rate_limit.as_ref() {
+            builder = builder.rate_limiter(std::sync::Arc::new(
+                rate_limiter::RateLimiter::new(
+                    rate_limit.requests_per_second,
+                    rate_limit.burst.unwrap_or(1),
+                ),
+            ));
+        }
+        builder
+    }
+}
+
+fn parse<T: std::str::FromStr>(
+    key: &'static str,
+    value: String,
+) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::Invalid { key, value })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [profiles.prod]
+        base_url = "https://api.chatex.com/v1/"
+        api_key = "PROD"
+        timeout_ms = 5000
+
+        [profiles.prod.retry]
+        max_retries = 2
+
+        [profiles.prod.rate_limit]
+        requests_per_second = 4.0
+        burst = 8
+
+        [profiles.local]
+        base_url = "http://127.0.0.1:8080/"
+        api_key = "LOCAL"
+    "#;
+
+    #[test]
+    fn profiles_and_env_precedence() {
+        let file = ConfigFile::parse(CONFIG).unwrap();
+        let prod = file.profile("prod").unwrap().resolve().unwrap();
+        assert_eq!(prod.base_url.as_str(), "https://api.chatex.com/v1/");
+        assert_eq!(prod.api_key.expose(), "PROD");
+        assert_eq!(prod.timeout, Some(std::time::Duration::from_secs(5)));
+        assert_eq!(prod.retry.max_retries, 2);
+        assert_eq!(
+            prod.retry.initial_backoff,
+            retry::RetryPolicy::default().initial_backoff
+        );
+        assert_eq!(prod.rate_limit.unwrap().burst, Some(8));
+        let local = file.profile("local").unwrap().resolve().unwrap();
+        assert_eq!(local.retry.max_retries, 0);
+        assert!(local.rate_limit.is_none());
+        let env: std::collections::HashMap<&str, &str> = [
+            ("CHATEX_BASE_URL", "http://staging/"),
+            ("CHATEX_MAX_RETRIES", "5"),
+            ("CHATEX_RATE_LIMIT", "2"),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let staging = file
+            .profile("prod")
+            .unwrap()
+            .merge_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap()
+            .resolve()
+            .unwrap();
+        assert_eq!(staging.base_url.as_str(), "http://staging/");
+        assert_eq!(staging.api_key.expose(), "PROD");
+        assert_eq!(staging.retry.max_retries, 5);
+        let rate_limit = staging.rate_limit.unwrap();
+        assert_eq!(rate_limit.requests_per_second, 2.0);
+        assert_eq!(rate_limit.burst, Some(8));
+        assert!(matches!(
+            file.profile("missing"),
+            Err(ConfigError::UnknownProfile(_))
+        ));
+        assert!(matches!(
+            Profile::default().resolve(),
+            Err(ConfigError::Missing("base_url"))
+        ));
+    }
+
+    #[test]
+    fn api_key_file() {
+        let path =
+            std::env::temp_dir().join(format!("chatex-key-{}", std::process::id()));
+        std::fs::write(&path, "FROM_FILE\n").unwrap();
+        let profile = Profile {
+            base_url: Some("http://127.0.0.1/".to_owned()),
+            api_key_file: Some(path.clone()),
+            ..Default::default()
+        };
+        let config = profile.clone().resolve().unwrap();
+        assert_eq!(config.api_key.expose(), "FROM_FILE");
+        let config = profile
+            .merge_env(|key| match key {
+                "CHATEX_API_KEY" => Some("FROM_ENV".to_owned()),
+                _ => None,
+            })
+            .unwrap()
+            .resolve()
+            .unwrap();
+        assert_eq!(config.api_key.expose(), "FROM_ENV");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,4 +1,6 @@
+use super::clock;
 use super::models;
+use super::secret;
 use chrono;
 use url;
 
@@ -15,41 +17,91 @@ impl BaseContext {
 
 pub struct ApiContext {
     pub base: BaseContext,
-    pub api_key: String,
+    pub api_key: secret::Secret,
 }
 
 impl ApiContext {
-    pub fn new(base: BaseContext, api_key: String) -> ApiContext {
+    pub fn new(base: BaseContext, api_key: secret::Secret) -> ApiContext {
         ApiContext { base, api_key }
     }
 }
 
-pub type AccessToken = String;
+pub type AccessToken = secret::Secret;
 
 pub struct AccessContext {
     pub base: BaseContext,
     pub access_token: models::AccessToken,
+    obtained_at: chrono::DateTime<chrono::Utc>,
     expires_at: chrono::DateTime<chrono::Utc>,
+    tolerance: chrono::Duration,
+    clock: std::sync::Arc<dyn clock::Clock>,
 }
 
 impl AccessContext {
-    const TIME_EXPIRATION_TOLERANCE: i64 = 60;
+    pub const TIME_EXPIRATION_TOLERANCE: i64 = 60;
 
     pub fn new(base: BaseContext, access_token: models::AccessToken) -> AccessContext {
+        AccessContext::with_tolerance(
+            base,
+            access_token,
+            chrono::Duration::seconds(Self::TIME_EXPIRATION_TOLERANCE),
+        )
+    }
+
+    /// The token is treated as expired `tolerance` before its actual expiration.
+    pub fn with_tolerance(
+        base: BaseContext,
+        access_token: models::AccessToken,
+        tolerance: chrono::Duration,
+    ) -> AccessContext {
+        AccessContext::with_clock(
+            base,
+            access_token,
+            tolerance,
+            std::sync::Arc::new(clock::SystemClock),
+        )
+    }
+
+    /// Uses `clock` instead of the system time for all the expiry decisions.
+    ///
+    /// Pass a `clock::ServerClock` to account for the server time skew.
+    pub fn with_clock(
+        base: BaseContext,
+        access_token: models::AccessToken,
+        tolerance: chrono::Duration,
+        clock: std::sync::Arc<dyn clock::Clock>,
+    ) -> AccessContext {
         let expires_at =
-            chrono::NaiveDateTime::from_timestamp(access_token.expires_at, 0);
-        let expires_at =
-            chrono::DateTime::<chrono::Utc>::from_utc(expires_at, chrono::Utc);
+            chrono::TimeZone::timestamp_opt(&chrono::Utc, access_token.expires_at, 0)
+                .single()
+                .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
         AccessContext {
             base,
             access_token,
+            obtained_at: clock.now(),
             expires_at,
+            tolerance,
+            clock,
         }
     }
 
+    pub fn expires_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.expires_at
+    }
+
+    /// The moment when `lifetime_fraction` of the token lifetime has passed.
+    ///
+    /// Never later than the moment the token is considered expired.
+    pub fn refresh_at(&self, lifetime_fraction: f64) -> chrono::DateTime<chrono::Utc> {
+        let lifetime = self.expires_at.signed_duration_since(self.obtained_at);
+        let elapsed = lifetime.num_milliseconds() as f64 * lifetime_fraction;
+        let refresh_at = self.obtained_at + chrono::Duration::milliseconds(elapsed as i64);
+        std::cmp::min(refresh_at, self.expires_at - self.tolerance)
+    }
+
     pub fn expired(&self) -> bool {
-        let subtracted = chrono::Utc::now().signed_duration_since(self.expires_at);
-        subtracted.num_seconds() > -Self::TIME_EXPIRATION_TOLERANCE
+        let subtracted = self.clock.now().signed_duration_since(self.expires_at);
+        subtracted.num_seconds() > -self.tolerance.num_seconds()
     }
 
     pub fn not_expired(&self) -> bool {
@@ -60,36 +112,113 @@ impl AccessContext {
 #[cfg(test)]
 mod test {
     use super::*;
+    use clock::Clock;
 
-    fn create_access_context(time: chrono::DateTime<chrono::Utc>) -> AccessContext {
+    fn create_clock() -> std::sync::Arc<clock::ManualClock> {
+        let now = chrono::TimeZone::timestamp_opt(&chrono::Utc, 1_600_000_000, 0).unwrap();
+        std::sync::Arc::new(clock::ManualClock::new(now))
+    }
+
+    fn create_access_context(
+        clock: std::sync::Arc<clock::ManualClock>,
+        time: chrono::DateTime<chrono::Utc>,
+        tolerance: chrono::Duration,
+    ) -> AccessContext {
         let blank_url = url::Url::parse("http://localhost:8000").unwrap();
         let base_context = BaseContext::new(blank_url);
-        AccessContext::new(
+        AccessContext::with_clock(
             base_context,
             models::AccessToken {
-                access_token: String::new(),
+                access_token: String::new().into(),
                 expires_at: time.timestamp(),
             },
+            tolerance,
+            clock,
         )
     }
 
+    fn default_tolerance() -> chrono::Duration {
+        chrono::Duration::seconds(AccessContext::TIME_EXPIRATION_TOLERANCE)
+    }
+
     #[test]
     fn test_expired() {
-        let access_context = create_access_context(chrono::Utc::now());
+        let clock = create_clock();
+        let access_context =
+            create_access_context(clock.clone(), clock.now(), default_tolerance());
         assert!(access_context.expired(), "AccessContext must be expired!");
     }
 
     #[test]
     fn test_not_expired() {
-        let valid_time = chrono::Utc::now()
+        let clock = create_clock();
+        let valid_time = clock
+            .now()
             .checked_add_signed(chrono::Duration::seconds(
                 AccessContext::TIME_EXPIRATION_TOLERANCE + 1,
             ))
             .expect("Failed to add TIME_EXPIRATION_TOLERANCE");
-        let access_context = create_access_context(valid_time);
+        let access_context =
+            create_access_context(clock.clone(), valid_time, default_tolerance());
         assert!(
             access_context.not_expired(),
             "AccessContext must not be expired!"
         );
+        clock.advance(chrono::Duration::seconds(2));
+        assert!(access_context.expired(), "AccessContext must be expired!");
+    }
+
+    #[test]
+    fn test_custom_tolerance() {
+        let clock = create_clock();
+        let expires_at = clock.now() + chrono::Duration::seconds(120);
+        let access_context = create_access_context(
+            clock.clone(),
+            expires_at,
+            chrono::Duration::seconds(300),
+        );
+        assert!(access_context.expired(), "AccessContext must be expired!");
+    }
+
+    #[test]
+    fn test_server_time_skew() {
+        let local = create_clock();
+        let server = std::sync::Arc::new(clock::ServerClock::new(local.clone()));
+        let expires_at = local.now() + chrono::Duration::seconds(600);
+        let blank_url = url::Url::parse("http://localhost:8000").unwrap();
+        let access_context = AccessContext::with_clock(
+            BaseContext::new(blank_url),
+            models::AccessToken {
+                access_token: String::new().into(),
+                expires_at: expires_at.timestamp(),
+            },
+            default_tolerance(),
+            server.clone(),
+        );
+        assert!(access_context.not_expired());
+        let mut headers = http::HeaderMap::new();
+        let server_time = (local.now() + chrono::Duration::seconds(590)).to_rfc2822();
+        headers.insert(
+            http::header::DATE,
+            http::HeaderValue::from_str(&server_time).unwrap(),
+        );
+        server.observe(&headers);
+        assert!(access_context.expired(), "Server time is ahead of local time!");
+    }
+
+    #[test]
+    fn test_refresh_at() {
+        let clock = create_clock();
+        let expires_at = clock.now() + chrono::Duration::seconds(1000);
+        let access_context =
+            create_access_context(clock.clone(), expires_at, default_tolerance());
+        assert_eq!(
+            access_context.refresh_at(0.5),
+            clock.now() + chrono::Duration::seconds(500)
+        );
+        assert_eq!(
+            access_context.refresh_at(1.0),
+            expires_at - default_tolerance()
+        );
     }
 }
@@ -1,6 +1,8 @@
+use super::backend;
 use super::coin;
 use super::context;
 use super::models;
+use super::secret;
 use chrono;
 use iso_currency;
 use isocountry;
@@ -35,18 +37,21 @@ impl Profile {
     pub fn get_access_token(
         &self,
         api_context: &context::ApiContext,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         create_post_request_with_url(&api_context.api_key, &self.auth)
     }
 
     pub fn get_me(
         &self,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         create_get_request_with_url(&access_token, &self.me)
     }
 
-    pub fn get_balance(&self, access_token: &str) -> Option<http::Request<hyper::Body>> {
+    pub fn get_balance(
+        &self,
+        access_token: &context::AccessToken,
+    ) -> Option<http::Request<backend::Body>> {
         create_get_request_with_url(&access_token, &self.balance)
     }
 }
@@ -63,7 +68,10 @@ impl Coin {
         Coin { coins }
     }
 
-    pub fn coins(&self, access_token: &str) -> Option<http::Request<hyper::Body>> {
+    pub fn coins(
+        &self,
+        access_token: &context::AccessToken,
+    ) -> Option<http::Request<backend::Body>> {
         create_get_request_with_url(&access_token, &self.coins)
     }
 
@@ -71,7 +79,7 @@ impl Coin {
         &self,
         coin: super::coin::Coin,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         // Bad solution. There should be way to implement in without allocations.
         // The simplest way is to use somehow 'static str.
         let coin: String = coin.into();
@@ -117,7 +125,7 @@ impl Exchange {
         offset: Option<u32>,
         limit: Option<u32>,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let pair = String::from(pair);
         let mut orders_url = self.orders.clone();
         orders_url
@@ -144,14 +152,13 @@ impl Exchange {
         amount: String,
         rate: String,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let pair = String::from(pair);
         let order_request = models::OrderRequest { pair, amount, rate };
-        log::info!("Order request: {:#?}", order_request);
         let order_request = serde_json::to_vec(&order_request).unwrap();
         create_post_request_builder_with_url(&access_token, &self.orders)
             .header("Content-Type", "application/json")
-            .body(hyper::body::Body::from(order_request))
+            .body(backend::Body::from(order_request))
             .ok()
     }
 
@@ -162,7 +169,7 @@ impl Exchange {
         offset: Option<u32>,
         limit: Option<u32>,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.my.clone();
         if let Some(pair) = pair {
             url.query_pairs_mut()
@@ -181,7 +188,7 @@ impl Exchange {
         offset: Option<u32>,
         limit: Option<u32>,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.trades.clone();
         if let Some(order_id) = order_id {
             url.query_pairs_mut()
@@ -195,7 +202,7 @@ impl Exchange {
         &self,
         id: &str,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.trades.clone();
         url.path_segments_mut().unwrap().push(id);
         create_get_request_with_url(&access_token, &url)
@@ -205,7 +212,7 @@ impl Exchange {
         &self,
         id: &str,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.orders.clone();
         url.path_segments_mut().unwrap().push(id);
         create_get_request_with_url(&access_token, &url)
@@ -216,15 +223,15 @@ impl Exchange {
         id: &str,
         order: models::UpdateOrder,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.orders.clone();
         url.path_segments_mut().unwrap().push(id);
         let order = serde_json::to_vec(&order).unwrap();
         create_default_request_builder(&access_token)
-            .method(hyper::Method::PUT)
+            .method(http::Method::PUT)
             .uri(url.to_string())
             .header("Content-Type", "application/json")
-            .body(hyper::Body::from(order))
+            .body(backend::Body::from(order))
             .ok()
     }
 
@@ -232,13 +239,13 @@ impl Exchange {
         &self,
         id: &str,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.orders.clone();
         url.path_segments_mut().unwrap().push(id);
         create_default_request_builder(&access_token)
-            .method(hyper::Method::DELETE)
+            .method(http::Method::DELETE)
             .uri(url.to_string())
-            .body(hyper::Body::empty())
+            .body(backend::Body::new())
             .ok()
     }
 
@@ -246,7 +253,7 @@ impl Exchange {
         &self,
         id: &str,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.orders.clone();
         url.path_segments_mut()
             .unwrap()
@@ -259,7 +266,7 @@ impl Exchange {
         &self,
         id: &str,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.orders.clone();
         url.path_segments_mut()
             .unwrap()
@@ -273,14 +280,13 @@ impl Exchange {
         id: &str,
         trade: &models::CreateTradeRequest,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.orders.clone();
         url.path_segments_mut().unwrap().push(id).push(Self::TRADES);
-        log::info!("Url: {}, Body: {:#?}", url, serde_json::to_string(trade));
         let trade = serde_json::to_vec(trade).unwrap();
         create_post_request_builder_with_url(&access_token, &url)
             .header("Content-Type", "application/json")
-            .body(hyper::Body::from(trade))
+            .body(backend::Body::from(trade))
             .ok()
     }
 }
@@ -312,7 +318,7 @@ impl Invoice {
         date_start: Option<chrono::DateTime<chrono::Utc>>,
         date_end: Option<chrono::DateTime<chrono::Utc>>,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.invoices.clone();
         if let Some(coins) = coins {
             let coins = Self::slice_to_string(coins);
@@ -358,12 +364,12 @@ impl Invoice {
         &self,
         invoice: models::CreateInvoice,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let url = self.invoices.clone();
         let invoice = serde_json::to_vec(&invoice).unwrap();
         create_post_request_builder_with_url(&access_token, &url)
             .header("Content-Type", "application/json")
-            .body(hyper::Body::from(invoice))
+            .body(backend::Body::from(invoice))
             .ok()
     }
 
@@ -371,7 +377,7 @@ impl Invoice {
         &self,
         id: String,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.invoices.clone();
         url.path_segments_mut().unwrap().push(id.as_ref());
         create_get_request_with_url(&access_token, &url)
@@ -417,12 +423,12 @@ impl PaymentSystem {
         &self,
         estimate: models::Estimate,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let url = self.estimate.clone();
         let estimate = serde_json::to_vec(&estimate).unwrap();
         create_post_request_builder_with_url(&access_token, &url)
             .header("Content-Type", "application/json")
-            .body(hyper::Body::from(estimate))
+            .body(backend::Body::from(estimate))
             .ok()
     }
 
@@ -430,7 +436,7 @@ impl PaymentSystem {
         &self,
         id: models::PaymentSystemId,
         access_token: &context::AccessToken,
-    ) -> Option<http::Request<hyper::Body>> {
+    ) -> Option<http::Request<backend::Body>> {
         let mut url = self.payment_systems.clone();
         url.path_segments_mut()
             .unwrap()
@@ -439,38 +445,42 @@ impl PaymentSystem {
     }
 }
 
-fn create_default_request_builder(token: &str) -> http::request::Builder {
+fn create_default_request_builder(token: &secret::Secret) -> http::request::Builder {
+    let mut authorization =
+        http::HeaderValue::from_str(&format!("Bearer {}", token.expose()))
+            .expect("Invalid characters in the token!");
+    authorization.set_sensitive(true);
     http::request::Builder::new()
         .header("Accept", "application/json")
-        .header("Authorization", format!("Bearer {}", &token))
+        .header("Authorization", authorization)
 }
 
 fn create_get_request_with_url(
-    token: &str,
+    token: &secret::Secret,
     url: &url::Url,
-) -> Option<http::Request<hyper::Body>> {
+) -> Option<http::Request<backend::Body>> {
     create_default_request_builder(token)
-        .method(hyper::Method::GET)
+        .method(http::Method::GET)
         .uri(url.to_string())
-        .body(hyper::Body::empty())
+        .body(backend::Body::new())
         .ok()
 }
 
 fn create_post_request_builder_with_url(
-    token: &str,
+    token: &secret::Secret,
     url: &url::Url,
 ) -> http::request::Builder {
     create_default_request_builder(token)
-        .method(hyper::Method::POST)
+        .method(http::Method::POST)
         .uri(url.to_string())
 }
 
 fn create_post_request_with_url(
-    token: &str,
+    token: &secret::Secret,
     url: &url::Url,
-) -> Option<http::Request<hyper::Body>> {
+) -> Option<http::Request<backend::Body>> {
     create_post_request_builder_with_url(token, url)
-        .body(hyper::Body::empty())
+        .body(backend::Body::new())
         .ok()
 }
 
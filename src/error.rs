@@ -1,4 +1,4 @@
-use hyper;
+use super::backend;
 use serde;
 
 #[derive(serde::Deserialize, Debug)]
@@ -51,10 +51,24 @@ impl std::fmt::Display for Error {
 impl std::error::Error for Error { }
 
 impl Error {
+    /// Name of the variant, suitable as a metric label.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Error::InternalServerError => "InternalServerError",
+            Error::NotFoundError => "NotFoundError",
+            Error::PermissionDeniedError => "PermissionDeniedError",
+            Error::RateLimitedError { .. } => "RateLimitedError",
+            Error::UnprocessableEntityError => "UnprocessableEntityError",
+            Error::ValidationError => "ValidationError",
+            Error::BadRequest => "BadRequest",
+            Error::Unauthorized => "Unauthorized",
+        }
+    }
+
     pub fn is_error_code(
-        status_code: hyper::StatusCode,
+        status_code: http::StatusCode,
     )-> bool {
-        use hyper::StatusCode;
+        use http::StatusCode;
         match status_code {
             StatusCode::OK => false,
             StatusCode::CREATED => false,
@@ -63,10 +77,10 @@ impl Error {
     }
 
     pub async fn to_error(
-        status_code: hyper::StatusCode,
-        body: hyper::Body,
+        status_code: http::StatusCode,
+        body: backend::Body,
     ) -> Error {
-        use hyper::StatusCode;
+        use http::StatusCode;
         use super::extractor;
         match status_code {
             StatusCode::BAD_REQUEST => Error::BadRequest,
@@ -95,7 +109,7 @@ impl Error {
 #[cfg(test)]
 mod test {
     use super::*;
-    use hyper::StatusCode;
+    use http::StatusCode;
     use tokio_test;
 
     #[test]
@@ -130,12 +144,8 @@ mod test {
 
     #[test]
     fn to_error() {
-        fn create_body(body_content: &'static str) -> hyper::Body {
-            let (mut sender, body) = hyper::Body::channel();
-            tokio_test::block_on(
-                sender.send_data(hyper::body::Bytes::from(body_content)))
-                .expect("Failed to send data");
-            body
+        fn create_body(body_content: &'static str) -> backend::Body {
+            backend::Body::from_static(body_content.as_bytes())
         }
         let empty_body = "{}";
         let body = create_body(empty_body);
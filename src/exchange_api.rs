@@ -0,0 +1,177 @@
+//! Exchange calls the strategy engine is written against.
+//!
+//! `LiveExchange` sends them to the API, `simulator::Simulator` answers them
+//! from recorded order books, so a strategy runs unchanged in both.
+use super::backend;
+use super::chatex_client;
+use super::coin;
+use super::error;
+use super::exchange_client;
+use super::models;
+use super::profile_client;
+
+pub type ApiFuture<'a, T> = futures::future::BoxFuture<'a, Result<T, error::Error>>;
+
+/// Subset of `ExchangeClient` and `ProfileClient` with the same semantics.
+pub trait ExchangeApi: Send + Sync {
+    /// Every order of `pair`, see `ExchangeClient::get_order_book`.
+    fn get_order_book(&self, pair: coin::CoinPair) -> ApiFuture<'_, models::Orders>;
+
+    fn get_all_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+    ) -> ApiFuture<'_, models::Orders>;
+
+    /// Trades of the account, newest first.
+    fn get_trades(
+        &self,
+        order_id: Option<u32>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> ApiFuture<'_, models::Trades>;
+
+    fn create_order_raw<'a>(
+        &'a self,
+        pair: coin::CoinPair,
+        amount: &'a str,
+        rate: &'a str,
+    ) -> ApiFuture<'a, models::Order>;
+
+    fn update_order_by_id<'a>(
+        &'a self,
+        id: &'a str,
+        order: &'a models::UpdateOrder,
+    ) -> ApiFuture<'a, models::Order>;
+
+    fn delete_order_by_id<'a>(&'a self, id: &'a str) -> ApiFuture<'a, models::Order>;
+
+    fn get_balance_summary(&self) -> ApiFuture<'_, models::Balance>;
+}
+
+impl<TApi> ExchangeApi for std::sync::Arc<TApi>
+where
+    TApi: ExchangeApi + ?Sized,
+{
+    fn get_order_book(&self, pair: coin::CoinPair) -> ApiFuture<'_, models::Orders> {
+        (**self).get_order_book(pair)
+    }
+
+    fn get_all_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+    ) -> ApiFuture<'_, models::Orders> {
+        (**self).get_all_my_orders(pair)
+    }
+
+    fn get_trades(
+        &self,
+        order_id: Option<u32>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> ApiFuture<'_, models::Trades> {
+        (**self).get_trades(order_id, offset, limit)
+    }
+
+    fn create_order_raw<'a>(
+        &'a self,
+        pair: coin::CoinPair,
+        amount: &'a str,
+        rate: &'a str,
+    ) -> ApiFuture<'a, models::Order> {
+        (**self).create_order_raw(pair, amount, rate)
+    }
+
+    fn update_order_by_id<'a>(
+        &'a self,
+        id: &'a str,
+        order: &'a models::UpdateOrder,
+    ) -> ApiFuture<'a, models::Order> {
+        (**self).update_order_by_id(id, order)
+    }
+
+    fn delete_order_by_id<'a>(&'a self, id: &'a str) -> ApiFuture<'a, models::Order> {
+        (**self).delete_order_by_id(id)
+    }
+
+    fn get_balance_summary(&self) -> ApiFuture<'_, models::Balance> {
+        (**self).get_balance_summary()
+    }
+}
+
+/// `ExchangeApi` of the real exchange.
+pub struct LiveExchange<TBackend> {
+    exchange: exchange_client::ExchangeClient<TBackend>,
+    profile: profile_client::ProfileClient<TBackend>,
+}
+
+impl<TBackend> LiveExchange<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new(
+        exchange: exchange_client::ExchangeClient<TBackend>,
+        profile: profile_client::ProfileClient<TBackend>,
+    ) -> LiveExchange<TBackend> {
+        LiveExchange { exchange, profile }
+    }
+
+    pub fn from_client(
+        client: &chatex_client::ChatexClient<TBackend>,
+    ) -> LiveExchange<TBackend> {
+        LiveExchange::new(client.exchange(), client.profile())
+    }
+
+    pub fn exchange(&self) -> &exchange_client::ExchangeClient<TBackend> {
+        &self.exchange
+    }
+}
+
+impl<TBackend> ExchangeApi for LiveExchange<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    fn get_order_book(&self, pair: coin::CoinPair) -> ApiFuture<'_, models::Orders> {
+        Box::pin(self.exchange.get_order_book(pair))
+    }
+
+    fn get_all_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+    ) -> ApiFuture<'_, models::Orders> {
+        Box::pin(self.exchange.get_all_my_orders(pair))
+    }
+
+    fn get_trades(
+        &self,
+        order_id: Option<u32>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> ApiFuture<'_, models::Trades> {
+        Box::pin(self.exchange.get_trades(order_id, offset, limit))
+    }
+
+    fn create_order_raw<'a>(
+        &'a self,
+        pair: coin::CoinPair,
+        amount: &'a str,
+        rate: &'a str,
+    ) -> ApiFuture<'a, models::Order> {
+        Box::pin(self.exchange.create_order_raw(pair, amount, rate))
+    }
+
+    fn update_order_by_id<'a>(
+        &'a self,
+        id: &'a str,
+        order: &'a models::UpdateOrder,
+    ) -> ApiFuture<'a, models::Order> {
+        Box::pin(self.exchange.update_order_by_id(id, order))
+    }
+
+    fn delete_order_by_id<'a>(&'a self, id: &'a str) -> ApiFuture<'a, models::Order> {
+        Box::pin(self.exchange.delete_order_by_id(id))
+    }
+
+    fn get_balance_summary(&self) -> ApiFuture<'_, models::Balance> {
+        Box::pin(self.profile.get_balance_summary())
+    }
+}
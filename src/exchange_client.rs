@@ -1,27 +1,38 @@
+use super::backend;
+use super::bulk;
+use super::call;
 use super::client_base;
+use super::clock::Clock;
 use super::coin;
 use super::endpoint;
 use super::error;
 use super::extractor;
 use super::models;
-use hyper;
 
-pub struct ExchangeClient<TConnector> {
-    base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+const ORDERS_PAGE_SIZE: u32 = 100;
+const MY_ORDERS_PAGE_SIZE: u32 = 100;
+
+pub struct ExchangeClient<TBackend> {
+    base: std::sync::Arc<client_base::ClientBase<TBackend>>,
     exchange: std::sync::Arc<endpoint::Exchange>,
 }
 
-impl<TConnector> ExchangeClient<TConnector>
+impl<TBackend> ExchangeClient<TBackend>
 where
-    TConnector: hyper::client::connect::Connect + Send + Sync + Clone + 'static,
+    TBackend: backend::HttpBackend,
 {
     pub fn new(
-        base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+        base: std::sync::Arc<client_base::ClientBase<TBackend>>,
         exchange: std::sync::Arc<endpoint::Exchange>,
-    ) -> ExchangeClient<TConnector> {
+    ) -> ExchangeClient<TBackend> {
         ExchangeClient { base, exchange }
     }
 
+    /// Current server time as estimated from the `Date` response headers.
+    pub fn server_time(&self) -> chrono::DateTime<chrono::Utc> {
+        self.base.transport.clock.now()
+    }
+
     pub async fn get_all_orders(
         &self,
         pair: coin::CoinPair,
@@ -35,10 +46,11 @@ where
                         .get_orders(pair.clone(), offset, limit, &access_token)
                         .expect("Failed to build /orders request!")
                 });
+        let call = call::Call::new("exchange.get_all_orders").pair(&pair);
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_orders(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_orders(body))
                     .await
             }
             Err(error) => Err(error),
@@ -64,11 +76,11 @@ where
                         )
                         .expect("Failed to build /orders request!")
                 });
+        let call = call::Call::new("exchange.create_order").pair(&pair);
         match request.await {
             Ok(request) => {
-                log::debug!("Create order request: {:#?}", request);
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_order(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_order(body))
                     .await
             }
             Err(error) => Err(error),
@@ -105,10 +117,11 @@ where
                         )
                         .expect("Failed to build /orders/my request!")
                 });
+        let call = call::Call::new("exchange.get_my_orders").maybe_pair(pair.as_ref());
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_orders(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_orders(body))
                     .await
             }
             Err(error) => Err(error),
@@ -128,10 +141,11 @@ where
                         .get_trades(order_id, offset, limit, &access_token)
                         .expect("Failed to build /trades request!")
                 });
+        let call = call::Call::new("exchange.get_trades");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_trades(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_trades(body))
                     .await
             }
             Err(error) => Err(error),
@@ -146,10 +160,11 @@ where
                         .get_trade_by_id(id, &access_token)
                         .expect("Failed to build /trades/id request!")
                 });
+        let call = call::Call::new("exchange.get_trade_by_id");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_trade(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_trade(body))
                     .await
             }
             Err(error) => Err(error),
@@ -164,10 +179,11 @@ where
                         .get_order_by_id(id, &access_token)
                         .expect("Failed to build /orders/id request!")
                 });
+        let call = call::Call::new("exchange.get_order_by_id").order_id(id);
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_order(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_order(body))
                     .await
             }
             Err(error) => Err(error),
@@ -186,10 +202,11 @@ where
                         .update_order_by_id(id, order.clone(), &access_token)
                         .expect("Failed to build /orders/id request!")
                 });
+        let call = call::Call::new("exchange.update_order_by_id").order_id(id);
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_order(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_order(body))
                     .await
             }
             Err(error) => Err(error),
@@ -207,10 +224,11 @@ where
                         .delete_order_by_id(id, &access_token)
                         .expect("Failed to build /orders/id request!")
                 });
+        let call = call::Call::new("exchange.delete_order_by_id").order_id(id);
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_order(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_order(body))
                     .await
             }
             Err(error) => Err(error),
@@ -228,10 +246,11 @@ where
                         .activate_order_by_id(id, &access_token)
                         .expect("Failed to build /orders/id/activate request!")
                 });
+        let call = call::Call::new("exchange.activate_order_by_id").order_id(id);
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_order(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_order(body))
                     .await
             }
             Err(error) => Err(error),
@@ -249,10 +268,11 @@ where
                         .deactivate_order_by_id(id, &access_token)
                         .expect("Failed to build /orders/id/activate request!")
                 });
+        let call = call::Call::new("exchange.deactivate_order_by_id").order_id(id);
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_order(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_order(body))
                     .await
             }
             Err(error) => Err(error),
@@ -271,16 +291,159 @@ where
                         .create_trade_for_order(id, trade, &access_token)
                         .expect("Failed to build /orders/id/trade request!")
                 });
+        let call = call::Call::new("exchange.create_trade_for_order").order_id(id);
         match request.await {
             Ok(request) => {
-                log::debug!("Create trade request: {:#?}", request);
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_trade(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_trade(body))
                     .await
             }
             Err(error) => Err(error),
         }
     }
+
+    /// Pages through `get_all_orders` and returns every order of `pair`.
+    pub async fn get_order_book(
+        &self,
+        pair: coin::CoinPair,
+    ) -> Result<models::Orders, error::Error> {
+        let mut orders = models::Orders::new();
+        loop {
+            let page = self
+                .get_all_orders(
+                    pair.clone(),
+                    Some(orders.len() as u32),
+                    Some(ORDERS_PAGE_SIZE),
+                )
+                .await?;
+            let is_last = page.len() < ORDERS_PAGE_SIZE as usize;
+            orders.extend(page);
+            if is_last {
+                return Ok(orders);
+            }
+        }
+    }
+
+    /// Pages through `get_my_orders` and returns every order of the account.
+    pub async fn get_all_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+    ) -> Result<models::Orders, error::Error> {
+        let mut orders = models::Orders::new();
+        loop {
+            let page = self
+                .get_my_orders(
+                    pair.clone(),
+                    None,
+                    Some(orders.len() as u32),
+                    Some(MY_ORDERS_PAGE_SIZE),
+                )
+                .await?;
+            let is_last = page.len() < MY_ORDERS_PAGE_SIZE as usize;
+            orders.extend(page);
+            if is_last {
+                return Ok(orders);
+            }
+        }
+    }
+
+    /// Deletes every open order, optionally only the ones of `pair`.
+    ///
+    /// Fails only when the orders cannot be listed, errors of the single
+    /// deletions end up in the report.
+    pub async fn cancel_all_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        concurrency: usize,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error> {
+        let ids = self
+            .my_order_ids(pair, |status| {
+                matches!(
+                    status,
+                    models::typed::OrderStatus::Active | models::typed::OrderStatus::Inactive
+                )
+            })
+            .await?;
+        Ok(bulk::run(ids, concurrency, |id| {
+            let id = id.to_string();
+            async move { self.delete_order_by_id(&id).await }
+        })
+        .await)
+    }
+
+    /// Deactivates every active order, optionally only the ones of `pair`.
+    pub async fn deactivate_all_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        concurrency: usize,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error> {
+        let ids = self
+            .my_order_ids(pair, |status| {
+                *status == models::typed::OrderStatus::Active
+            })
+            .await?;
+        Ok(bulk::run(ids, concurrency, |id| {
+            let id = id.to_string();
+            async move { self.deactivate_order_by_id(&id).await }
+        })
+        .await)
+    }
+
+    /// Activates every inactive order, optionally only the ones of `pair`.
+    pub async fn activate_all_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+        concurrency: usize,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error> {
+        let ids = self
+            .my_order_ids(pair, |status| {
+                *status == models::typed::OrderStatus::Inactive
+            })
+            .await?;
+        Ok(bulk::run(ids, concurrency, |id| {
+            let id = id.to_string();
+            async move { self.activate_order_by_id(&id).await }
+        })
+        .await)
+    }
+
+    /// Creates all the orders. Requests with an invalid pair fail with
+    /// `ValidationError` without being sent.
+    pub async fn create_orders(
+        &self,
+        orders: Vec<models::OrderRequest>,
+        concurrency: usize,
+    ) -> bulk::BulkReport<models::OrderRequest, models::Order> {
+        bulk::run(orders, concurrency, |order| {
+            let order = order.clone();
+            async move {
+                match coin::CoinPair::parse(&order.pair) {
+                    Some(pair) => {
+                        self.create_order_raw(pair, &order.amount, &order.rate)
+                            .await
+                    }
+                    None => Err(error::Error::ValidationError),
+                }
+            }
+        })
+        .await
+    }
+
+    async fn my_order_ids<F>(
+        &self,
+        pair: Option<coin::CoinPair>,
+        filter: F,
+    ) -> Result<Vec<u32>, error::Error>
+    where
+        F: Fn(&models::typed::OrderStatus) -> bool,
+    {
+        let orders = self.get_all_my_orders(pair).await?;
+        Ok(orders
+            .into_iter()
+            .filter(|order| filter(&models::typed::OrderStatus::from(order.status.as_str())))
+            .map(|order| order.id)
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -288,7 +451,7 @@ mod test {
     use super::*;
     use crate::test::*;
 
-    fn create_exchange_client(test_case: &TestCase) -> ExchangeClient<Connector> {
+    fn create_exchange_client(test_case: &TestCase) -> ExchangeClient<Backend> {
         ExchangeClient::new(
             test_case.client_base.clone(),
             std::sync::Arc::new(crate::endpoint::Exchange::new(
@@ -361,4 +524,53 @@ mod test {
         access_token_mock.assert();
         create_order_mock.assert();
     }
+
+    #[test]
+    fn cancel_all_orders() {
+        let case = TestCase::new();
+        let access_token_mock = case.mock_access_token();
+        let order = |id: u32, status: &str| crate::models::Order {
+            id,
+            status: status.to_owned(),
+            ..crate::models::Order::default()
+        };
+        let my_orders = vec![
+            order(1, "ACTIVE"),
+            order(2, "COMPLETED"),
+            order(3, "INACTIVE"),
+        ];
+        let my_orders = serde_json::to_string(&my_orders).expect(SERDE_ERROR);
+        let my_orders_mock = case.server.mock(|when, then| {
+            default_get_when(when)
+                .path("/exchange/orders/my")
+                .query_param("pair", "test/test")
+                .query_param("offset", "0");
+            default_then_content_type(then)
+                .status(200)
+                .body(my_orders.clone());
+        });
+        let deleted_order = serde_json::to_string(&order(1, "CANCELED")).expect(SERDE_ERROR);
+        let delete_mock = case.server.mock(|when, then| {
+            when.method(httpmock::Method::DELETE).path("/exchange/orders/1");
+            default_then_content_type(then)
+                .status(200)
+                .body(deleted_order.clone());
+        });
+        let missing_mock = case.server.mock(|when, then| {
+            when.method(httpmock::Method::DELETE).path("/exchange/orders/3");
+            then.status(404);
+        });
+        let client = create_exchange_client(&case);
+        let report = tokio_test::block_on(
+            client.cancel_all_orders(Some(create_test_pair()), 2)).unwrap();
+        let succeeded: Vec<u32> = report.succeeded().map(|(id, _)| *id).collect();
+        assert_eq!(succeeded, vec![1]);
+        let failed: Vec<u32> = report.failed().map(|(id, _)| *id).collect();
+        assert_eq!(failed, vec![3]);
+        // The test token is expired, so every request asks for a new one.
+        access_token_mock.assert_hits(3);
+        my_orders_mock.assert();
+        delete_mock.assert();
+        missing_mock.assert();
+    }
 }
@@ -0,0 +1,589 @@
+//! Exports of the trade and invoice history for accounting.
+//!
+//! Rows are written page by page while the history is fetched, either as CSV
+//! with a header or as JSON Lines. Amounts are rounded to the decimals of
+//! their coin as reported by `/coins`.
+use super::backend;
+use super::chatex_client;
+use super::coin_client;
+use super::error;
+use super::exchange_client;
+use super::invoice_client;
+use super::models;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+const PAGE_SIZE: u32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Api(error::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Api(error) => {
+                write!(formatter, "Failed to fetch history: {}", error)
+            }
+            ExportError::Io(error) => {
+                write!(formatter, "Failed to write export: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Api(_) => None,
+            ExportError::Io(error) => Some(error),
+        }
+    }
+}
+
+impl From<error::Error> for ExportError {
+    fn from(error: error::Error) -> ExportError {
+        ExportError::Api(error)
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(error: std::io::Error) -> ExportError {
+        ExportError::Io(error)
+    }
+}
+
+/// Number of decimals of every coin, by coin name.
+#[derive(Clone, Debug, Default)]
+pub struct Decimals {
+    coins: std::collections::HashMap<String, u32>,
+}
+
+impl Decimals {
+    pub fn new(coins: &[models::Coin]) -> Decimals {
+        Decimals {
+            coins: coins
+                .iter()
+                .map(|coin| (coin.name.clone(), coin.decimals))
+                .collect(),
+        }
+    }
+
+    /// Decimals of the coin. `/coins` may list a token without the network
+    /// suffix used in pairs, e.g. `usdt` for `usdt_erc20`.
+    pub fn get(&self, coin: &str) -> Option<u32> {
+        self.coins.get(coin).copied().or_else(|| {
+            let (name, _network) = coin.split_once('_')?;
+            self.coins.get(name).copied()
+        })
+    }
+
+    /// Rounds the amount to the decimals of the coin. Unknown coins and
+    /// unparsable amounts are kept as they are.
+    pub fn format(&self, coin: &str, amount: &str) -> String {
+        match (self.get(coin), Decimal::from_str(amount)) {
+            (Some(decimals), Ok(amount)) => {
+                format!("{:.*}", decimals as usize, amount.round_dp(decimals))
+            }
+            _ => amount.to_owned(),
+        }
+    }
+}
+
+/// Timestamps of the API as RFC 3339 in UTC.
+fn normalize_time(value: &str) -> String {
+    match chrono::DateTime::parse_from_rfc3339(value) {
+        Ok(time) => time
+            .with_timezone(&chrono::Utc)
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        Err(_) => value.to_owned(),
+    }
+}
+
+fn in_range(
+    value: &str,
+    from: &chrono::DateTime<chrono::Utc>,
+    to: &chrono::DateTime<chrono::Utc>,
+) -> bool {
+    match chrono::DateTime::parse_from_rfc3339(value) {
+        Ok(time) => *from <= time && time < *to,
+        Err(_) => false,
+    }
+}
+
+/// Whether a page ordered newest first has gone past `from`, so the
+/// following pages are all older.
+fn is_past(created_at: &[&str], from: &chrono::DateTime<chrono::Utc>) -> bool {
+    created_at.iter().any(|value| {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .map(|time| time < *from)
+            .unwrap_or(false)
+    })
+}
+
+/// A trade with normalized columns.
+///
+/// `side` is `sell` when the order belongs to the account, `buy` when the
+/// account traded against somebody else's order and empty when the API did
+/// not tell. `amount` is in the paid coin,
+/// `fee` and `received_amount` in the received one, see
+/// `models::typed::TypedTrade`. The rate is in the right coin of the pair.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeRecord {
+    pub trade_id: u32,
+    pub order_id: u32,
+    pub pair: String,
+    pub side: Option<models::typed::Side>,
+    pub amount: String,
+    pub rate: String,
+    pub fee: String,
+    pub received_amount: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl TradeRecord {
+    pub const COLUMNS: [&'static str; 10] = [
+        "trade_id",
+        "order_id",
+        "pair",
+        "side",
+        "amount",
+        "rate",
+        "fee",
+        "received_amount",
+        "created_at",
+        "updated_at",
+    ];
+
+    pub fn new(trade: &models::Trade, decimals: &Decimals) -> TradeRecord {
+        let (left, right) = trade
+            .order
+            .pair
+            .split_once('/')
+            .unwrap_or((&trade.order.pair, ""));
+        let side = models::typed::Side::for_owner(trade.order.is_owner);
+        let (paid, received) = match side {
+            Some(models::typed::Side::Buy) => (right, left),
+            Some(models::typed::Side::Sell) => (left, right),
+            None => ("", ""),
+        };
+        TradeRecord {
+            trade_id: trade.id,
+            order_id: trade.order.id,
+            pair: trade.order.pair.clone(),
+            side,
+            amount: decimals.format(paid, &trade.amount),
+            rate: decimals.format(right, &trade.order.rate),
+            fee: decimals.format(received, &trade.fee),
+            received_amount: decimals.format(received, &trade.received_amount),
+            created_at: normalize_time(&trade.created_at),
+            updated_at: normalize_time(&trade.updated_at),
+        }
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.trade_id.to_string(),
+            self.order_id.to_string(),
+            self.pair.clone(),
+            self.side.map(|side| side.to_string()).unwrap_or_default(),
+            self.amount.clone(),
+            self.rate.clone(),
+            self.fee.clone(),
+            self.received_amount.clone(),
+            self.created_at.clone(),
+            self.updated_at.clone(),
+        ]
+    }
+}
+
+/// An invoice with normalized columns.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct InvoiceRecord {
+    pub invoice_id: String,
+    pub status: String,
+    pub coin: String,
+    pub amount: String,
+    pub fiat: String,
+    pub payment_system_id: models::PaymentSystemId,
+    pub created_at: String,
+}
+
+impl InvoiceRecord {
+    pub const COLUMNS: [&'static str; 7] = [
+        "invoice_id",
+        "status",
+        "coin",
+        "amount",
+        "fiat",
+        "payment_system_id",
+        "created_at",
+    ];
+
+    pub fn new(invoice: &models::Invoice, decimals: &Decimals) -> InvoiceRecord {
+        InvoiceRecord {
+            invoice_id: invoice.id.clone(),
+            status: invoice.status.clone(),
+            coin: invoice.coin.clone(),
+            amount: decimals.format(&invoice.coin, &invoice.amount.to_string()),
+            fiat: invoice.fiat.clone(),
+            payment_system_id: invoice.payment_system_id,
+            created_at: normalize_time(&invoice.created_at),
+        }
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.invoice_id.clone(),
+            self.status.clone(),
+            self.coin.clone(),
+            self.amount.clone(),
+            self.fiat.clone(),
+            self.payment_system_id.to_string(),
+            self.created_at.clone(),
+        ]
+    }
+}
+
+fn write_csv_row<W, TField>(writer: &mut W, fields: &[TField]) -> std::io::Result<()>
+where
+    W: std::io::Write,
+    TField: AsRef<str>,
+{
+    let row = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    writer.write_all(row.as_bytes())?;
+    writer.write_all(b"\r\n")
+}
+
+fn write_json_line<W, T>(writer: &mut W, record: &T) -> std::io::Result<()>
+where
+    W: std::io::Write,
+    T: serde::Serialize,
+{
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")
+}
+
+pub struct Exporter<TBackend> {
+    coin: coin_client::CoinClient<TBackend>,
+    exchange: exchange_client::ExchangeClient<TBackend>,
+    invoice: invoice_client::InvoiceClient<TBackend>,
+}
+
+impl<TBackend> Exporter<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new(
+        coin: coin_client::CoinClient<TBackend>,
+        exchange: exchange_client::ExchangeClient<TBackend>,
+        invoice: invoice_client::InvoiceClient<TBackend>,
+    ) -> Exporter<TBackend> {
+        Exporter {
+            coin,
+            exchange,
+            invoice,
+        }
+    }
+
+    pub fn from_client(
+        client: &chatex_client::ChatexClient<TBackend>,
+    ) -> Exporter<TBackend> {
+        Exporter::new(client.coin(), client.exchange(), client.invoice())
+    }
+
+    pub async fn decimals(&self) -> Result<Decimals, error::Error> {
+        Ok(Decimals::new(&self.coin.get_available_coins().await?))
+    }
+
+    /// Writes the trades created in `[from, to)` and returns their number.
+    ///
+    /// Trades come newest first, so paging stops at the first page reaching
+    /// past `from`.
+    pub async fn export_trades<W: std::io::Write>(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<usize, ExportError> {
+        let decimals = self.decimals().await?;
+        if format == Format::Csv {
+            write_csv_row(writer, &TradeRecord::COLUMNS)?;
+        }
+        let mut offset = 0;
+        let mut exported = 0;
+        loop {
+            let page = self
+                .exchange
+                .get_trades(None, Some(offset), Some(PAGE_SIZE))
+                .await?;
+            offset += page.len() as u32;
+            for trade in page.iter() {
+                if !in_range(&trade.created_at, &from, &to) {
+                    continue;
+                }
+                let record = TradeRecord::new(trade, &decimals);
+                match format {
+                    Format::Csv => write_csv_row(writer, &record.fields())?,
+                    Format::JsonLines => write_json_line(writer, &record)?,
+                }
+                exported += 1;
+            }
+            let created_at: Vec<&str> =
+                page.iter().map(|trade| trade.created_at.as_str()).collect();
+            if page.len() < PAGE_SIZE as usize || is_past(&created_at, &from) {
+                writer.flush()?;
+                return Ok(exported);
+            }
+        }
+    }
+
+    /// Writes the invoices created in `[from, to)` and returns their number.
+    ///
+    /// Like `export_trades`, paging stops at the first page reaching past
+    /// `from` in case the API ignores the date filter.
+    pub async fn export_invoices<W: std::io::Write>(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<usize, ExportError> {
+        let decimals = self.decimals().await?;
+        if format == Format::Csv {
+            write_csv_row(writer, &InvoiceRecord::COLUMNS)?;
+        }
+        let mut offset = 0;
+        let mut exported = 0;
+        loop {
+            let page = self
+                .invoice
+                .get_invoices(
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(offset),
+                    Some(PAGE_SIZE),
+                    Some(from),
+                    Some(to),
+                )
+                .await?;
+            offset += page.len() as u32;
+            for invoice in page.iter() {
+                if !in_range(&invoice.created_at, &from, &to) {
+                    continue;
+                }
+                let record = InvoiceRecord::new(invoice, &decimals);
+                match format {
+                    Format::Csv => write_csv_row(writer, &record.fields())?,
+                    Format::JsonLines => write_json_line(writer, &record)?,
+                }
+                exported += 1;
+            }
+            let created_at: Vec<&str> = page
+                .iter()
+                .map(|invoice| invoice.created_at.as_str())
+                .collect();
+            if page.len() < PAGE_SIZE as usize || is_past(&created_at, &from) {
+                writer.flush()?;
+                return Ok(exported);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    fn trade(id: u32, created_at: &str, is_owner: bool) -> models::Trade {
+        models::Trade {
+            amount: "0.123456789".to_owned(),
+            created_at: created_at.to_owned(),
+            fee: "0.0001".to_owned(),
+            id,
+            order: models::Order {
+                id: 7,
+                is_owner: Some(is_owner),
+                pair: "btc/usdt_erc20".to_owned(),
+                rate: "50000.123".to_owned(),
+                ..models::Order::default()
+            },
+            received_amount: "0.123356789".to_owned(),
+            updated_at: "2021-03-01T10:00:00+02:00".to_owned(),
+        }
+    }
+
+    #[test]
+    fn export_trades() {
+        let case = TestCase::new();
+        let access_token_mock = case.mock_access_token();
+        let coins = vec![
+            models::Coin {
+                decimals: 8,
+                full_name: "Bitcoin".to_owned(),
+                name: "btc".to_owned(),
+            },
+            models::Coin {
+                decimals: 6,
+                full_name: "USDT ERC20".to_owned(),
+                name: "usdt".to_owned(),
+            },
+        ];
+        let coins = serde_json::to_string(&coins).expect(SERDE_ERROR);
+        let coins_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/coins");
+            default_then_content_type(then)
+                .status(200)
+                .body(coins.clone());
+        });
+        let trades = vec![
+            trade(1, "2021-03-01T10:00:00+02:00", true),
+            trade(2, "2021-02-28T23:59:59Z", false),
+            trade(3, "2021-03-31T23:59:59Z", false),
+        ];
+        let trades = serde_json::to_string(&trades).expect(SERDE_ERROR);
+        let trades_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/exchange/orders/trades");
+            default_then_content_type(then)
+                .status(200)
+                .body(trades.clone());
+        });
+        let exporter = Exporter::new(
+            crate::CoinClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Coin::new(&case.base_context)),
+            ),
+            crate::ExchangeClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Exchange::new(&case.base_context)),
+            ),
+            crate::InvoiceClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Invoice::new(&case.base_context)),
+            ),
+        );
+        let month = |month: u32| {
+            chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2021, month, 1, 0, 0, 0)
+                .unwrap()
+        };
+        let mut csv = Vec::new();
+        let exported = tokio_test::block_on(exporter.export_trades(
+            month(3),
+            month(4),
+            Format::Csv,
+            &mut csv,
+        ))
+        .unwrap();
+        assert_eq!(exported, 2);
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "trade_id,order_id,pair,side,amount,rate,fee,received_amount,created_at,updated_at\r\n\
+             1,7,btc/usdt_erc20,sell,0.12345679,50000.123000,0.000100,0.123357,\
+             2021-03-01T08:00:00Z,2021-03-01T08:00:00Z\r\n\
+             3,7,btc/usdt_erc20,buy,0.123457,50000.123000,0.00010000,0.12335679,\
+             2021-03-31T23:59:59Z,2021-03-01T08:00:00Z\r\n"
+        );
+        let mut lines = Vec::new();
+        tokio_test::block_on(exporter.export_trades(
+            month(3),
+            month(4),
+            Format::JsonLines,
+            &mut lines,
+        ))
+        .unwrap();
+        let lines = String::from_utf8(lines).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(first["side"], "sell");
+        assert_eq!(first["amount"], "0.12345679");
+        assert_eq!(lines.lines().count(), 2);
+        let mut unknown = trade(4, "2021-03-02T10:00:00Z", true);
+        unknown.order.is_owner = None;
+        let record = TradeRecord::new(&unknown, &Decimals::new(&[]));
+        assert_eq!(record.side, None);
+        assert_eq!(record.fields()[3], "");
+        access_token_mock.assert_hits(4);
+        coins_mock.assert_hits(2);
+        trades_mock.assert_hits(2);
+    }
+
+    #[test]
+    fn export_trades_stops_paging_past_range() {
+        let case = TestCase::new();
+        let _access_token_mock = case.mock_access_token();
+        let coins_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/coins");
+            default_then_content_type(then).status(200).body("[]");
+        });
+        let mut trades: Vec<models::Trade> = (0..PAGE_SIZE - 1)
+            .map(|id| trade(id, "2021-03-02T10:00:00Z", true))
+            .collect();
+        trades.push(trade(PAGE_SIZE, "2021-02-28T10:00:00Z", true));
+        let trades = serde_json::to_string(&trades).expect(SERDE_ERROR);
+        let first_page_mock = case.server.mock(|when, then| {
+            default_get_when(when)
+                .path("/exchange/orders/trades")
+                .query_param("offset", "0");
+            default_then_content_type(then).status(200).body(trades);
+        });
+        let second_page_mock = case.server.mock(|when, then| {
+            default_get_when(when)
+                .path("/exchange/orders/trades")
+                .query_param("offset", &PAGE_SIZE.to_string());
+            default_then_content_type(then).status(200).body("[]");
+        });
+        let exporter = Exporter::new(
+            crate::CoinClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Coin::new(&case.base_context)),
+            ),
+            crate::ExchangeClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Exchange::new(&case.base_context)),
+            ),
+            crate::InvoiceClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Invoice::new(&case.base_context)),
+            ),
+        );
+        let from = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2021, 3, 1, 0, 0, 0)
+            .unwrap();
+        let exported = tokio_test::block_on(exporter.export_trades(
+            from,
+            from + chrono::Duration::days(31),
+            Format::JsonLines,
+            &mut Vec::new(),
+        ))
+        .unwrap();
+        assert_eq!(exported, PAGE_SIZE as usize - 1);
+        coins_mock.assert();
+        first_page_mock.assert();
+        second_page_mock.assert_hits(0);
+    }
+}
@@ -0,0 +1,490 @@
+//! Order and trade creation which is safe to repeat after an ambiguous failure.
+//!
+//! A timed out `POST` may still have created the order. Every creation is
+//! tagged with a client-generated `IdempotencyKey` and recorded in an
+//! `IdempotencyStore`. When the outcome of a request is unknown, the account
+//! orders or trades are scanned for a match before the request is repeated.
+use super::backend;
+use super::coin;
+use super::error;
+use super::exchange_client;
+use super::models;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+const TRADES_PAGE_SIZE: u32 = 100;
+/// Attempts to create a value, each but the first preceded by a reconciliation.
+const MAX_ATTEMPTS: usize = 2;
+
+/// Client-generated identifier of a single creation.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    /// Unique key made of the current time, the process id and a counter.
+    pub fn generate() -> IdempotencyKey {
+        static COUNTER: std::sync::atomic::AtomicU64 =
+            std::sync::atomic::AtomicU64::new(0);
+        let counter = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        IdempotencyKey(format!(
+            "{:x}-{:x}-{:x}",
+            nanos,
+            std::process::id(),
+            counter
+        ))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for IdempotencyKey {
+    fn from(key: String) -> IdempotencyKey {
+        IdempotencyKey(key)
+    }
+}
+
+impl From<&str> for IdempotencyKey {
+    fn from(key: &str) -> IdempotencyKey {
+        IdempotencyKey(key.to_owned())
+    }
+}
+
+impl std::fmt::Display for IdempotencyKey {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+/// What a key was used to create.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub enum Creation {
+    Order {
+        pair: String,
+        amount: String,
+        rate: String,
+    },
+    Trade {
+        order_id: String,
+        amount: String,
+        rate: String,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct CreationRecord {
+    pub creation: Creation,
+    /// Server time when the first attempt was made.
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Id of the created order or trade once it is known.
+    pub created_id: Option<u32>,
+}
+
+impl CreationRecord {
+    fn is_order(&self) -> bool {
+        matches!(self.creation, Creation::Order { .. })
+    }
+}
+
+/// Keeps creation records between calls and, for persistent stores, between
+/// processes.
+pub trait IdempotencyStore: Send + Sync {
+    fn load(&self, key: &IdempotencyKey) -> Option<CreationRecord>;
+
+    fn store(
+        &self,
+        key: &IdempotencyKey,
+        record: &CreationRecord,
+    ) -> Result<(), std::io::Error>;
+
+    fn records(&self) -> Vec<(IdempotencyKey, CreationRecord)>;
+}
+
+#[derive(Default)]
+pub struct MemoryIdempotencyStore {
+    records: std::sync::Mutex<std::collections::HashMap<IdempotencyKey, CreationRecord>>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> MemoryIdempotencyStore {
+        Default::default()
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    fn load(&self, key: &IdempotencyKey) -> Option<CreationRecord> {
+        self.records.lock().unwrap().get(key).cloned()
+    }
+
+    fn store(
+        &self,
+        key: &IdempotencyKey,
+        record: &CreationRecord,
+    ) -> Result<(), std::io::Error> {
+        self.records
+            .lock()
+            .unwrap()
+            .insert(key.clone(), record.clone());
+        Ok(())
+    }
+
+    fn records(&self) -> Vec<(IdempotencyKey, CreationRecord)> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, record)| (key.clone(), record.clone()))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum Outcome<T> {
+    /// Created by this call.
+    Created(T),
+    /// Created earlier with the same key, possibly by a request which seemed
+    /// to fail.
+    AlreadyExisted(T),
+    Failed(error::Error),
+}
+
+impl<T> Outcome<T> {
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Outcome::Created(value) | Outcome::AlreadyExisted(value) => Some(value),
+            Outcome::Failed(_) => None,
+        }
+    }
+}
+
+/// Errors after which the request may or may not have been processed.
+fn is_ambiguous(error: &error::Error) -> bool {
+    matches!(error, error::Error::InternalServerError)
+}
+
+fn decimal_eq(left: &str, right: &str) -> bool {
+    match (Decimal::from_str(left), Decimal::from_str(right)) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+/// Wraps `ExchangeClient` creations with idempotency keys.
+pub struct IdempotentExchange<TBackend> {
+    exchange: exchange_client::ExchangeClient<TBackend>,
+    store: std::sync::Arc<dyn IdempotencyStore>,
+    window: chrono::Duration,
+}
+
+impl<TBackend> IdempotentExchange<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new(
+        exchange: exchange_client::ExchangeClient<TBackend>,
+        store: std::sync::Arc<dyn IdempotencyStore>,
+    ) -> IdempotentExchange<TBackend> {
+        IdempotentExchange {
+            exchange,
+            store,
+            window: chrono::Duration::minutes(1),
+        }
+    }
+
+    /// Tolerance around the creation time when looking for a match, 1 minute
+    /// by default.
+    pub fn with_window(
+        mut self,
+        window: chrono::Duration,
+    ) -> IdempotentExchange<TBackend> {
+        self.window = window;
+        self
+    }
+
+    pub fn exchange(&self) -> &exchange_client::ExchangeClient<TBackend> {
+        &self.exchange
+    }
+
+    pub async fn create_order(
+        &self,
+        key: &IdempotencyKey,
+        pair: coin::CoinPair,
+        amount: &str,
+        rate: &str,
+    ) -> Outcome<models::Order> {
+        let creation = Creation::Order {
+            pair: String::from(&pair),
+            amount: amount.to_owned(),
+            rate: rate.to_owned(),
+        };
+        let pair = &pair;
+        self.create(
+            key,
+            creation,
+            |id| async move { self.exchange.get_order_by_id(&id.to_string()).await },
+            |record| async move { self.find_order(key, &record, pair, amount, rate).await },
+            || self.exchange.create_order_raw(pair.clone(), amount, rate),
+            |order| order.id,
+        )
+        .await
+    }
+
+    pub async fn create_trade_for_order(
+        &self,
+        key: &IdempotencyKey,
+        order_id: &str,
+        trade: &models::CreateTradeRequest,
+    ) -> Outcome<models::Trade> {
+        let creation = Creation::Trade {
+            order_id: order_id.to_owned(),
+            amount: trade.amount.clone(),
+            rate: trade.rate.clone(),
+        };
+        self.create(
+            key,
+            creation,
+            |id| async move { self.exchange.get_trade_by_id(&id.to_string()).await },
+            |record| async move { self.find_trade(key, &record, order_id, trade).await },
+            || self.exchange.create_trade_for_order(order_id, trade),
+            |trade| trade.id,
+        )
+        .await
+    }
+
+    /// Creates the value unless the key is known, reconciling after every
+    /// ambiguous failure and once more for a key left pending earlier.
+    async fn create<'a, T, Get, GetFuture, Find, FindFuture, Make, MakeFuture>(
+        &'a self,
+        key: &'a IdempotencyKey,
+        creation: Creation,
+        get: Get,
+        find: Find,
+        make: Make,
+        id_of: fn(&T) -> u32,
+    ) -> Outcome<T>
+    where
+        Get: Fn(u32) -> GetFuture,
+        GetFuture: futures::Future<Output = Result<T, error::Error>>,
+        Find: Fn(CreationRecord) -> FindFuture,
+        FindFuture: futures::Future<Output = Result<Option<T>, error::Error>>,
+        Make: Fn() -> MakeFuture,
+        MakeFuture: futures::Future<Output = Result<T, error::Error>>,
+    {
+        let (record, existed) = match self.begin(key, creation) {
+            Ok(begun) => begun,
+            Err(error) => return Outcome::Failed(error),
+        };
+        if let Some(id) = record.created_id {
+            return match get(id).await {
+                Ok(value) => Outcome::AlreadyExisted(value),
+                Err(error) => Outcome::Failed(error),
+            };
+        }
+        let mut reconcile = existed;
+        for _ in 0..MAX_ATTEMPTS {
+            if reconcile {
+                match find(record.clone()).await {
+                    Ok(Some(value)) => {
+                        self.finish(key, &record, id_of(&value));
+                        return Outcome::AlreadyExisted(value);
+                    }
+                    Ok(None) => (),
+                    Err(error) => return Outcome::Failed(error),
+                }
+            }
+            match make().await {
+                Ok(value) => {
+                    self.finish(key, &record, id_of(&value));
+                    return Outcome::Created(value);
+                }
+                Err(error) if is_ambiguous(&error) => {
+                    log::warn!("{} may have been created: {}", key, error);
+                    reconcile = true;
+                }
+                Err(error) => return Outcome::Failed(error),
+            }
+        }
+        Outcome::Failed(error::Error::InternalServerError)
+    }
+
+    /// Returns the stored record of the key, or stores a new one, and whether
+    /// the key was known.
+    ///
+    /// A key reused for a different creation is a `ValidationError`.
+    fn begin(
+        &self,
+        key: &IdempotencyKey,
+        creation: Creation,
+    ) -> Result<(CreationRecord, bool), error::Error> {
+        if let Some(record) = self.store.load(key) {
+            return if record.creation == creation {
+                Ok((record, true))
+            } else {
+                log::error!("Idempotency key {} was used for {:?}", key, record.creation);
+                Err(error::Error::ValidationError)
+            };
+        }
+        let record = CreationRecord {
+            creation,
+            started_at: self.exchange.server_time(),
+            created_id: None,
+        };
+        self.save(key, &record);
+        Ok((record, false))
+    }
+
+    fn finish(&self, key: &IdempotencyKey, record: &CreationRecord, id: u32) {
+        let record = CreationRecord {
+            created_id: Some(id),
+            ..record.clone()
+        };
+        self.save(key, &record);
+    }
+
+    fn save(&self, key: &IdempotencyKey, record: &CreationRecord) {
+        if let Err(error) = self.store.store(key, record) {
+            log::warn!("Failed to store idempotency key {}: {}", key, error);
+        }
+    }
+
+    /// Ids already taken by other keys, so one order never matches two keys.
+    fn claimed_ids(&self, key: &IdempotencyKey, orders: bool) -> Vec<u32> {
+        self.store
+            .records()
+            .into_iter()
+            .filter(|(other, record)| other != key && record.is_order() == orders)
+            .filter_map(|(_, record)| record.created_id)
+            .collect()
+    }
+
+    fn in_window(&self, record: &CreationRecord, created_at: &str) -> bool {
+        match chrono::DateTime::parse_from_rfc3339(created_at) {
+            Ok(created_at) => {
+                let created_at = created_at.with_timezone(&chrono::Utc);
+                created_at >= record.started_at - self.window
+                    && created_at <= self.exchange.server_time() + self.window
+            }
+            Err(_) => false,
+        }
+    }
+
+    async fn find_order(
+        &self,
+        key: &IdempotencyKey,
+        record: &CreationRecord,
+        pair: &coin::CoinPair,
+        amount: &str,
+        rate: &str,
+    ) -> Result<Option<models::Order>, error::Error> {
+        let claimed = self.claimed_ids(key, true);
+        let orders = self.exchange.get_all_my_orders(Some(pair.clone())).await?;
+        Ok(orders.into_iter().find(|order| {
+            !claimed.contains(&order.id)
+                && coin::CoinPair::parse(&order.pair).as_ref() == Some(pair)
+                && decimal_eq(
+                    order.initial_amount.as_ref().unwrap_or(&order.amount),
+                    amount,
+                )
+                && decimal_eq(&order.rate, rate)
+                && self.in_window(record, &order.created_at)
+        }))
+    }
+
+    async fn find_trade(
+        &self,
+        key: &IdempotencyKey,
+        record: &CreationRecord,
+        order_id: &str,
+        trade: &models::CreateTradeRequest,
+    ) -> Result<Option<models::Trade>, error::Error> {
+        let claimed = self.claimed_ids(key, false);
+        let mut offset = 0;
+        loop {
+            let page = self
+                .exchange
+                .get_trades(order_id.parse().ok(), Some(offset), Some(TRADES_PAGE_SIZE))
+                .await?;
+            let is_last = page.len() < TRADES_PAGE_SIZE as usize;
+            offset += page.len() as u32;
+            let found = page.into_iter().find(|candidate| {
+                !claimed.contains(&candidate.id)
+                    && candidate.order.id.to_string() == order_id
+                    && decimal_eq(&candidate.amount, &trade.amount)
+                    && decimal_eq(&candidate.order.rate, &trade.rate)
+                    && self.in_window(record, &candidate.created_at)
+            });
+            if found.is_some() || is_last {
+                return Ok(found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn reconciles_ambiguous_order_creation() {
+        let case = TestCase::new();
+        let access_token_mock = case.mock_access_token();
+        let order = models::Order {
+            id: 42,
+            initial_amount: Some("37.0".to_owned()),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            ..models::Order::default()
+        };
+        let order = serde_json::to_string(&order).expect(SERDE_ERROR);
+        let create_order_mock = case.server.mock(|when, then| {
+            default_post_when(when).path("/exchange/orders");
+            then.status(502);
+        });
+        let my_orders_mock = case.server.mock(|when, then| {
+            default_get_when(when)
+                .path("/exchange/orders/my")
+                .query_param("pair", "test/test");
+            default_then_content_type(then)
+                .status(200)
+                .body(format!("[{}]", order));
+        });
+        let order_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/exchange/orders/42");
+            default_then_content_type(then)
+                .status(200)
+                .body(order.clone());
+        });
+        let exchange = IdempotentExchange::new(
+            crate::ExchangeClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Exchange::new(&case.base_context)),
+            ),
+            std::sync::Arc::new(MemoryIdempotencyStore::new()),
+        );
+        let pair = coin::CoinPair::new("test".into(), "test".into());
+        let key = IdempotencyKey::generate();
+        let create = |key: &IdempotencyKey, amount: &'static str| {
+            tokio_test::block_on(exchange.create_order(key, pair.clone(), amount, "13"))
+        };
+        match create(&key, "37") {
+            Outcome::AlreadyExisted(order) => assert_eq!(order.id, 42),
+            outcome => panic!("Unexpected outcome {:?}", outcome),
+        }
+        create_order_mock.assert();
+        my_orders_mock.assert();
+        match create(&key, "37") {
+            Outcome::AlreadyExisted(order) => assert_eq!(order.id, 42),
+            outcome => panic!("Unexpected outcome {:?}", outcome),
+        }
+        order_mock.assert();
+        assert!(matches!(
+            create(&key, "38"),
+            Outcome::Failed(error::Error::ValidationError)
+        ));
+        create_order_mock.assert();
+        access_token_mock.assert_hits(3);
+    }
+}
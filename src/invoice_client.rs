@@ -1,22 +1,21 @@
-use super::{client_base, coin, endpoint, error, extractor, models};
+use super::{backend, call, client_base, coin, endpoint, error, extractor, models};
 use chrono;
-use hyper;
 use iso_currency;
 use isolanguage_1;
 
-pub struct InvoiceClient<TConnector> {
-    base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+pub struct InvoiceClient<TBackend> {
+    base: std::sync::Arc<client_base::ClientBase<TBackend>>,
     invoice: std::sync::Arc<endpoint::Invoice>,
 }
 
-impl<TConnector> InvoiceClient<TConnector>
+impl<TBackend> InvoiceClient<TBackend>
 where
-    TConnector: hyper::client::connect::Connect + Send + Sync + Clone + 'static,
+    TBackend: backend::HttpBackend,
 {
     pub fn new(
-        base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+        base: std::sync::Arc<client_base::ClientBase<TBackend>>,
         invoice: std::sync::Arc<endpoint::Invoice>,
-    ) -> InvoiceClient<TConnector> {
+    ) -> InvoiceClient<TBackend> {
         InvoiceClient { base, invoice }
     }
 
@@ -52,10 +51,11 @@ where
                     )
                     .expect("Failed to build /invoices request!")
             });
+        let call = call::Call::new("invoice.get_invoices");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_invoices(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_invoices(body))
                     .await
             }
             Err(error) => Err(error),
@@ -73,10 +73,11 @@ where
                     .create_invoice(create_invoice.clone(), &access_token)
                     .expect("Failed to build /invoices request!")
             });
+        let call = call::Call::new("invoice.create_invoice");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_invoices(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_invoices(body))
                     .await
             }
             Err(error) => Err(error),
@@ -94,10 +95,11 @@ where
                     .get_invoice_by_id(id.into(), &access_token)
                     .expect("Failed to build /invoices/id request!")
             });
+        let call = call::Call::new("invoice.get_invoice_by_id");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_invoice(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_invoice(body))
                     .await
             }
             Err(error) => Err(error),
@@ -0,0 +1,448 @@
+//! Durable record of the orders, trades and invoices seen by the SDK.
+//!
+//! Every mutating call made through `ExchangeClient` and `InvoiceClient` is
+//! stored with its request, response or error, and every `Order`, `Trade` and
+//! `Invoice` found in a response is stored as a snapshot. Everything lives in
+//! an embedded SQLite database.
+use super::call;
+use super::coin;
+use super::models;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS calls (
+    id INTEGER PRIMARY KEY,
+    recorded_at TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    pair TEXT,
+    order_id TEXT,
+    request TEXT,
+    response TEXT,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS orders (
+    row_id INTEGER PRIMARY KEY,
+    id INTEGER NOT NULL,
+    pair TEXT NOT NULL,
+    status TEXT NOT NULL,
+    seen_at TEXT NOT NULL,
+    snapshot TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS orders_id ON orders (id);
+CREATE TABLE IF NOT EXISTS trades (
+    row_id INTEGER PRIMARY KEY,
+    id INTEGER NOT NULL,
+    order_id INTEGER NOT NULL,
+    pair TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    seen_at TEXT NOT NULL,
+    snapshot TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS trades_pair_created_at ON trades (pair, created_at);
+CREATE TABLE IF NOT EXISTS invoices (
+    row_id INTEGER PRIMARY KEY,
+    id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    seen_at TEXT NOT NULL,
+    snapshot TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS invoices_id ON invoices (id);
+";
+
+/// A mutating call as stored in the journal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallEntry {
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub endpoint: String,
+    pub method: String,
+    pub path: String,
+    pub pair: Option<String>,
+    pub order_id: Option<String>,
+    pub request: Option<String>,
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OrderSnapshot {
+    pub seen_at: chrono::DateTime<chrono::Utc>,
+    pub order: models::Order,
+}
+
+pub struct Journal {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+}
+
+fn format_time(time: &chrono::DateTime<chrono::Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+fn parse_time(
+    column: usize,
+    value: &str,
+) -> rusqlite::Result<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&chrono::Utc))
+        .map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(
+                column,
+                rusqlite::types::Type::Text,
+                Box::new(error),
+            )
+        })
+}
+
+/// Timestamps of the API in the stored form, so they compare as text.
+fn normalize_time(value: &str) -> String {
+    match chrono::DateTime::parse_from_rfc3339(value) {
+        Ok(time) => format_time(&time.with_timezone(&chrono::Utc)),
+        Err(_) => value.to_owned(),
+    }
+}
+
+fn parse_snapshot<T: serde::de::DeserializeOwned>(
+    column: usize,
+    value: &str,
+) -> rusqlite::Result<T> {
+    serde_json::from_str(value).map_err(|error| {
+        rusqlite::Error::FromSqlConversionFailure(
+            column,
+            rusqlite::types::Type::Text,
+            Box::new(error),
+        )
+    })
+}
+
+fn to_text<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Models are always serializable")
+}
+
+impl Journal {
+    pub fn open<TPath: AsRef<std::path::Path>>(path: TPath) -> rusqlite::Result<Journal> {
+        Journal::with_connection(rusqlite::Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> rusqlite::Result<Journal> {
+        Journal::with_connection(rusqlite::Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: rusqlite::Connection) -> rusqlite::Result<Journal> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Journal {
+            connection: std::sync::Mutex::new(connection),
+        })
+    }
+
+    pub fn record_call(&self, entry: &CallEntry) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO calls (recorded_at, endpoint, method, path, pair, order_id, \
+             request, response, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            rusqlite::params![
+                format_time(&entry.recorded_at),
+                entry.endpoint,
+                entry.method,
+                entry.path,
+                entry.pair,
+                entry.order_id,
+                entry.request,
+                entry.response,
+                entry.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_order(&self, order: &models::Order) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO orders (id, pair, status, seen_at, snapshot) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                order.id,
+                order.pair,
+                order.status,
+                format_time(&chrono::Utc::now()),
+                to_text(order),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_trade(&self, trade: &models::Trade) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO trades (id, order_id, pair, created_at, seen_at, snapshot) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                trade.id,
+                trade.order.id,
+                trade.order.pair,
+                normalize_time(&trade.created_at),
+                format_time(&chrono::Utc::now()),
+                to_text(trade),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_invoice(&self, invoice: &models::Invoice) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO invoices (id, status, seen_at, snapshot) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![
+                invoice.id,
+                invoice.status,
+                format_time(&chrono::Utc::now()),
+                to_text(invoice),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Mutating calls, oldest first.
+    pub fn calls(&self) -> rusqlite::Result<Vec<CallEntry>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT recorded_at, endpoint, method, path, pair, order_id, request, \
+             response, error FROM calls ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(CallEntry {
+                recorded_at: parse_time(0, &row.get::<_, String>(0)?)?,
+                endpoint: row.get(1)?,
+                method: row.get(2)?,
+                path: row.get(3)?,
+                pair: row.get(4)?,
+                order_id: row.get(5)?,
+                request: row.get(6)?,
+                response: row.get(7)?,
+                error: row.get(8)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Every snapshot of the order, oldest first.
+    pub fn order_history(&self, id: u32) -> rusqlite::Result<Vec<OrderSnapshot>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT seen_at, snapshot FROM orders WHERE id = ?1 ORDER BY row_id",
+        )?;
+        let rows = statement.query_map([id], |row| {
+            Ok(OrderSnapshot {
+                seen_at: parse_time(0, &row.get::<_, String>(0)?)?,
+                order: parse_snapshot(1, &row.get::<_, String>(1)?)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Latest snapshots of the trades of the pair created in `[from, to)`,
+    /// oldest first.
+    pub fn trades_by_pair(
+        &self,
+        pair: &coin::CoinPair,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> rusqlite::Result<Vec<models::Trade>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT snapshot FROM trades \
+             WHERE row_id IN (SELECT MAX(row_id) FROM trades GROUP BY id) \
+             AND pair = ?1 AND created_at >= ?2 AND created_at < ?3 \
+             ORDER BY created_at, id",
+        )?;
+        let rows = statement.query_map(
+            rusqlite::params![String::from(pair), format_time(&from), format_time(&to)],
+            |row| parse_snapshot(0, &row.get::<_, String>(0)?),
+        )?;
+        rows.collect()
+    }
+
+    /// Latest snapshots of the invoices currently in the status.
+    pub fn invoices_by_status(
+        &self,
+        status: &models::InvoiceStatus,
+    ) -> rusqlite::Result<Vec<models::Invoice>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT snapshot FROM invoices \
+             WHERE row_id IN (SELECT MAX(row_id) FROM invoices GROUP BY id) \
+             AND status = ?1 ORDER BY row_id",
+        )?;
+        let rows = statement.query_map([status.to_string()], |row| {
+            parse_snapshot(0, &row.get::<_, String>(0)?)
+        })?;
+        rows.collect()
+    }
+
+    /// Records the outcome of a call made by a sub-client, the response
+    /// body or the error message.
+    ///
+    /// Failures to write are logged, the journal never fails the call itself.
+    /// Blocks on SQLite, so it is called on the blocking thread pool.
+    pub(crate) fn observe(
+        &self,
+        call: &call::Call,
+        method: &http::Method,
+        path: &str,
+        request: &[u8],
+        response: Result<&[u8], &str>,
+    ) {
+        let is_journaled = call.endpoint.starts_with("exchange.")
+            || call.endpoint.starts_with("invoice.");
+        if !is_journaled {
+            return;
+        }
+        if method != http::Method::GET {
+            let text = |body: &[u8]| {
+                if body.is_empty() {
+                    None
+                } else {
+                    Some(String::from_utf8_lossy(body).into_owned())
+                }
+            };
+            let entry = CallEntry {
+                recorded_at: chrono::Utc::now(),
+                endpoint: call.endpoint.to_owned(),
+                method: method.to_string(),
+                path: path.to_owned(),
+                pair: call.pair.clone(),
+                order_id: call.order_id.clone(),
+                request: text(request),
+                response: response.ok().and_then(text),
+                error: response.err().map(str::to_owned),
+            };
+            if let Err(error) = self.record_call(&entry) {
+                log::error!("Failed to journal {}: {}", call.endpoint, error);
+            }
+        }
+        if let Ok(body) = response {
+            if let Err(error) = self.record_snapshots(call.endpoint, body) {
+                log::error!("Failed to journal {} response: {}", call.endpoint, error);
+            }
+        }
+    }
+
+    fn record_snapshots(&self, endpoint: &str, body: &[u8]) -> rusqlite::Result<()> {
+        let values = match serde_json::from_slice(body) {
+            Ok(serde_json::Value::Array(values)) => values,
+            Ok(value) => vec![value],
+            Err(_) => return Ok(()),
+        };
+        for value in values.into_iter() {
+            if endpoint.starts_with("invoice.") {
+                if let Ok(invoice) = serde_json::from_value(value) {
+                    self.record_invoice(&invoice)?;
+                }
+            } else if endpoint.contains("trade") {
+                if let Ok(trade) = serde_json::from_value(value) {
+                    self.record_trade(&trade)?;
+                }
+            } else if let Ok(order) = serde_json::from_value(value) {
+                self.record_order(&order)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Journal {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.debug_struct("Journal").finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn records_calls_and_snapshots() {
+        let journal = std::sync::Arc::new(Journal::in_memory().unwrap());
+        let case = TestCase::with_transport(|transport| {
+            transport.journal = Some(journal.clone());
+        });
+        let access_token_mock = case.mock_access_token();
+        let order = models::Order {
+            pair: "btc/usdt_erc20".to_owned(),
+            ..models::Order::default()
+        };
+        let created_order = serde_json::to_string(&order).expect(SERDE_ERROR);
+        let create_order_mock = case.server.mock(|when, then| {
+            default_post_when(when).path("/exchange/orders");
+            default_then_content_type(then)
+                .status(201)
+                .body(created_order.clone());
+        });
+        let trades = vec![
+            models::Trade {
+                amount: "1".to_owned(),
+                created_at: "2021-03-01T10:00:00+01:00".to_owned(),
+                fee: "0".to_owned(),
+                id: 1,
+                order: order.clone(),
+                received_amount: "1".to_owned(),
+                updated_at: "2021-03-01T10:00:00Z".to_owned(),
+            },
+            models::Trade {
+                amount: "2".to_owned(),
+                created_at: "2021-03-02T10:00:00Z".to_owned(),
+                fee: "0".to_owned(),
+                id: 2,
+                order: order.clone(),
+                received_amount: "2".to_owned(),
+                updated_at: "2021-03-02T10:00:00Z".to_owned(),
+            },
+        ];
+        let trades = serde_json::to_string(&trades).expect(SERDE_ERROR);
+        let trades_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/exchange/orders/trades");
+            default_then_content_type(then)
+                .status(200)
+                .body(trades.clone());
+        });
+        let client = crate::ExchangeClient::new(
+            case.client_base.clone(),
+            std::sync::Arc::new(crate::endpoint::Exchange::new(&case.base_context)),
+        );
+        let pair = coin::CoinPair::new(coin::Coin::BTC, coin::Coin::USDT);
+        tokio_test::block_on(client.create_order(pair.clone(), 37f64, 13f64)).unwrap();
+        tokio_test::block_on(client.get_trades(None, None, None)).unwrap();
+
+        let calls = journal.calls().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].endpoint, "exchange.create_order");
+        assert_eq!(calls[0].method, "POST");
+        assert_eq!(calls[0].response.as_deref(), Some(created_order.as_str()));
+        assert!(calls[0]
+            .request
+            .as_deref()
+            .unwrap()
+            .contains("btc/usdt_erc20"));
+        let history = journal.order_history(order.id).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].order.pair, order.pair);
+        let day = |day: u32| {
+            chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2021, 3, day, 0, 0, 0)
+                .unwrap()
+        };
+        let found = journal.trades_by_pair(&pair, day(1), day(2)).unwrap();
+        assert_eq!(
+            found.iter().map(|trade| trade.id).collect::<Vec<_>>(),
+            vec![1]
+        );
+        let found = journal.trades_by_pair(&pair, day(2), day(3)).unwrap();
+        assert_eq!(
+            found.iter().map(|trade| trade.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+        let other = coin::CoinPair::new(coin::Coin::ETH, coin::Coin::USDT);
+        assert!(journal
+            .trades_by_pair(&other, day(1), day(3))
+            .unwrap()
+            .is_empty());
+        access_token_mock.assert_hits(2);
+        create_order_mock.assert();
+        trades_mock.assert();
+    }
+}
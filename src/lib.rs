@@ -1,13 +1,46 @@
+pub mod backend;
+pub mod bulk;
+pub mod call;
+#[cfg(feature = "cassette")]
+pub mod cassette;
+pub mod clock;
+pub mod config;
 pub mod coin;
 pub mod context;
 pub mod endpoint;
 pub mod error;
+pub mod export;
 pub mod extractor;
+#[cfg(feature = "journal")]
+pub mod journal;
+pub mod idempotency;
 pub mod models;
+pub mod pnl;
+#[cfg(feature = "recorder")]
+pub mod recorder;
+pub mod secret;
+pub mod simulator;
+pub mod strategy;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+pub mod tls;
+pub mod token_store;
 pub mod client_base;
+pub mod middleware;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod retry;
+pub mod rate_limiter;
+pub mod transport;
 pub mod profile_client;
 pub mod access_controller;
+pub mod accounts;
+pub mod backoff;
+pub mod balance_watcher;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod token_refresher;
 pub mod coin_client;
+pub mod exchange_api;
 pub mod exchange_client;
 pub mod invoice_client;
 pub mod payment_system_client;
@@ -16,7 +49,8 @@ pub mod chatex_client;
 #[cfg(test)]
 pub(crate) mod test;
 
-pub use chatex_client::ChatexClient;
+pub use accounts::ChatexAccounts;
+pub use chatex_client::{ChatexClient, ChatexClientBuilder};
 pub use profile_client::ProfileClient;
 pub use coin_client::CoinClient;
 pub use exchange_client::ExchangeClient;
@@ -0,0 +1,318 @@
+use super::error;
+
+/// Receives measurements taken by the SDK.
+///
+/// Implement it to forward the data into your own metrics system, or use
+/// `Registry` which keeps everything in memory and renders the Prometheus
+/// text exposition format.
+pub trait Recorder: Send + Sync {
+    fn record_request(
+        &self,
+        endpoint: &'static str,
+        latency: std::time::Duration,
+        error: Option<&error::Error>,
+    );
+
+    fn record_token_refresh(
+        &self,
+        latency: std::time::Duration,
+        error: Option<&error::Error>,
+    );
+
+    fn record_rate_limited(&self, endpoint: &'static str);
+}
+
+/// Upper bounds of the latency histogram buckets in seconds.
+pub const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, bounds: &[f64], value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; bounds.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(bounds.iter()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct State {
+    requests: std::collections::BTreeMap<&'static str, u64>,
+    errors: std::collections::BTreeMap<(&'static str, &'static str), u64>,
+    latencies: std::collections::BTreeMap<&'static str, Histogram>,
+    token_refreshes: std::collections::BTreeMap<&'static str, u64>,
+    token_refresh_latency: Histogram,
+    rate_limited: std::collections::BTreeMap<&'static str, u64>,
+}
+
+/// In-memory `Recorder` with a Prometheus text renderer.
+pub struct Registry {
+    bounds: Vec<f64>,
+    state: std::sync::Mutex<State>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+
+    pub fn with_buckets(bounds: Vec<f64>) -> Registry {
+        Registry {
+            bounds,
+            state: Default::default(),
+        }
+    }
+
+    /// Renders all the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        use std::fmt::Write;
+        let state = self.state.lock().unwrap();
+        let mut output = String::new();
+        writeln!(output, "# HELP chatex_requests_total Number of API calls.").unwrap();
+        writeln!(output, "# TYPE chatex_requests_total counter").unwrap();
+        for (endpoint, count) in state.requests.iter() {
+            writeln!(
+                output,
+                "chatex_requests_total{{endpoint=\"{}\"}} {}",
+                endpoint, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            output,
+            "# HELP chatex_request_errors_total Number of failed API calls by error."
+        )
+        .unwrap();
+        writeln!(output, "# TYPE chatex_request_errors_total counter").unwrap();
+        for ((endpoint, error), count) in state.errors.iter() {
+            writeln!(
+                output,
+                "chatex_request_errors_total{{endpoint=\"{}\",error=\"{}\"}} {}",
+                endpoint, error, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            output,
+            "# HELP chatex_request_duration_seconds Latency of API calls."
+        )
+        .unwrap();
+        writeln!(output, "# TYPE chatex_request_duration_seconds histogram").unwrap();
+        for (endpoint, histogram) in state.latencies.iter() {
+            let labels = format!("endpoint=\"{}\"", endpoint);
+            self.render_histogram(
+                &mut output,
+                "chatex_request_duration_seconds",
+                &labels,
+                histogram,
+            );
+        }
+        writeln!(
+            output,
+            "# HELP chatex_token_refreshes_total Number of access token requests."
+        )
+        .unwrap();
+        writeln!(output, "# TYPE chatex_token_refreshes_total counter").unwrap();
+        for (result, count) in state.token_refreshes.iter() {
+            writeln!(
+                output,
+                "chatex_token_refreshes_total{{result=\"{}\"}} {}",
+                result, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            output,
+            "# HELP chatex_token_refresh_duration_seconds Latency of access token requests."
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "# TYPE chatex_token_refresh_duration_seconds histogram"
+        )
+        .unwrap();
+        if state.token_refresh_latency.count > 0 {
+            self.render_histogram(
+                &mut output,
+                "chatex_token_refresh_duration_seconds",
+                "",
+                &state.token_refresh_latency,
+            );
+        }
+        writeln!(
+            output,
+            "# HELP chatex_rate_limited_total Number of responses with status 429."
+        )
+        .unwrap();
+        writeln!(output, "# TYPE chatex_rate_limited_total counter").unwrap();
+        for (endpoint, count) in state.rate_limited.iter() {
+            writeln!(
+                output,
+                "chatex_rate_limited_total{{endpoint=\"{}\"}} {}",
+                endpoint, count
+            )
+            .unwrap();
+        }
+        output
+    }
+
+    fn render_histogram(
+        &self,
+        output: &mut String,
+        name: &str,
+        labels: &str,
+        histogram: &Histogram,
+    ) {
+        use std::fmt::Write;
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in self.bounds.iter().zip(histogram.buckets.iter()) {
+            writeln!(
+                output,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            output,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, histogram.count
+        )
+        .unwrap();
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        writeln!(output, "{}_sum{} {}", name, labels, histogram.sum).unwrap();
+        writeln!(output, "{}_count{} {}", name, labels, histogram.count).unwrap();
+    }
+}
+
+impl Recorder for Registry {
+    fn record_request(
+        &self,
+        endpoint: &'static str,
+        latency: std::time::Duration,
+        error: Option<&error::Error>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        *state.requests.entry(endpoint).or_insert(0) += 1;
+        if let Some(error) = error {
+            *state.errors.entry((endpoint, error.name())).or_insert(0) += 1;
+        }
+        state
+            .latencies
+            .entry(endpoint)
+            .or_default()
+            .observe(&self.bounds, latency.as_secs_f64());
+    }
+
+    fn record_token_refresh(
+        &self,
+        latency: std::time::Duration,
+        error: Option<&error::Error>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let result = if error.is_some() { "error" } else { "ok" };
+        *state.token_refreshes.entry(result).or_insert(0) += 1;
+        state
+            .token_refresh_latency
+            .observe(&self.bounds, latency.as_secs_f64());
+    }
+
+    fn record_rate_limited(&self, endpoint: &'static str) {
+        let mut state = self.state.lock().unwrap();
+        *state.rate_limited.entry(endpoint).or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn render() {
+        let registry = Registry::with_buckets(vec![0.1, 1.0]);
+        registry.record_request(
+            "exchange.get_all_orders",
+            std::time::Duration::from_millis(50),
+            None,
+        );
+        registry.record_request(
+            "exchange.get_all_orders",
+            std::time::Duration::from_millis(500),
+            Some(&error::Error::RateLimitedError { retry_after: 1 }),
+        );
+        registry.record_rate_limited("exchange.get_all_orders");
+        registry.record_token_refresh(std::time::Duration::from_millis(20), None);
+        let output = registry.render();
+        for line in [
+            r#"chatex_requests_total{endpoint="exchange.get_all_orders"} 2"#,
+            r#"chatex_request_errors_total{endpoint="exchange.get_all_orders",error="RateLimitedError"} 1"#,
+            r#"chatex_request_duration_seconds_bucket{endpoint="exchange.get_all_orders",le="0.1"} 1"#,
+            r#"chatex_request_duration_seconds_bucket{endpoint="exchange.get_all_orders",le="1"} 2"#,
+            r#"chatex_request_duration_seconds_bucket{endpoint="exchange.get_all_orders",le="+Inf"} 2"#,
+            r#"chatex_request_duration_seconds_count{endpoint="exchange.get_all_orders"} 2"#,
+            r#"chatex_token_refreshes_total{result="ok"} 1"#,
+            r#"chatex_token_refresh_duration_seconds_count 1"#,
+            r#"chatex_rate_limited_total{endpoint="exchange.get_all_orders"} 1"#,
+        ]
+        .iter()
+        {
+            assert!(output.lines().any(|rendered| rendered == *line), "{}", output);
+        }
+    }
+
+    #[test]
+    fn records_calls_and_token_refresh() {
+        let registry = std::sync::Arc::new(Registry::new());
+        let case = TestCase::with_metrics(registry.clone());
+        let access_token_mock = case.mock_access_token();
+        let balance_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/me/balance");
+            default_then_content_type(then)
+                .status(429)
+                .body(r#"{ "retryAfter": 3 }"#);
+        });
+        let profile =
+            std::sync::Arc::new(crate::endpoint::Profile::new(&case.base_context));
+        let client = crate::ProfileClient::new(case.client_base.clone(), profile);
+        let balance = tokio_test::block_on(client.get_balance_summary());
+        assert!(matches!(
+            balance,
+            Err(error::Error::RateLimitedError { retry_after: 3 })
+        ));
+        let output = registry.render();
+        assert!(output.contains(
+            r#"chatex_requests_total{endpoint="profile.get_balance_summary"} 1"#
+        ));
+        assert!(output.contains(
+            r#"chatex_rate_limited_total{endpoint="profile.get_balance_summary"} 1"#
+        ));
+        assert!(output.contains(r#"chatex_token_refreshes_total{result="ok"} 1"#));
+        access_token_mock.assert();
+        balance_mock.assert();
+    }
+}
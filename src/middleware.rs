@@ -0,0 +1,242 @@
+use super::backend;
+use super::error;
+use super::secret;
+
+/// Description of the request a response or an error belongs to.
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    pub method: http::Method,
+    pub uri: http::Uri,
+}
+
+impl RequestInfo {
+    pub fn new<TBody>(request: &http::Request<TBody>) -> RequestInfo {
+        RequestInfo {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+        }
+    }
+}
+
+/// Everything known about a response before its body is read.
+#[derive(Debug)]
+pub struct ResponseInfo<'a> {
+    pub status: http::StatusCode,
+    pub headers: &'a http::HeaderMap,
+    pub latency: std::time::Duration,
+}
+
+/// Hooks invoked around every HTTP exchange, including the access token request.
+///
+/// All hooks are optional. `before_send` is allowed to mutate the request,
+/// e.g. to inject headers or to replace the body.
+pub trait Middleware: Send + Sync {
+    fn before_send(&self, _request: &mut http::Request<backend::Body>) {}
+
+    fn after_receive(&self, _request: &RequestInfo, _response: &ResponseInfo<'_>) {}
+
+    fn on_error(&self, _request: &RequestInfo, _error: &error::Error) {}
+}
+
+/// Ordered list of middlewares.
+///
+/// `before_send` hooks run in insertion order, `after_receive` and `on_error`
+/// hooks run in reverse order, so the first middleware wraps all the others.
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+    middlewares: Vec<std::sync::Arc<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> MiddlewareChain {
+        MiddlewareChain {
+            middlewares: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, middleware: std::sync::Arc<dyn Middleware>) {
+        self.middlewares.push(middleware);
+    }
+
+    pub fn len(&self) -> usize {
+        self.middlewares.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.middlewares.is_empty()
+    }
+
+    pub fn before_send(&self, request: &mut http::Request<backend::Body>) {
+        for middleware in self.middlewares.iter() {
+            middleware.before_send(request);
+        }
+    }
+
+    pub fn after_receive(&self, request: &RequestInfo, response: &ResponseInfo<'_>) {
+        for middleware in self.middlewares.iter().rev() {
+            middleware.after_receive(request, response);
+        }
+    }
+
+    pub fn on_error(&self, request: &RequestInfo, error: &error::Error) {
+        for middleware in self.middlewares.iter().rev() {
+            middleware.on_error(request, error);
+        }
+    }
+}
+
+impl std::fmt::Debug for MiddlewareChain {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter
+            .debug_struct("MiddlewareChain")
+            .field("len", &self.middlewares.len())
+            .finish()
+    }
+}
+
+/// Logs requests and responses at debug level.
+///
+/// Sensitive headers are redacted with `secret::redact_headers` and bodies
+/// are never logged.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoggingMiddleware;
+
+impl Middleware for LoggingMiddleware {
+    fn before_send(&self, request: &mut http::Request<backend::Body>) {
+        log::debug!(
+            "--> {} {} {:?}",
+            request.method(),
+            request.uri(),
+            secret::redact_headers(request.headers())
+        );
+    }
+
+    fn after_receive(&self, request: &RequestInfo, response: &ResponseInfo<'_>) {
+        log::debug!(
+            "<-- {} {} {} in {:?} {:?}",
+            request.method,
+            request.uri,
+            response.status,
+            response.latency,
+            secret::redact_headers(response.headers)
+        );
+    }
+
+    fn on_error(&self, request: &RequestInfo, error: &error::Error) {
+        log::debug!("<-- {} {} failed: {}", request.method, request.uri, error);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: std::sync::Mutex<Vec<String>>,
+        name: &'static str,
+    }
+
+    impl Recorder {
+        fn named(name: &'static str) -> Recorder {
+            Recorder {
+                events: Default::default(),
+                name,
+            }
+        }
+    }
+
+    impl Middleware for Recorder {
+        fn before_send(&self, request: &mut http::Request<backend::Body>) {
+            request
+                .headers_mut()
+                .append("X-Middleware", http::HeaderValue::from_static(self.name));
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("before {}", request.uri().path()));
+        }
+
+        fn after_receive(&self, request: &RequestInfo, response: &ResponseInfo<'_>) {
+            self.events.lock().unwrap().push(format!(
+                "after {} {}",
+                request.uri.path(),
+                response.status.as_u16()
+            ));
+        }
+
+        fn on_error(&self, request: &RequestInfo, error: &error::Error) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("error {} {}", request.uri.path(), error));
+        }
+    }
+
+    #[test]
+    fn hooks_run_for_auth_and_api_requests() {
+        let recorder = std::sync::Arc::new(Recorder::named("first"));
+        let mut chain = MiddlewareChain::new();
+        chain.push(recorder.clone());
+        let case = TestCase::with_middleware(chain);
+        let access_token_mock = case.mock_access_token();
+        let me_mock = case.server.mock(|when, then| {
+            default_get_when(when)
+                .path("/me")
+                .header("X-Middleware", "first");
+            then.status(404);
+        });
+        let profile = std::sync::Arc::new(crate::endpoint::Profile::new(
+            &case.base_context,
+        ));
+        let client = crate::ProfileClient::new(case.client_base.clone(), profile);
+        let result = tokio_test::block_on(client.get_account_information());
+        assert!(matches!(result, Err(error::Error::NotFoundError)));
+        let events = recorder.events.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            vec![
+                "before /auth/access-token".to_owned(),
+                "after /auth/access-token 200".to_owned(),
+                "before /me".to_owned(),
+                "after /me 404".to_owned(),
+                "error /me Not Found Error".to_owned(),
+            ]
+        );
+        access_token_mock.assert();
+        me_mock.assert();
+    }
+
+    #[test]
+    fn no_secrets_in_logs() {
+        install_capturing_logger();
+        let mut chain = MiddlewareChain::new();
+        chain.push(std::sync::Arc::new(LoggingMiddleware));
+        let case = TestCase::with_middleware(chain);
+        let access_token_mock = case.mock_access_token();
+        let created_order = serde_json::to_string(&crate::models::Order::default())
+            .expect(SERDE_ERROR);
+        let create_order_mock = case.server.mock(|when, then| {
+            default_post_when(when).path("/exchange/orders");
+            default_then_content_type(then)
+                .status(201)
+                .body(created_order.clone());
+        });
+        let exchange = std::sync::Arc::new(crate::endpoint::Exchange::new(
+            &case.base_context,
+        ));
+        let client = crate::ExchangeClient::new(case.client_base.clone(), exchange);
+        let pair =
+            crate::coin::CoinPair::new(crate::coin::Coin::BTC, crate::coin::Coin::USDT);
+        tokio_test::block_on(client.create_order(pair, 37f64, 13f64)).unwrap();
+        let logs = captured_logs();
+        assert!(logs.iter().any(|line| line.contains("/exchange/orders")));
+        for line in logs.iter() {
+            assert!(!line.contains(SECRET), "Secret leaked into logs: {}", line);
+            assert!(!line.contains("TOKEN"), "Token leaked into logs: {}", line);
+        }
+        access_token_mock.assert();
+        create_order_mock.assert();
+    }
+}
@@ -1,8 +1,9 @@
 use super::coin;
+use super::secret;
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct AccessToken {
-    pub access_token: String,
+    pub access_token: secret::Secret,
     pub expires_at: i64,
 }
 
@@ -58,8 +59,11 @@ pub struct Currency {
 
 pub mod typed {
     use crate::coin;
+    use rust_decimal::Decimal;
+    use std::convert::TryFrom;
     use std::str::FromStr;
 
+    #[derive(Clone, Debug)]
     pub struct Currency {
         pub coin: coin::Coin,
         pub amount: f64,
@@ -76,7 +80,7 @@ pub mod typed {
         }
     }
 
-    #[derive(Clone)]
+    #[derive(Clone, Debug)]
     pub struct Order {
         pub amount: f64,
         pub rate: f64,
@@ -97,40 +101,556 @@ pub mod typed {
         }
     }
 
-    impl From<Order> for super::Order {
-        fn from(order: Order) -> super::Order {
-            super::Order {
-                rate: format!("{}", order.rate),
-                amount: format!("{}", order.amount),
-                pair: String::from(&order.pair),
-                id: 1337,
-                initial_amount: None,
-                is_owner: None,
-                status: "status".to_owned(),
-                updated_at: "udpated_at".to_owned(),
-                created_at: "created_at".to_owned(),
+    impl From<Order> for super::OrderRequest {
+        fn from(order: Order) -> super::OrderRequest {
+            super::OrderRequest::new(order.pair, order.amount, order.rate)
+        }
+    }
+
+    /// A wire model field which could not be converted into its typed form.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct ModelError {
+        pub field: &'static str,
+        pub value: String,
+    }
+
+    impl ModelError {
+        fn new(field: &'static str, value: &str) -> ModelError {
+            ModelError {
+                field,
+                value: value.to_owned(),
             }
         }
     }
 
-    impl From<Order> for super::Trade {
-        fn from(order: Order) -> super::Trade {
-            super::Trade {
-                amount: format!("{}", order.amount),
-                order: order.clone().into(),
-                created_at: "created_at".to_owned(),
-                updated_at: "udpated_at".to_owned(),
-                id: 32,
-                fee: "fee".to_owned(),
-                received_amount: "received_amount".to_owned(),
+    impl std::fmt::Display for ModelError {
+        fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(formatter, "Invalid value of {}: {:?}", self.field, self.value)
+        }
+    }
+
+    impl std::error::Error for ModelError {}
+
+    fn parse_decimal(field: &'static str, value: &str) -> Result<Decimal, ModelError> {
+        Decimal::from_str(value)
+            .or_else(|_| Decimal::from_scientific(value))
+            .map_err(|_| ModelError::new(field, value))
+    }
+
+    fn parse_time(
+        field: &'static str,
+        value: &str,
+    ) -> Result<chrono::DateTime<chrono::Utc>, ModelError> {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .map(|time| time.with_timezone(&chrono::Utc))
+            .map_err(|_| ModelError::new(field, value))
+    }
+
+    fn ratio(numerator: Decimal, denominator: Decimal) -> Option<Decimal> {
+        if denominator.is_zero() {
+            None
+        } else {
+            numerator.checked_div(denominator)
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    pub enum OrderStatus {
+        Active,
+        Inactive,
+        Completed,
+        Canceled,
+        Unknown(String),
+    }
+
+    impl From<&str> for OrderStatus {
+        fn from(status: &str) -> OrderStatus {
+            match status.to_ascii_uppercase().as_str() {
+                "ACTIVE" => OrderStatus::Active,
+                "INACTIVE" => OrderStatus::Inactive,
+                "COMPLETED" => OrderStatus::Completed,
+                "CANCELED" | "CANCELLED" => OrderStatus::Canceled,
+                _ => OrderStatus::Unknown(status.to_owned()),
+            }
+        }
+    }
+
+    impl std::fmt::Display for OrderStatus {
+        fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+            match self {
+                OrderStatus::Active => formatter.write_str("ACTIVE"),
+                OrderStatus::Inactive => formatter.write_str("INACTIVE"),
+                OrderStatus::Completed => formatter.write_str("COMPLETED"),
+                OrderStatus::Canceled => formatter.write_str("CANCELED"),
+                OrderStatus::Unknown(status) => formatter.write_str(status),
+            }
+        }
+    }
+
+    /// `super::Currency` with parsed amounts.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TypedCurrency {
+        pub coin: coin::Coin,
+        pub amount: Decimal,
+        pub held: Decimal,
+    }
+
+    impl TryFrom<super::Currency> for TypedCurrency {
+        type Error = ModelError;
+
+        fn try_from(currency: super::Currency) -> Result<TypedCurrency, ModelError> {
+            Ok(TypedCurrency {
+                amount: parse_decimal("amount", &currency.amount)?,
+                held: parse_decimal("held", &currency.held)?,
+                coin: coin::Coin::from(currency.coin.as_str()),
+            })
+        }
+    }
+
+    /// `super::Order` with parsed fields.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TypedOrder {
+        pub id: u32,
+        pub pair: coin::CoinPair,
+        /// Amount of `pair.left` still left in the order.
+        pub amount: Decimal,
+        /// Price of one `pair.left` in `pair.right`.
+        pub rate: Decimal,
+        pub initial_amount: Option<Decimal>,
+        pub is_owner: Option<bool>,
+        pub status: OrderStatus,
+        pub created_at: chrono::DateTime<chrono::Utc>,
+        pub updated_at: chrono::DateTime<chrono::Utc>,
+    }
+
+    impl TypedOrder {
+        /// Amount already traded. Unknown without `initial_amount`.
+        pub fn filled_amount(&self) -> Option<Decimal> {
+            self.initial_amount
+                .map(|initial_amount| initial_amount - self.amount)
+        }
+
+        /// Share of `initial_amount` already traded, from 0 to 1.
+        pub fn fill_ratio(&self) -> Option<Decimal> {
+            let initial_amount = self.initial_amount?;
+            ratio(self.filled_amount()?, initial_amount)
+        }
+
+        /// Value of the remaining amount in `pair.right`.
+        pub fn notional(&self) -> Decimal {
+            self.amount * self.rate
+        }
+    }
+
+    impl TryFrom<super::Order> for TypedOrder {
+        type Error = ModelError;
+
+        fn try_from(order: super::Order) -> Result<TypedOrder, ModelError> {
+            Ok(TypedOrder {
+                id: order.id,
+                pair: coin::CoinPair::parse(&order.pair)
+                    .ok_or_else(|| ModelError::new("pair", &order.pair))?,
+                amount: parse_decimal("amount", &order.amount)?,
+                rate: parse_decimal("rate", &order.rate)?,
+                initial_amount: order
+                    .initial_amount
+                    .as_deref()
+                    .map(|amount| parse_decimal("initial_amount", amount))
+                    .transpose()?,
+                is_owner: order.is_owner,
+                status: OrderStatus::from(order.status.as_str()),
+                created_at: parse_time("created_at", &order.created_at)?,
+                updated_at: parse_time("updated_at", &order.updated_at)?,
+            })
+        }
+    }
+
+    /// Direction of a trade for the account, in terms of `pair.left`.
+    #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Side {
+        Buy,
+        Sell,
+    }
+
+    impl Side {
+        /// Orders sell `pair.left`, so trades of our own orders are sells and
+        /// trades against somebody else's orders are buys. Unknown without
+        /// `is_owner`.
+        pub fn for_owner(is_owner: Option<bool>) -> Option<Side> {
+            match is_owner? {
+                true => Some(Side::Sell),
+                false => Some(Side::Buy),
+            }
+        }
+    }
+
+    impl std::fmt::Display for Side {
+        fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+            match self {
+                Side::Buy => formatter.write_str("buy"),
+                Side::Sell => formatter.write_str("sell"),
+            }
+        }
+    }
+
+    /// `super::Trade` with parsed fields.
+    ///
+    /// A buy pays `pair.right` and receives `pair.left`, a sell the other way
+    /// round. The fee is taken from the received coin.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TypedTrade {
+        pub id: u32,
+        pub order: TypedOrder,
+        pub side: Side,
+        /// Amount paid for the trade.
+        pub amount: Decimal,
+        /// Amount received after the fee.
+        pub received_amount: Decimal,
+        pub fee: Decimal,
+        pub created_at: chrono::DateTime<chrono::Utc>,
+        pub updated_at: chrono::DateTime<chrono::Utc>,
+    }
+
+    impl TypedTrade {
+        /// Coins paid and received, in this order.
+        pub fn coins(&self) -> (&coin::Coin, &coin::Coin) {
+            match self.side {
+                Side::Buy => (&self.order.pair.right, &self.order.pair.left),
+                Side::Sell => (&self.order.pair.left, &self.order.pair.right),
+            }
+        }
+
+        /// Received amount per unit paid, fee included.
+        pub fn effective_rate(&self) -> Option<Decimal> {
+            ratio(self.received_amount, self.amount)
+        }
+
+        /// Share of the gross received amount taken as the fee, from 0 to 1.
+        pub fn fee_share(&self) -> Option<Decimal> {
+            ratio(self.fee, self.received_amount + self.fee)
+        }
+    }
+
+    impl TryFrom<super::Trade> for TypedTrade {
+        type Error = ModelError;
+
+        /// Fails without `order.is_owner`, the side of the trade is unknown
+        /// then.
+        fn try_from(trade: super::Trade) -> Result<TypedTrade, ModelError> {
+            let side = Side::for_owner(trade.order.is_owner)
+                .ok_or_else(|| ModelError::new("is_owner", "null"))?;
+            Ok(TypedTrade {
+                id: trade.id,
+                side,
+                amount: parse_decimal("amount", &trade.amount)?,
+                received_amount: parse_decimal("received_amount", &trade.received_amount)?,
+                fee: parse_decimal("fee", &trade.fee)?,
+                created_at: parse_time("created_at", &trade.created_at)?,
+                updated_at: parse_time("updated_at", &trade.updated_at)?,
+                order: TypedOrder::try_from(trade.order)?,
+            })
+        }
+    }
+
+    /// `super::AML5Limits` with parsed fields.
+    ///
+    /// The API reports a single current withdrawal, it is counted against
+    /// both the overall and the daily withdrawal limit.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Limits {
+        pub current_turnover: Decimal,
+        pub turnover_limit: Decimal,
+        pub current_withdraw: Decimal,
+        pub withdraw_limit: Decimal,
+        pub withdraw_limit_daily: Decimal,
+    }
+
+    fn remaining(limit: Decimal, current: Decimal) -> Decimal {
+        (limit - current).max(Decimal::ZERO)
+    }
+
+    fn percentage(current: Decimal, limit: Decimal) -> Option<Decimal> {
+        ratio(current, limit).map(|ratio| ratio * Decimal::ONE_HUNDRED)
+    }
+
+    impl Limits {
+        pub fn remaining_turnover(&self) -> Decimal {
+            remaining(self.turnover_limit, self.current_turnover)
+        }
+
+        pub fn remaining_withdraw(&self) -> Decimal {
+            remaining(self.withdraw_limit, self.current_withdraw)
+        }
+
+        pub fn remaining_withdraw_daily(&self) -> Decimal {
+            remaining(self.withdraw_limit_daily, self.current_withdraw)
+        }
+
+        /// Used share of the turnover limit in percent. Unknown for a zero
+        /// limit.
+        pub fn turnover_utilization(&self) -> Option<Decimal> {
+            percentage(self.current_turnover, self.turnover_limit)
+        }
+
+        /// Used share of the stricter withdrawal limit in percent.
+        pub fn withdraw_utilization(&self) -> Option<Decimal> {
+            let overall = percentage(self.current_withdraw, self.withdraw_limit);
+            let daily = percentage(self.current_withdraw, self.withdraw_limit_daily);
+            overall.max(daily)
+        }
+
+        pub fn can_withdraw(&self, amount: Decimal) -> bool {
+            amount <= self.remaining_withdraw().min(self.remaining_withdraw_daily())
+        }
+    }
+
+    impl TryFrom<super::AML5Limits> for Limits {
+        type Error = ModelError;
+
+        fn try_from(limits: super::AML5Limits) -> Result<Limits, ModelError> {
+            Ok(Limits {
+                current_turnover: parse_decimal("current_turnover", &limits.current_turnover)?,
+                turnover_limit: parse_decimal("turnover_limit", &limits.turnover_limit)?,
+                current_withdraw: parse_decimal("current_withdraw", &limits.current_withdraw)?,
+                withdraw_limit: parse_decimal("withdraw_limit", &limits.withdraw_limit)?,
+                withdraw_limit_daily: parse_decimal(
+                    "withdraw_limit_daily",
+                    &limits.withdraw_limit_daily,
+                )?,
+            })
+        }
+    }
+
+    /// Why an operation does not fit into the account limits.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum LimitViolation {
+        FinanceBlocked,
+        Turnover { remaining: Decimal },
+        Withdraw { remaining: Decimal },
+        MerchantAmount { limit: Decimal },
+    }
+
+    impl std::fmt::Display for LimitViolation {
+        fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+            match self {
+                LimitViolation::FinanceBlocked => formatter.write_str("Finance is blocked"),
+                LimitViolation::Turnover { remaining } => {
+                    write!(formatter, "Remaining turnover is {}", remaining)
+                }
+                LimitViolation::Withdraw { remaining } => {
+                    write!(formatter, "Remaining withdrawal is {}", remaining)
+                }
+                LimitViolation::MerchantAmount { limit } => {
+                    write!(formatter, "Merchant amount limit is {} USD", limit)
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for LimitViolation {}
+
+    /// Limits of `super::BasicInfo`.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct AccountLimits {
+        pub is_finance_blocked: bool,
+        pub limits: Limits,
+        /// Largest invoice in USD, for merchants only.
+        pub usd_amount_max_limit: Option<Decimal>,
+    }
+
+    impl AccountLimits {
+        pub fn can_withdraw(&self, amount: Decimal) -> bool {
+            self.check_withdraw(amount).is_ok()
+        }
+
+        pub fn check_withdraw(&self, amount: Decimal) -> Result<(), LimitViolation> {
+            if self.is_finance_blocked {
+                return Err(LimitViolation::FinanceBlocked);
+            }
+            if !self.limits.can_withdraw(amount) {
+                return Err(LimitViolation::Withdraw {
+                    remaining: self
+                        .limits
+                        .remaining_withdraw()
+                        .min(self.limits.remaining_withdraw_daily()),
+                });
+            }
+            Ok(())
+        }
+
+        /// Checks an invoice of `usd_amount` before it is created.
+        pub fn check_invoice(&self, usd_amount: Decimal) -> Result<(), LimitViolation> {
+            if self.is_finance_blocked {
+                return Err(LimitViolation::FinanceBlocked);
+            }
+            if let Some(limit) = self.usd_amount_max_limit {
+                if usd_amount > limit {
+                    return Err(LimitViolation::MerchantAmount { limit });
+                }
+            }
+            let remaining = self.limits.remaining_turnover();
+            if usd_amount > remaining {
+                return Err(LimitViolation::Turnover { remaining });
+            }
+            Ok(())
+        }
+    }
+
+    impl TryFrom<super::BasicInfo> for AccountLimits {
+        type Error = ModelError;
+
+        fn try_from(info: super::BasicInfo) -> Result<AccountLimits, ModelError> {
+            Ok(AccountLimits {
+                is_finance_blocked: info.profile.is_finance_blocked,
+                limits: Limits::try_from(info.profile.limits)?,
+                usd_amount_max_limit: info
+                    .merchant_info
+                    .map(|merchant| {
+                        parse_decimal("usd_amount_max_limit", &merchant.usd_amount_max_limit)
+                    })
+                    .transpose()?,
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        fn order() -> crate::models::Order {
+            crate::models::Order {
+                amount: "0.25".to_owned(),
+                created_at: "2021-03-01T10:00:00Z".to_owned(),
+                id: 7,
+                initial_amount: Some("1".to_owned()),
+                is_owner: Some(true),
+                pair: "btc/usdt_erc20".to_owned(),
+                rate: "50000.5".to_owned(),
+                status: "ACTIVE".to_owned(),
+                updated_at: "2021-03-01T11:30:00+01:00".to_owned(),
             }
         }
+
+        #[test]
+        fn typed_order_and_trade() {
+            let typed = TypedOrder::try_from(order()).unwrap();
+            assert_eq!(
+                typed.pair,
+                coin::CoinPair::new(coin::Coin::BTC, coin::Coin::USDT)
+            );
+            assert_eq!(typed.status, OrderStatus::Active);
+            assert_eq!(typed.filled_amount(), Some(Decimal::new(75, 2)));
+            assert_eq!(typed.fill_ratio(), Some(Decimal::new(75, 2)));
+            assert_eq!(typed.notional(), Decimal::new(12500125, 3));
+            assert_eq!(typed.updated_at.to_rfc3339(), "2021-03-01T10:30:00+00:00");
+            let trade = crate::models::Trade {
+                amount: "100".to_owned(),
+                created_at: "2021-03-01T10:00:00Z".to_owned(),
+                fee: "1".to_owned(),
+                id: 3,
+                order: order(),
+                received_amount: "99".to_owned(),
+                updated_at: "2021-03-01T10:00:00Z".to_owned(),
+            };
+            let typed = TypedTrade::try_from(trade.clone()).unwrap();
+            assert_eq!(typed.effective_rate(), Some(Decimal::new(99, 2)));
+            assert_eq!(typed.fee_share(), Some(Decimal::new(1, 2)));
+            assert_eq!(typed.side, Side::Sell);
+            let unknown = crate::models::Trade {
+                order: crate::models::Order {
+                    is_owner: None,
+                    ..order()
+                },
+                ..trade.clone()
+            };
+            assert_eq!(
+                TypedTrade::try_from(unknown),
+                Err(ModelError::new("is_owner", "null"))
+            );
+            let invalid = crate::models::Trade {
+                fee: "fee".to_owned(),
+                ..trade
+            };
+            assert_eq!(
+                TypedTrade::try_from(invalid),
+                Err(ModelError::new("fee", "fee"))
+            );
+            let invalid = crate::models::Order {
+                pair: "btc".to_owned(),
+                ..order()
+            };
+            assert_eq!(
+                TypedOrder::try_from(invalid),
+                Err(ModelError::new("pair", "btc"))
+            );
+        }
+
+        #[test]
+        fn account_limits() {
+            let info = crate::models::BasicInfo {
+                merchant_info: Some(crate::models::MerchantInfo {
+                    name: "merchant".to_owned(),
+                    usd_amount_max_limit: "500".to_owned(),
+                }),
+                profile: crate::models::Profile {
+                    limits: crate::models::AML5Limits {
+                        current_turnover: "9200".to_owned(),
+                        current_withdraw: "300.5".to_owned(),
+                        turnover_limit: "10000".to_owned(),
+                        withdraw_limit: "5000".to_owned(),
+                        withdraw_limit_daily: "1000".to_owned(),
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let limits = AccountLimits::try_from(info.clone()).unwrap();
+            assert_eq!(limits.limits.remaining_turnover(), Decimal::from(800));
+            assert_eq!(limits.limits.remaining_withdraw(), Decimal::new(46995, 1));
+            assert_eq!(limits.limits.remaining_withdraw_daily(), Decimal::new(6995, 1));
+            assert_eq!(limits.limits.turnover_utilization(), Some(Decimal::from(92)));
+            assert_eq!(
+                limits.limits.withdraw_utilization(),
+                Some(Decimal::new(3005, 2))
+            );
+            assert!(limits.can_withdraw(Decimal::new(6995, 1)));
+            assert_eq!(
+                limits.check_withdraw(Decimal::from(700)),
+                Err(LimitViolation::Withdraw {
+                    remaining: Decimal::new(6995, 1)
+                })
+            );
+            assert_eq!(limits.check_invoice(Decimal::from(500)), Ok(()));
+            assert_eq!(
+                limits.check_invoice(Decimal::from(501)),
+                Err(LimitViolation::MerchantAmount {
+                    limit: Decimal::from(500)
+                })
+            );
+            let blocked = AccountLimits {
+                is_finance_blocked: true,
+                usd_amount_max_limit: None,
+                ..limits
+            };
+            assert!(!blocked.can_withdraw(Decimal::ONE));
+            assert_eq!(
+                blocked.check_invoice(Decimal::ONE),
+                Err(LimitViolation::FinanceBlocked)
+            );
+            let mut invalid = info;
+            invalid.profile.limits = Default::default();
+            assert_eq!(
+                AccountLimits::try_from(invalid),
+                Err(ModelError::new("current_turnover", "current_turnover"))
+            );
+        }
     }
 }
 
 pub type Coins = Vec<Coin>;
 
-#[derive(serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Coin {
     pub decimals: u32,
     pub full_name: String,
@@ -198,7 +718,7 @@ pub struct CreateTradeRequest {
 
 pub type Invoices = Vec<Invoice>;
 
-#[derive(serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Invoice {
     pub amount: f64,
     pub callback_url: String,
@@ -237,21 +757,21 @@ pub enum InvoiceStatus {
 
 pub type PaymentSystemId = u32;
 
-#[derive(serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct PaymentSystem {
-    id: PaymentSystemId,
-    name: String,
+    pub id: PaymentSystemId,
+    pub name: String,
 }
 
 #[derive(serde::Serialize, Clone, Debug)]
 pub struct Estimate {
-    amount: String,
-    coin: String,
+    pub amount: String,
+    pub coin: String,
 }
 
 pub type FiatEstimations = Vec<FiatEstimation>;
 
-#[derive(serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct FiatEstimation {
     pub estimations: PaymentSystemEstimations,
     pub fiat: Fiat,
@@ -259,13 +779,13 @@ pub struct FiatEstimation {
 
 pub type PaymentSystemEstimations = Vec<PaymentSystemEstimation>;
 
-#[derive(serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct PaymentSystemEstimation {
     pub estimated_fiat_amount: f64,
     pub payment_system: PaymentSystem,
 }
 
-#[derive(serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Fiat {
     pub decimals: u32,
     pub full_name: String,
@@ -289,7 +809,7 @@ pub(crate) mod test {
     impl Default for AccessToken {
         fn default() -> Self {
             AccessToken {
-                access_token: "TOKEN".to_owned(),
+                access_token: "TOKEN".into(),
                 expires_at: 1337,
             }
         }
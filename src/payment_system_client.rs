@@ -1,19 +1,18 @@
-use super::{client_base, endpoint, error, extractor, models};
-use hyper;
+use super::{backend, call, client_base, endpoint, error, extractor, models};
 
-pub struct PaymentSystemClient<TConnector> {
-    base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+pub struct PaymentSystemClient<TBackend> {
+    base: std::sync::Arc<client_base::ClientBase<TBackend>>,
     payment_system: std::sync::Arc<endpoint::PaymentSystem>,
 }
 
-impl<'a, TConnector> PaymentSystemClient<TConnector>
+impl<'a, TBackend> PaymentSystemClient<TBackend>
 where
-    TConnector: hyper::client::connect::Connect + Send + Sync + Clone + 'static,
+    TBackend: backend::HttpBackend,
 {
     pub fn new(
-        base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+        base: std::sync::Arc<client_base::ClientBase<TBackend>>,
         payment_system: std::sync::Arc<endpoint::PaymentSystem>,
-    ) -> PaymentSystemClient<TConnector> {
+    ) -> PaymentSystemClient<TBackend> {
         PaymentSystemClient {
             base,
             payment_system,
@@ -35,10 +34,12 @@ where
                     .expect("Failed to build /payment-system/estimate request!")
             },
         );
+        let call =
+            call::Call::new("payment_system.get_list_of_estimated_payment_systems");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| {
+                    .call_to_endpoint(call, request, |body| {
                         extractor::extract_fiat_estimations(body)
                     })
                     .await
@@ -59,10 +60,11 @@ where
                     .expect("Failed to build /payment-system/id request!")
             },
         );
+        let call = call::Call::new("payment_system.get_payment_system_by_id");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| {
+                    .call_to_endpoint(call, request, |body| {
                         extractor::extract_payment_system(body)
                     })
                     .await
@@ -0,0 +1,351 @@
+//! Realized profit and loss and cost basis of the trade history.
+//!
+//! Every pair `left/right` is a position in `left` valued in `right`. Buys add
+//! the received amount to the inventory at the paid amount, sells realize the
+//! received amount against the cost of the sold lots. Fees are already part of
+//! the paid and received amounts and are reported separately, valued in
+//! `right`.
+use super::coin;
+use super::export;
+use super::models;
+use super::models::typed;
+use rust_decimal::Decimal;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Which lots a sell is matched with.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    Fifo,
+    Lifo,
+    AverageCost,
+}
+
+/// A trade reduced to what the calculation needs, see `typed::TypedTrade`
+/// for the meaning of the amounts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub id: u32,
+    pub pair: coin::CoinPair,
+    pub side: typed::Side,
+    pub amount: Decimal,
+    pub rate: Decimal,
+    pub fee: Decimal,
+    pub received_amount: Decimal,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<&typed::TypedTrade> for Fill {
+    fn from(trade: &typed::TypedTrade) -> Fill {
+        Fill {
+            id: trade.id,
+            pair: trade.order.pair.clone(),
+            side: trade.side,
+            amount: trade.amount,
+            rate: trade.order.rate,
+            fee: trade.fee,
+            received_amount: trade.received_amount,
+            created_at: trade.created_at,
+        }
+    }
+}
+
+impl TryFrom<models::Trade> for Fill {
+    type Error = typed::ModelError;
+
+    fn try_from(trade: models::Trade) -> Result<Fill, typed::ModelError> {
+        Ok(Fill::from(&typed::TypedTrade::try_from(trade)?))
+    }
+}
+
+/// Reads back a row of `export::Exporter::export_trades`.
+impl TryFrom<&export::TradeRecord> for Fill {
+    type Error = typed::ModelError;
+
+    fn try_from(record: &export::TradeRecord) -> Result<Fill, typed::ModelError> {
+        let error = |field: &'static str, value: &str| typed::ModelError {
+            field,
+            value: value.to_owned(),
+        };
+        let decimal = |field: &'static str, value: &str| {
+            Decimal::from_str(value).map_err(|_| error(field, value))
+        };
+        Ok(Fill {
+            id: record.trade_id,
+            pair: coin::CoinPair::parse(&record.pair)
+                .ok_or_else(|| error("pair", &record.pair))?,
+            side: record.side.ok_or_else(|| error("side", ""))?,
+            amount: decimal("amount", &record.amount)?,
+            rate: decimal("rate", &record.rate)?,
+            fee: decimal("fee", &record.fee)?,
+            received_amount: decimal("received_amount", &record.received_amount)?,
+            created_at: chrono::DateTime::parse_from_rfc3339(&record.created_at)
+                .map_err(|_| error("created_at", &record.created_at))?
+                .with_timezone(&chrono::Utc),
+        })
+    }
+}
+
+/// Result for a single pair. Amounts are in `quote` unless said otherwise.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct PairPnl {
+    pub pair: String,
+    pub base: String,
+    pub quote: String,
+    pub trades: usize,
+    pub realized: Decimal,
+    pub fees: Decimal,
+    /// Quantity of `base` bought.
+    pub bought: Decimal,
+    /// Quantity of `base` sold.
+    pub sold: Decimal,
+    /// Quantity of `base` left from the buys.
+    pub inventory: Decimal,
+    /// Cost of the `inventory`.
+    pub cost_basis: Decimal,
+    /// Quantity of `base` sold without a matching buy, realized at zero cost.
+    pub unmatched: Decimal,
+}
+
+/// Pair results summed by their quote coin.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct CoinPnl {
+    pub coin: String,
+    pub realized: Decimal,
+    pub fees: Decimal,
+    pub cost_basis: Decimal,
+}
+
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct PnlReport {
+    pub method: Method,
+    pub pairs: Vec<PairPnl>,
+    pub coins: Vec<CoinPnl>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Lot {
+    quantity: Decimal,
+    cost: Decimal,
+}
+
+struct Position {
+    method: Method,
+    lots: std::collections::VecDeque<Lot>,
+    pnl: PairPnl,
+}
+
+impl Position {
+    fn new(method: Method, pair: &coin::CoinPair) -> Position {
+        Position {
+            method,
+            lots: Default::default(),
+            pnl: PairPnl {
+                pair: String::from(pair),
+                base: pair.left.to_string(),
+                quote: pair.right.to_string(),
+                trades: 0,
+                realized: Decimal::ZERO,
+                fees: Decimal::ZERO,
+                bought: Decimal::ZERO,
+                sold: Decimal::ZERO,
+                inventory: Decimal::ZERO,
+                cost_basis: Decimal::ZERO,
+                unmatched: Decimal::ZERO,
+            },
+        }
+    }
+
+    fn apply(&mut self, fill: &Fill) {
+        self.pnl.trades += 1;
+        match fill.side {
+            typed::Side::Buy => {
+                self.pnl.fees += fill.fee * fill.rate;
+                self.buy(fill.received_amount, fill.amount);
+            }
+            typed::Side::Sell => {
+                self.pnl.fees += fill.fee;
+                let cost = self.sell(fill.amount);
+                self.pnl.realized += fill.received_amount - cost;
+            }
+        }
+    }
+
+    fn buy(&mut self, quantity: Decimal, cost: Decimal) {
+        self.pnl.bought += quantity;
+        self.pnl.inventory += quantity;
+        self.pnl.cost_basis += cost;
+        match (self.method, self.lots.back_mut()) {
+            (Method::AverageCost, Some(lot)) => {
+                lot.quantity += quantity;
+                lot.cost += cost;
+            }
+            _ => self.lots.push_back(Lot { quantity, cost }),
+        }
+    }
+
+    /// Removes the quantity from the lots and returns its cost.
+    fn sell(&mut self, quantity: Decimal) -> Decimal {
+        self.pnl.sold += quantity;
+        let mut remaining = quantity;
+        let mut cost = Decimal::ZERO;
+        while remaining > Decimal::ZERO {
+            let lot = match self.method {
+                Method::Fifo | Method::AverageCost => self.lots.front_mut(),
+                Method::Lifo => self.lots.back_mut(),
+            };
+            let lot = match lot {
+                Some(lot) => lot,
+                None => break,
+            };
+            if lot.quantity <= remaining {
+                remaining -= lot.quantity;
+                cost += lot.cost;
+                match self.method {
+                    Method::Lifo => self.lots.pop_back(),
+                    Method::Fifo | Method::AverageCost => self.lots.pop_front(),
+                };
+            } else {
+                let part = lot.cost * remaining / lot.quantity;
+                lot.quantity -= remaining;
+                lot.cost -= part;
+                cost += part;
+                remaining = Decimal::ZERO;
+            }
+        }
+        self.pnl.unmatched += remaining;
+        self.pnl.inventory = self.lots.iter().map(|lot| lot.quantity).sum();
+        self.pnl.cost_basis = self.lots.iter().map(|lot| lot.cost).sum();
+        cost
+    }
+}
+
+/// Replays the fills in the order they happened, regardless of the order
+/// they are given in.
+pub fn calculate<TFills>(fills: TFills, method: Method) -> PnlReport
+where
+    TFills: IntoIterator<Item = Fill>,
+{
+    let mut fills: Vec<Fill> = fills.into_iter().collect();
+    fills.sort_by(|left, right| {
+        (left.created_at, left.id).cmp(&(right.created_at, right.id))
+    });
+    let mut positions: std::collections::BTreeMap<String, Position> = Default::default();
+    for fill in fills.iter() {
+        positions
+            .entry(String::from(&fill.pair))
+            .or_insert_with(|| Position::new(method, &fill.pair))
+            .apply(fill);
+    }
+    let pairs: Vec<PairPnl> = positions
+        .into_values()
+        .map(|position| position.pnl)
+        .collect();
+    let mut coins: std::collections::BTreeMap<&str, CoinPnl> = Default::default();
+    for pair in pairs.iter() {
+        let coin = coins.entry(&pair.quote).or_insert_with(|| CoinPnl {
+            coin: pair.quote.clone(),
+            realized: Decimal::ZERO,
+            fees: Decimal::ZERO,
+            cost_basis: Decimal::ZERO,
+        });
+        coin.realized += pair.realized;
+        coin.fees += pair.fees;
+        coin.cost_basis += pair.cost_basis;
+    }
+    let coins = coins.into_values().collect();
+    PnlReport {
+        method,
+        pairs,
+        coins,
+    }
+}
+
+/// `calculate` for trades as returned by `get_trades`.
+pub fn from_trades<TTrades>(
+    trades: TTrades,
+    method: Method,
+) -> Result<PnlReport, typed::ModelError>
+where
+    TTrades: IntoIterator<Item = models::Trade>,
+{
+    let fills = trades
+        .into_iter()
+        .map(Fill::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(calculate(fills, method))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn trade(
+        id: u32,
+        is_owner: bool,
+        amount: &str,
+        rate: &str,
+        fee: &str,
+        received_amount: &str,
+    ) -> models::Trade {
+        let created_at = format!("2021-03-0{}T10:00:00Z", id);
+        models::Trade {
+            amount: amount.to_owned(),
+            created_at: created_at.clone(),
+            fee: fee.to_owned(),
+            id,
+            order: models::Order {
+                created_at: created_at.clone(),
+                is_owner: Some(is_owner),
+                pair: "btc/usdt_erc20".to_owned(),
+                rate: rate.to_owned(),
+                updated_at: created_at.clone(),
+                ..models::Order::default()
+            },
+            received_amount: received_amount.to_owned(),
+            updated_at: created_at,
+        }
+    }
+
+    #[test]
+    fn realized_pnl_by_method() {
+        // Newest first, as returned by `get_trades`.
+        let trades = vec![
+            trade(3, true, "1", "300", "10", "290"),
+            trade(2, false, "200", "200", "0.01", "0.99"),
+            trade(1, false, "100", "100", "0", "1"),
+        ];
+        let pnl = |method| {
+            let report = from_trades(trades.clone(), method).unwrap();
+            assert_eq!(report.pairs.len(), 1);
+            assert_eq!(report.coins.len(), 1);
+            assert_eq!(report.coins[0].coin, "usdt_erc20");
+            assert_eq!(report.coins[0].realized, report.pairs[0].realized);
+            report.pairs[0].clone()
+        };
+        let fifo = pnl(Method::Fifo);
+        assert_eq!(fifo.realized, Decimal::from(190));
+        assert_eq!(fifo.inventory, Decimal::new(99, 2));
+        assert_eq!(fifo.cost_basis, Decimal::from(200));
+        assert_eq!(fifo.fees, Decimal::from(12));
+        assert_eq!(fifo.bought, Decimal::new(199, 2));
+        assert_eq!(fifo.sold, Decimal::from(1));
+        assert_eq!(fifo.unmatched, Decimal::ZERO);
+        let lifo = pnl(Method::Lifo);
+        assert_eq!(lifo.realized, Decimal::from(89));
+        assert_eq!(lifo.inventory, Decimal::new(99, 2));
+        assert_eq!(lifo.cost_basis, Decimal::from(99));
+        let average = pnl(Method::AverageCost);
+        assert_eq!(average.realized.round_dp(4), Decimal::new(1392462, 4));
+        assert_eq!(average.cost_basis.round_dp(4), Decimal::new(1492462, 4));
+        let oversold =
+            from_trades(vec![trade(1, true, "2", "100", "0", "200")], Method::Fifo)
+                .unwrap();
+        assert_eq!(oversold.pairs[0].unmatched, Decimal::from(2));
+        assert_eq!(oversold.pairs[0].realized, Decimal::from(200));
+        let json = serde_json::to_value(&oversold).unwrap();
+        assert_eq!(json["method"], "fifo");
+        assert_eq!(json["pairs"][0]["realized"], "200");
+    }
+}
@@ -1,23 +1,24 @@
+use super::backend;
+use super::call;
 use super::client_base;
 use super::endpoint;
 use super::error;
 use super::extractor;
 use super::models;
-use hyper;
 
-pub struct ProfileClient<TConnector> {
-    base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+pub struct ProfileClient<TBackend> {
+    base: std::sync::Arc<client_base::ClientBase<TBackend>>,
     profile: std::sync::Arc<endpoint::Profile>,
 }
 
-impl<TConnector> ProfileClient<TConnector>
+impl<TBackend> ProfileClient<TBackend>
 where
-    TConnector: hyper::client::connect::Connect + Send + Sync + Clone + 'static,
+    TBackend: backend::HttpBackend,
 {
     pub fn new(
-        base: std::sync::Arc<client_base::ClientBase<TConnector>>,
+        base: std::sync::Arc<client_base::ClientBase<TBackend>>,
         profile: std::sync::Arc<endpoint::Profile>,
-    ) -> ProfileClient<TConnector> {
+    ) -> ProfileClient<TBackend> {
         ProfileClient { base, profile }
     }
 
@@ -26,16 +27,8 @@ where
             .profile
             .get_access_token(&self.base.api_context)
             .expect("Failed to create access_token request!");
-        let auth_response = self.base.client.request(auth_request).await.unwrap();
-        if error::Error::is_error_code(auth_response.status()) {
-            let error =
-                error::Error::to_error(auth_response.status(), auth_response.into_body())
-                    .await;
-            Err(error)
-        } else {
-            let auth_body = auth_response.into_body();
-            Ok(extractor::extract_access_token(auth_body).await.unwrap())
-        }
+        let (_header, auth_body) = self.base.transport.send(auth_request).await?;
+        Ok(extractor::extract_access_token(auth_body).await.unwrap())
     }
 
     pub async fn get_account_information(
@@ -48,10 +41,11 @@ where
                         .get_me(&access_token)
                         .expect("Failed to build /me request")
                 });
+        let call = call::Call::new("profile.get_account_information");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_basic_info(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_basic_info(body))
                     .await
             }
             Err(error) => Err(error),
@@ -66,10 +60,11 @@ where
                         .get_balance(&access_token)
                         .expect("Failed to build /balance request")
                 });
+        let call = call::Call::new("profile.get_balance_summary");
         match request.await {
             Ok(request) => {
                 self.base
-                    .call_to_endpoint(request, |body| extractor::extract_balance(body))
+                    .call_to_endpoint(call, request, |body| extractor::extract_balance(body))
                     .await
             }
             Err(error) => Err(error),
@@ -81,7 +76,7 @@ where
 mod test {
     use crate::test::*;
 
-    fn create_profile_client(test_case: &TestCase) -> super::ProfileClient<Connector> {
+    fn create_profile_client(test_case: &TestCase) -> super::ProfileClient<Backend> {
         let profile = crate::endpoint::Profile::new(&test_case.base_context);
         let profile = std::sync::Arc::new(profile);
         super::ProfileClient::new(test_case.client_base.clone(), profile)
@@ -0,0 +1,127 @@
+use super::clock;
+
+/// Token bucket limiting how many requests are sent per second.
+///
+/// Shared by every request of a client, including the access token request.
+/// Callers wait in `acquire` until a token is available.
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    clock: std::sync::Arc<dyn clock::Clock>,
+    state: std::sync::Mutex<State>,
+}
+
+struct State {
+    tokens: f64,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl RateLimiter {
+    /// Allows `requests_per_second` on average and up to `burst` at once.
+    pub fn new(requests_per_second: f64, burst: u32) -> RateLimiter {
+        RateLimiter::with_clock(
+            requests_per_second,
+            burst,
+            std::sync::Arc::new(clock::SystemClock),
+        )
+    }
+
+    /// Refills the bucket by `clock`. Time going backwards adds no tokens.
+    pub fn with_clock(
+        requests_per_second: f64,
+        burst: u32,
+        clock: std::sync::Arc<dyn clock::Clock>,
+    ) -> RateLimiter {
+        assert!(requests_per_second > 0.0, "Rate limit must be positive");
+        let burst = f64::from(burst.max(1));
+        let updated_at = clock.now();
+        RateLimiter {
+            requests_per_second,
+            burst,
+            clock,
+            state: std::sync::Mutex::new(State {
+                tokens: burst,
+                updated_at,
+            }),
+        }
+    }
+
+    pub fn requests_per_second(&self) -> f64 {
+        self.requests_per_second
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst as u32
+    }
+
+    /// Takes a token, or returns how long to wait until one is available.
+    pub fn try_acquire(&self) -> Result<(), std::time::Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.now();
+        let elapsed = now
+            .signed_duration_since(state.updated_at)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64();
+        state.tokens =
+            (state.tokens + elapsed * self.requests_per_second).min(self.burst);
+        state.updated_at = state.updated_at.max(now);
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - state.tokens;
+            Err(std::time::Duration::from_secs_f64(
+                missing / self.requests_per_second,
+            ))
+        }
+    }
+
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bursts_then_waits() {
+        let clock = std::sync::Arc::new(clock::ManualClock::new(chrono::Utc::now()));
+        let limiter = RateLimiter::with_clock(10.0, 2, clock.clone());
+        assert!(limiter.try_acquire().is_ok());
+        assert!(limiter.try_acquire().is_ok());
+        assert_eq!(
+            limiter.try_acquire(),
+            Err(std::time::Duration::from_millis(100))
+        );
+        clock.advance(chrono::Duration::milliseconds(60));
+        assert_eq!(
+            limiter.try_acquire(),
+            Err(std::time::Duration::from_millis(40))
+        );
+        clock.advance(chrono::Duration::milliseconds(40));
+        assert!(limiter.try_acquire().is_ok());
+        clock.advance(chrono::Duration::seconds(10));
+        assert!(limiter.try_acquire().is_ok());
+        assert!(limiter.try_acquire().is_ok());
+        assert!(limiter.try_acquire().is_err());
+    }
+
+    #[test]
+    fn clock_going_backwards_adds_no_tokens() {
+        let clock = std::sync::Arc::new(clock::ManualClock::new(chrono::Utc::now()));
+        let limiter = RateLimiter::with_clock(10.0, 1, clock.clone());
+        assert!(limiter.try_acquire().is_ok());
+        clock.advance(chrono::Duration::seconds(-10));
+        assert!(limiter.try_acquire().is_err());
+        clock.advance(chrono::Duration::seconds(10));
+        assert_eq!(
+            limiter.try_acquire(),
+            Err(std::time::Duration::from_millis(100))
+        );
+    }
+}
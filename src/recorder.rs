@@ -0,0 +1,458 @@
+//! Recording of the order books over time.
+//!
+//! Every round appends one gzip member of JSON Lines to the output, so a file
+//! stays readable up to the last finished round even if the recorder is
+//! killed. A line is either the full book of a pair or, in `Mode::Diffs`, the
+//! orders changed and removed since the previous round. The first round of a
+//! recorder always writes full books.
+use super::backend;
+use super::coin;
+use super::error;
+use super::exchange_client;
+use super::models;
+use super::simulator;
+use std::io::BufRead;
+use std::io::Write;
+
+pub const DEFAULT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Snapshots,
+    Diffs,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Snapshot {
+        time: chrono::DateTime<chrono::Utc>,
+        pair: String,
+        orders: models::Orders,
+    },
+    Diff {
+        time: chrono::DateTime<chrono::Utc>,
+        pair: String,
+        /// Orders which are new or changed.
+        changed: models::Orders,
+        removed: Vec<u32>,
+    },
+}
+
+impl Record {
+    pub fn time(&self) -> chrono::DateTime<chrono::Utc> {
+        match self {
+            Record::Snapshot { time, .. } | Record::Diff { time, .. } => *time,
+        }
+    }
+
+    pub fn pair(&self) -> &str {
+        match self {
+            Record::Snapshot { pair, .. } | Record::Diff { pair, .. } => pair,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RecorderError {
+    Api(error::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// A diff of a pair without a preceding snapshot.
+    MissingSnapshot(String),
+    /// A record of a pair which is not a valid `coin::CoinPair`.
+    InvalidPair(String),
+}
+
+impl std::fmt::Display for RecorderError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecorderError::Api(error) => {
+                write!(formatter, "Failed to fetch order book: {}", error)
+            }
+            RecorderError::Io(error) => {
+                write!(formatter, "Failed to access recording: {}", error)
+            }
+            RecorderError::Json(error) => write!(formatter, "Invalid record: {}", error),
+            RecorderError::MissingSnapshot(pair) => {
+                write!(formatter, "Diff of {} without a snapshot", pair)
+            }
+            RecorderError::InvalidPair(pair) => {
+                write!(formatter, "Invalid pair in record: {:?}", pair)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecorderError {}
+
+impl From<error::Error> for RecorderError {
+    fn from(error: error::Error) -> RecorderError {
+        RecorderError::Api(error)
+    }
+}
+
+impl From<std::io::Error> for RecorderError {
+    fn from(error: std::io::Error) -> RecorderError {
+        RecorderError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for RecorderError {
+    fn from(error: serde_json::Error) -> RecorderError {
+        RecorderError::Json(error)
+    }
+}
+
+type Orders = std::collections::BTreeMap<u32, models::Order>;
+
+fn to_map(orders: models::Orders) -> Orders {
+    orders.into_iter().map(|order| (order.id, order)).collect()
+}
+
+fn same(left: &models::Order, right: &models::Order) -> bool {
+    left.amount == right.amount
+        && left.rate == right.rate
+        && left.status == right.status
+        && left.updated_at == right.updated_at
+}
+
+pub struct Recorder<TBackend> {
+    exchange: exchange_client::ExchangeClient<TBackend>,
+    pairs: Vec<coin::CoinPair>,
+    mode: Mode,
+    interval: std::time::Duration,
+    previous: std::collections::HashMap<coin::CoinPair, Orders>,
+}
+
+impl<TBackend> Recorder<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    /// Records `pairs` and their reverses.
+    pub fn new(
+        exchange: exchange_client::ExchangeClient<TBackend>,
+        pairs: Vec<coin::CoinPair>,
+        mode: Mode,
+    ) -> Recorder<TBackend> {
+        let mut recorded: Vec<coin::CoinPair> = Vec::new();
+        for pair in pairs
+            .iter()
+            .flat_map(|pair| vec![pair.clone(), pair.reversed()])
+        {
+            if !recorded.contains(&pair) {
+                recorded.push(pair);
+            }
+        }
+        Recorder {
+            exchange,
+            pairs: recorded,
+            mode,
+            interval: DEFAULT_INTERVAL,
+            previous: Default::default(),
+        }
+    }
+
+    pub fn interval(mut self, interval: std::time::Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn pairs(&self) -> &[coin::CoinPair] {
+        &self.pairs
+    }
+
+    /// Fetches every book once and appends the records to `writer`. Returns
+    /// the number of records, unchanged books are skipped in `Mode::Diffs`.
+    pub async fn record<W: Write>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<usize, RecorderError> {
+        let mut books = Vec::with_capacity(self.pairs.len());
+        for pair in self.pairs.iter() {
+            books.push((
+                pair.clone(),
+                self.exchange.get_order_book(pair.clone()).await?,
+            ));
+        }
+        let time = self.exchange.server_time();
+        let mut encoder =
+            flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut records = 0;
+        for (pair, orders) in books {
+            let current = to_map(orders);
+            let record = match (self.mode, self.previous.get(&pair)) {
+                (Mode::Diffs, Some(previous)) => {
+                    let changed: models::Orders = current
+                        .values()
+                        .filter(|order| {
+                            previous
+                                .get(&order.id)
+                                .is_none_or(|previous| !same(previous, order))
+                        })
+                        .cloned()
+                        .collect();
+                    let removed: Vec<u32> = previous
+                        .keys()
+                        .filter(|id| !current.contains_key(id))
+                        .copied()
+                        .collect();
+                    if changed.is_empty() && removed.is_empty() {
+                        None
+                    } else {
+                        Some(Record::Diff {
+                            time,
+                            pair: String::from(&pair),
+                            changed,
+                            removed,
+                        })
+                    }
+                }
+                _ => Some(Record::Snapshot {
+                    time,
+                    pair: String::from(&pair),
+                    orders: current.values().cloned().collect(),
+                }),
+            };
+            if let Some(record) = record {
+                serde_json::to_writer(&mut encoder, &record)?;
+                encoder.write_all(b"\n")?;
+                records += 1;
+            }
+            self.previous.insert(pair, current);
+        }
+        writer.write_all(&encoder.finish()?)?;
+        writer.flush()?;
+        Ok(records)
+    }
+
+    /// Records every `interval` until `shutdown` completes.
+    ///
+    /// Failed fetches are logged and the round is skipped, write errors stop
+    /// the recording.
+    pub async fn run<W, F>(
+        &mut self,
+        writer: &mut W,
+        shutdown: F,
+    ) -> Result<(), RecorderError>
+    where
+        W: Write,
+        F: futures::Future<Output = ()>,
+    {
+        futures::pin_mut!(shutdown);
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            let round = async {
+                interval.tick().await;
+                self.record(writer).await
+            };
+            futures::pin_mut!(round);
+            match futures::future::select(shutdown.as_mut(), round).await {
+                futures::future::Either::Left(_) => return Ok(()),
+                futures::future::Either::Right((Err(RecorderError::Api(error)), _)) => {
+                    log::warn!("Order book recording failed: {}", error);
+                }
+                futures::future::Either::Right((Err(error), _)) => return Err(error),
+                futures::future::Either::Right((Ok(_), _)) => {}
+            }
+        }
+    }
+}
+
+/// Books rebuilt from a recording.
+pub struct BookReader {
+    records: Vec<Record>,
+}
+
+impl BookReader {
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<BookReader, RecorderError> {
+        BookReader::from_reader(std::fs::File::open(path)?)
+    }
+
+    /// A round cut short at the end of the input is ignored.
+    pub fn from_reader<R: std::io::Read>(reader: R) -> Result<BookReader, RecorderError> {
+        let reader = std::io::BufReader::new(flate2::read::MultiGzDecoder::new(reader));
+        let mut records = Vec::new();
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error.into()),
+            };
+            if !line.is_empty() {
+                records.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(BookReader { records })
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Book of `pair` as last recorded at or before `time`, ordered by id.
+    /// None before the first record of the pair.
+    pub fn book_at(
+        &self,
+        pair: &coin::CoinPair,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<models::Orders>, RecorderError> {
+        let pair = String::from(pair);
+        let mut book = None;
+        for record in self.records.iter() {
+            if record.time() > time {
+                break;
+            }
+            if record.pair() == pair {
+                book = Some(apply(book, record)?);
+            }
+        }
+        Ok(book.map(|book| book.into_values().collect()))
+    }
+
+    /// Every recorded state of every book in time order, e.g. to replay them
+    /// with `simulator::Simulator`.
+    pub fn snapshots(&self) -> Result<Vec<simulator::Snapshot>, RecorderError> {
+        let mut books: std::collections::HashMap<&str, Orders> = Default::default();
+        let mut snapshots = Vec::with_capacity(self.records.len());
+        for record in self.records.iter() {
+            let pair = coin::CoinPair::parse(record.pair())
+                .ok_or_else(|| RecorderError::InvalidPair(record.pair().to_owned()))?;
+            let book = apply(books.remove(record.pair()), record)?;
+            snapshots.push(simulator::Snapshot {
+                time: record.time(),
+                pair,
+                orders: book.values().cloned().collect(),
+            });
+            books.insert(record.pair(), book);
+        }
+        Ok(snapshots)
+    }
+}
+
+fn apply(book: Option<Orders>, record: &Record) -> Result<Orders, RecorderError> {
+    match record {
+        Record::Snapshot { orders, .. } => Ok(to_map(orders.clone())),
+        Record::Diff {
+            pair,
+            changed,
+            removed,
+            ..
+        } => {
+            let mut book =
+                book.ok_or_else(|| RecorderError::MissingSnapshot(pair.clone()))?;
+            for id in removed.iter() {
+                book.remove(id);
+            }
+            book.extend(changed.iter().map(|order| (order.id, order.clone())));
+            Ok(book)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    fn order(id: u32, amount: &str) -> models::Order {
+        models::Order {
+            amount: amount.to_owned(),
+            id,
+            pair: "btc/usdt_erc20".to_owned(),
+            ..models::Order::default()
+        }
+    }
+
+    fn mock_book<'a>(
+        case: &'a TestCase,
+        pair: &str,
+        orders: &[models::Order],
+    ) -> httpmock::MockRef<'a> {
+        let orders = serde_json::to_string(orders).expect(SERDE_ERROR);
+        case.server.mock(|when, then| {
+            default_get_when(when)
+                .path("/exchange/orders")
+                .query_param("pair", pair);
+            default_then_content_type(then).status(200).body(orders);
+        })
+    }
+
+    #[test]
+    fn record_and_rebuild() {
+        let case = TestCase::new();
+        let _access_token_mock = case.mock_access_token();
+        let pair = coin::CoinPair::new(coin::Coin::BTC, coin::Coin::USDT);
+        let mut recorder = Recorder::new(
+            crate::exchange_client::ExchangeClient::new(
+                case.client_base.clone(),
+                std::sync::Arc::new(crate::endpoint::Exchange::new(&case.base_context)),
+            ),
+            vec![pair.clone(), pair.reversed()],
+            Mode::Diffs,
+        );
+        assert_eq!(recorder.pairs(), &[pair.clone(), pair.reversed()]);
+        let mut output = Vec::new();
+        let mut book_mock =
+            mock_book(&case, "btc/usdt_erc20", &[order(1, "1"), order(2, "2")]);
+        let reversed_mock = mock_book(&case, "usdt_erc20/btc", &[]);
+        let records = tokio_test::block_on(recorder.record(&mut output)).unwrap();
+        assert_eq!(records, 2);
+        book_mock.delete();
+        let book_mock =
+            mock_book(&case, "btc/usdt_erc20", &[order(2, "1.5"), order(3, "3")]);
+        let records = tokio_test::block_on(recorder.record(&mut output)).unwrap();
+        assert_eq!(records, 1);
+        book_mock.assert();
+        reversed_mock.assert_hits(2);
+        // A round cut short is dropped.
+        output.extend_from_slice(&[0x1f, 0x8b, 0x08]);
+
+        let reader = BookReader::from_reader(output.as_slice()).unwrap();
+        let records = reader.records();
+        assert_eq!(records.len(), 3);
+        match &records[2] {
+            Record::Diff {
+                changed, removed, ..
+            } => {
+                let changed: Vec<u32> = changed.iter().map(|order| order.id).collect();
+                assert_eq!(changed, vec![2, 3]);
+                assert_eq!(removed, &vec![1]);
+            }
+            record => panic!("Unexpected record: {:?}", record),
+        }
+        let book = reader.book_at(&pair, records[2].time()).unwrap().unwrap();
+        let book: Vec<(u32, &str)> = book
+            .iter()
+            .map(|order| (order.id, order.amount.as_str()))
+            .collect();
+        assert_eq!(book, vec![(2, "1.5"), (3, "3")]);
+        let before = records[0].time() - chrono::Duration::seconds(1);
+        assert!(reader.book_at(&pair, before).unwrap().is_none());
+        let snapshots = reader.snapshots().unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[0].orders.len(), 2);
+        assert_eq!(snapshots[1].pair, pair.reversed());
+        assert_eq!(snapshots[2].orders[0].amount, "1.5");
+    }
+
+    #[test]
+    fn snapshots_reject_invalid_pair() {
+        let record = Record::Snapshot {
+            time: chrono::Utc::now(),
+            pair: "btc".to_owned(),
+            orders: Vec::new(),
+        };
+        let mut encoder =
+            flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        serde_json::to_writer(&mut encoder, &record).unwrap();
+        encoder.write_all(b"\n").unwrap();
+        let output = encoder.finish().unwrap();
+        let reader = BookReader::from_reader(output.as_slice()).unwrap();
+        match reader.snapshots() {
+            Err(RecorderError::InvalidPair(pair)) => assert_eq!(pair, "btc"),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::backoff;
+use super::error;
+
+/// When and how often failed requests are sent again.
+///
+/// Connection failures, timeouts, `5xx` responses and `429 Too Many Requests`
+/// are retried. Other errors are returned right away. Requests with
+/// non-idempotent methods, like `POST`, are only retried when
+/// `retry_non_idempotent` is set, since the server may have processed the
+/// failed attempt.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Attempts made after the first one. Zero disables retries.
+    pub max_retries: u32,
+    pub initial_backoff: std::time::Duration,
+    pub max_backoff: std::time::Duration,
+    pub retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn new(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    pub fn backoff(&self) -> backoff::Backoff {
+        backoff::Backoff::new(self.initial_backoff, self.max_backoff)
+    }
+
+    pub fn allows(&self, method: &http::Method) -> bool {
+        self.max_retries > 0 && (self.retry_non_idempotent || is_idempotent(method))
+    }
+
+    /// Returns the delay before the next attempt, or `None` if `error` is
+    /// final. `retries` is the number of retries made so far.
+    pub fn delay(
+        &self,
+        retries: u32,
+        retryable: bool,
+        error: &error::Error,
+        backoff: &mut backoff::Backoff,
+    ) -> Option<std::time::Duration> {
+        if retries >= self.max_retries || !retryable {
+            return None;
+        }
+        let delay = backoff.next_delay();
+        match error {
+            error::Error::RateLimitedError { retry_after } if *retry_after > 0 => {
+                Some(delay.max(std::time::Duration::from_secs(*retry_after as u64)))
+            }
+            _ => Some(delay),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: std::time::Duration::from_millis(200),
+            max_backoff: std::time::Duration::from_secs(10),
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+fn is_idempotent(method: &http::Method) -> bool {
+    matches!(
+        *method,
+        http::Method::GET
+            | http::Method::HEAD
+            | http::Method::OPTIONS
+            | http::Method::PUT
+            | http::Method::DELETE
+    )
+}
+
+/// Whether a response with `status` is worth another attempt.
+pub fn is_retryable_status(status: http::StatusCode) -> bool {
+    status.is_server_error() || status == http::StatusCode::TOO_MANY_REQUESTS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn honours_retry_after() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: std::time::Duration::from_millis(100),
+            max_backoff: std::time::Duration::from_secs(1),
+            retry_non_idempotent: false,
+        };
+        let mut backoff = policy.backoff();
+        let rate_limited = error::Error::RateLimitedError { retry_after: 3 };
+        assert_eq!(
+            policy.delay(0, true, &rate_limited, &mut backoff),
+            Some(std::time::Duration::from_secs(3))
+        );
+        assert_eq!(
+            policy.delay(1, true, &error::Error::InternalServerError, &mut backoff),
+            Some(std::time::Duration::from_millis(200))
+        );
+        assert_eq!(
+            policy.delay(2, true, &error::Error::InternalServerError, &mut backoff),
+            None
+        );
+        assert_eq!(
+            policy.delay(0, false, &error::Error::NotFoundError, &mut backoff),
+            None
+        );
+        assert!(policy.allows(&http::Method::GET));
+        assert!(!policy.allows(&http::Method::POST));
+        assert!(!RetryPolicy::none().allows(&http::Method::GET));
+    }
+}
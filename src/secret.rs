@@ -0,0 +1,111 @@
+use serde;
+use zeroize::Zeroize;
+
+/// Holds an API key or an access token.
+///
+/// `Debug` and `Display` never show the value and the memory is wiped on
+/// drop. Use `expose` to get the value for building a request.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub const REDACTED: &'static str = "[REDACTED]";
+
+    pub fn new(value: String) -> Secret {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Secret {
+        Secret::new(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Secret {
+        Secret::new(value.to_owned())
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "Secret({})", Self::REDACTED)
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(Self::REDACTED)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Returns a copy of the headers that is safe to log.
+///
+/// Values of `Authorization`, `Proxy-Authorization`, `Cookie` and of any
+/// header marked as sensitive are replaced with `[REDACTED]`.
+pub fn redact_headers(headers: &http::HeaderMap) -> http::HeaderMap {
+    let mut redacted = http::HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers.iter() {
+        let value = if value.is_sensitive()
+            || name == http::header::AUTHORIZATION
+            || name == http::header::PROXY_AUTHORIZATION
+            || name == http::header::COOKIE
+        {
+            http::HeaderValue::from_static(Secret::REDACTED)
+        } else {
+            value.clone()
+        };
+        redacted.append(name.clone(), value);
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn debug_and_display_are_redacted() {
+        let secret = Secret::from("SECRET");
+        assert_eq!(format!("{}", secret), "[REDACTED]");
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+        assert_eq!(secret.expose(), "SECRET");
+    }
+
+    #[test]
+    fn access_token_debug_is_redacted() {
+        let access_token = crate::models::AccessToken::default();
+        let debug = format!("{:?}", access_token);
+        assert!(!debug.contains("TOKEN"), "{}", debug);
+        let serialized = serde_json::to_string(&access_token).unwrap();
+        assert!(serialized.contains(r#""access_token":"TOKEN""#));
+    }
+
+    #[test]
+    fn authorization_header_is_redacted() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::AUTHORIZATION,
+            http::HeaderValue::from_static("Bearer TOKEN"),
+        );
+        headers.insert(
+            http::header::ACCEPT,
+            http::HeaderValue::from_static("application/json"),
+        );
+        let redacted = redact_headers(&headers);
+        assert_eq!(redacted[http::header::AUTHORIZATION], "[REDACTED]");
+        assert_eq!(redacted[http::header::ACCEPT], "application/json");
+    }
+}
@@ -0,0 +1,772 @@
+//! Deterministic exchange for backtests.
+//!
+//! Replays recorded order book snapshots and matches the orders placed
+//! through `exchange_api::ExchangeApi` against them. Orders of `left/right`
+//! sell `left`, so the book of `right/left` is the other side of a pair.
+//!
+//! Matching follows price-time priority. An order placed or updated into a
+//! crossing book takes liquidity at the rates of the book, resting orders are
+//! filled at their own rate when a later snapshot crosses them. Liquidity
+//! taken from a snapshot stays taken until the next snapshot of its pair.
+use super::balance_watcher;
+use super::coin;
+use super::error;
+use super::exchange_api;
+use super::models;
+use super::models::typed;
+use super::pnl;
+use super::strategy;
+use rust_decimal::Decimal;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Orders of a pair at a moment.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub pair: coin::CoinPair,
+    pub orders: models::Orders,
+}
+
+/// Fees as a share of the gross received amount.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fees {
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Liquidity {
+    Maker,
+    Taker,
+}
+
+#[derive(Clone, Debug)]
+struct BookOrder {
+    id: u32,
+    amount: Decimal,
+    rate: Decimal,
+}
+
+#[derive(Clone, Debug)]
+struct Book {
+    time: chrono::DateTime<chrono::Utc>,
+    pair: coin::CoinPair,
+    orders: Vec<BookOrder>,
+}
+
+#[derive(Clone, Debug)]
+struct Order {
+    id: u32,
+    pair: coin::CoinPair,
+    amount: Decimal,
+    rate: Decimal,
+    initial_amount: Decimal,
+    status: typed::OrderStatus,
+    /// Time priority, renewed by updates.
+    sequence: u64,
+    created_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Order {
+    fn filled_amount(&self) -> Decimal {
+        self.initial_amount - self.amount
+    }
+
+    fn to_model(&self) -> models::Order {
+        models::Order {
+            amount: format(self.amount),
+            created_at: self.created_at.to_rfc3339(),
+            id: self.id,
+            initial_amount: Some(format(self.initial_amount)),
+            is_owner: Some(true),
+            pair: String::from(&self.pair),
+            rate: format(self.rate),
+            status: self.status.to_string(),
+            updated_at: self.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Trade {
+    trade: models::Trade,
+    quantity: Decimal,
+    rate: Decimal,
+    limit: Decimal,
+}
+
+struct State {
+    time: chrono::DateTime<chrono::Utc>,
+    pending: std::collections::VecDeque<Book>,
+    snapshots: usize,
+    books: std::collections::HashMap<coin::CoinPair, Vec<BookOrder>>,
+    orders: Vec<Order>,
+    trades: Vec<Trade>,
+    initial: balance_watcher::Balances,
+    balances: balance_watcher::Balances,
+    sequence: u64,
+}
+
+fn format(value: Decimal) -> String {
+    value.normalize().to_string()
+}
+
+fn parse(value: &str) -> Result<Decimal, error::Error> {
+    match Decimal::from_str(value) {
+        Ok(value) if value > Decimal::ZERO => Ok(value),
+        _ => Err(error::Error::ValidationError),
+    }
+}
+
+impl State {
+    fn holdings(&mut self, coin: &coin::Coin) -> &mut balance_watcher::Holdings {
+        self.balances.entry(coin.clone()).or_default()
+    }
+
+    /// Moves `amount` of `coin` from the available to the held balance.
+    fn hold(&mut self, coin: &coin::Coin, amount: Decimal) -> Result<(), error::Error> {
+        let holdings = self.holdings(coin);
+        if holdings.amount < amount {
+            return Err(error::Error::UnprocessableEntityError);
+        }
+        holdings.amount -= amount;
+        holdings.held += amount;
+        Ok(())
+    }
+
+    fn release(&mut self, coin: &coin::Coin, amount: Decimal) {
+        let holdings = self.holdings(coin);
+        holdings.amount += amount;
+        holdings.held -= amount;
+    }
+
+    fn order_index(&self, id: &str) -> Result<usize, error::Error> {
+        let id = u32::from_str(id).map_err(|_| error::Error::NotFoundError)?;
+        self.orders
+            .iter()
+            .position(|order| order.id == id)
+            .ok_or(error::Error::NotFoundError)
+    }
+
+    fn next_sequence(&mut self) -> u64 {
+        self.sequence += 1;
+        self.sequence
+    }
+
+    fn create(
+        &mut self,
+        pair: coin::CoinPair,
+        amount: &str,
+        rate: &str,
+        fees: &Fees,
+    ) -> Result<models::Order, error::Error> {
+        let amount = parse(amount)?;
+        let rate = parse(rate)?;
+        self.hold(&pair.left, amount)?;
+        let order = Order {
+            id: self.orders.len() as u32 + 1,
+            pair,
+            amount,
+            rate,
+            initial_amount: amount,
+            status: typed::OrderStatus::Active,
+            sequence: self.next_sequence(),
+            created_at: self.time,
+            updated_at: self.time,
+        };
+        self.orders.push(order);
+        let index = self.orders.len() - 1;
+        self.fill(index, Liquidity::Taker, fees);
+        Ok(self.orders[index].to_model())
+    }
+
+    fn update(
+        &mut self,
+        id: &str,
+        update: &models::UpdateOrder,
+        fees: &Fees,
+    ) -> Result<models::Order, error::Error> {
+        let index = self.order_index(id)?;
+        let amount = parse(&update.amount)?;
+        let rate = parse(&update.rate)?;
+        let order = self.orders[index].clone();
+        if order.status != typed::OrderStatus::Active {
+            return Err(error::Error::UnprocessableEntityError);
+        }
+        self.release(&order.pair.left, order.amount);
+        if let Err(error) = self.hold(&order.pair.left, amount) {
+            self.hold(&order.pair.left, order.amount)?;
+            return Err(error);
+        }
+        let sequence = self.next_sequence();
+        let order = &mut self.orders[index];
+        order.initial_amount = order.filled_amount() + amount;
+        order.amount = amount;
+        order.rate = rate;
+        order.sequence = sequence;
+        order.updated_at = self.time;
+        self.fill(index, Liquidity::Taker, fees);
+        Ok(self.orders[index].to_model())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<models::Order, error::Error> {
+        let index = self.order_index(id)?;
+        let order = self.orders[index].clone();
+        if order.status != typed::OrderStatus::Active {
+            return Err(error::Error::UnprocessableEntityError);
+        }
+        self.release(&order.pair.left, order.amount);
+        let order = &mut self.orders[index];
+        order.status = typed::OrderStatus::Canceled;
+        order.updated_at = self.time;
+        Ok(order.to_model())
+    }
+
+    /// Matches an order against the book of the reversed pair.
+    fn fill(&mut self, index: usize, liquidity: Liquidity, fees: &Fees) {
+        let reversed = self.orders[index].pair.reversed();
+        loop {
+            let order = &self.orders[index];
+            if order.status != typed::OrderStatus::Active || order.amount.is_zero() {
+                return;
+            }
+            let book = match self.books.get_mut(&reversed) {
+                Some(book) => book,
+                None => return,
+            };
+            let best = book
+                .iter_mut()
+                .filter(|other| other.amount > Decimal::ZERO)
+                .min_by(|left, right| left.rate.cmp(&right.rate));
+            let other = match best {
+                Some(other) if other.rate * order.rate <= Decimal::ONE => other,
+                _ => return,
+            };
+            let rate = match liquidity {
+                Liquidity::Maker => order.rate,
+                Liquidity::Taker => Decimal::ONE / other.rate,
+            };
+            let capacity = other.amount / rate;
+            let quantity = if capacity <= order.amount {
+                other.amount = Decimal::ZERO;
+                capacity
+            } else {
+                other.amount -= order.amount * rate;
+                order.amount
+            };
+            let fee_share = match liquidity {
+                Liquidity::Maker => fees.maker,
+                Liquidity::Taker => fees.taker,
+            };
+            let gross = quantity * rate;
+            let fee = gross * fee_share;
+            let time = self.time;
+            let order = &mut self.orders[index];
+            order.amount -= quantity;
+            if order.amount.is_zero() {
+                order.status = typed::OrderStatus::Completed;
+            }
+            order.updated_at = time;
+            let order = order.clone();
+            self.holdings(&order.pair.left).held -= quantity;
+            self.holdings(&order.pair.right).amount += gross - fee;
+            self.trades.push(Trade {
+                trade: models::Trade {
+                    amount: format(quantity),
+                    created_at: time.to_rfc3339(),
+                    fee: format(fee),
+                    id: self.trades.len() as u32 + 1,
+                    order: order.to_model(),
+                    received_amount: format(gross - fee),
+                    updated_at: time.to_rfc3339(),
+                },
+                quantity,
+                rate,
+                limit: order.rate,
+            });
+        }
+    }
+
+    /// Applies the snapshots of the next moment and fills the orders they
+    /// cross.
+    fn advance(&mut self, fees: &Fees) -> bool {
+        let time = match self.pending.front() {
+            Some(book) => book.time,
+            None => return false,
+        };
+        self.time = time;
+        let mut pairs = Vec::new();
+        while self.pending.front().map(|book| book.time) == Some(time) {
+            let book = self.pending.pop_front().expect("Checked above");
+            self.snapshots += 1;
+            pairs.push(book.pair.reversed());
+            self.books.insert(book.pair, book.orders);
+        }
+        let mut resting: Vec<usize> = (0..self.orders.len())
+            .filter(|index| pairs.contains(&self.orders[*index].pair))
+            .collect();
+        resting.sort_by_key(|index| {
+            (self.orders[*index].rate, self.orders[*index].sequence)
+        });
+        for index in resting {
+            self.fill(index, Liquidity::Maker, fees);
+        }
+        true
+    }
+}
+
+/// Balance of a coin at the start and the end of a run.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct BalanceChange {
+    pub coin: String,
+    pub start: Decimal,
+    pub end: Decimal,
+    pub change: Decimal,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct RunReport {
+    pub snapshots: usize,
+    pub orders: usize,
+    pub completed_orders: usize,
+    pub trades: usize,
+    /// Filled share of the amounts placed, from 0 to 1.
+    pub fill_rate: Option<Decimal>,
+    /// Mean relative difference between the execution rate and the order
+    /// rate, weighted by the filled amount. Positive when filled better than
+    /// asked.
+    pub slippage: Option<Decimal>,
+    /// Held balances included.
+    pub balances: Vec<BalanceChange>,
+    pub pnl: pnl::PnlReport,
+}
+
+pub struct Simulator {
+    fees: Fees,
+    state: std::sync::Mutex<State>,
+}
+
+impl Simulator {
+    /// Snapshots may be given in any order. Orders which fail to parse or
+    /// have a non-positive amount or rate are rejected.
+    pub fn new(
+        snapshots: Vec<Snapshot>,
+        balances: balance_watcher::Balances,
+    ) -> Result<Simulator, typed::ModelError> {
+        let mut books = snapshots
+            .into_iter()
+            .map(|snapshot| {
+                let orders = snapshot
+                    .orders
+                    .into_iter()
+                    .map(|order| {
+                        let order = typed::TypedOrder::try_from(order)?;
+                        let positive = |field: &'static str, value: Decimal| {
+                            if value > Decimal::ZERO {
+                                Ok(value)
+                            } else {
+                                Err(typed::ModelError {
+                                    field,
+                                    value: value.to_string(),
+                                })
+                            }
+                        };
+                        positive("amount", order.amount)?;
+                        positive("rate", order.rate)?;
+                        Ok(BookOrder {
+                            id: order.id,
+                            amount: order.amount,
+                            rate: order.rate,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, typed::ModelError>>()?;
+                Ok(Book {
+                    time: snapshot.time,
+                    pair: snapshot.pair,
+                    orders,
+                })
+            })
+            .collect::<Result<Vec<_>, typed::ModelError>>()?;
+        books.sort_by_key(|book| book.time);
+        let time = books
+            .first()
+            .map(|book| book.time)
+            .unwrap_or_else(chrono::Utc::now);
+        Ok(Simulator {
+            fees: Default::default(),
+            state: std::sync::Mutex::new(State {
+                time,
+                pending: books.into(),
+                snapshots: 0,
+                books: Default::default(),
+                orders: Vec::new(),
+                trades: Vec::new(),
+                initial: balances.clone(),
+                balances,
+                sequence: 0,
+            }),
+        })
+    }
+
+    pub fn fees(mut self, fees: Fees) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("Simulator state is poisoned")
+    }
+
+    /// Time of the last applied snapshot.
+    pub fn time(&self) -> chrono::DateTime<chrono::Utc> {
+        self.state().time
+    }
+
+    /// Applies the snapshots of the next moment. False when none are left.
+    pub fn advance(&self) -> bool {
+        self.state().advance(&self.fees)
+    }
+
+    pub fn balances(&self) -> balance_watcher::Balances {
+        self.state().balances.clone()
+    }
+
+    pub fn report(&self, method: pnl::Method) -> RunReport {
+        let state = self.state();
+        let placed: Decimal = state.orders.iter().map(|order| order.initial_amount).sum();
+        let filled: Decimal = state.trades.iter().map(|trade| trade.quantity).sum();
+        let slippage: Decimal = state
+            .trades
+            .iter()
+            .map(|trade| (trade.rate - trade.limit) / trade.limit * trade.quantity)
+            .sum();
+        let ratio = |numerator: Decimal, denominator: Decimal| {
+            if denominator.is_zero() {
+                None
+            } else {
+                Some(numerator / denominator)
+            }
+        };
+        let mut coins: Vec<&coin::Coin> =
+            state.initial.keys().chain(state.balances.keys()).collect();
+        coins.sort_by(|left, right| left.get_name().cmp(right.get_name()));
+        coins.dedup();
+        let total = |balances: &balance_watcher::Balances, coin: &coin::Coin| {
+            balances
+                .get(coin)
+                .map(|holdings| holdings.amount + holdings.held)
+                .unwrap_or_default()
+        };
+        let balances = coins
+            .into_iter()
+            .map(|coin| {
+                let start = total(&state.initial, coin);
+                let end = total(&state.balances, coin);
+                BalanceChange {
+                    coin: coin.to_string(),
+                    start,
+                    end,
+                    change: end - start,
+                }
+            })
+            .collect();
+        let fills = state.trades.iter().map(|trade| {
+            pnl::Fill::try_from(trade.trade.clone()).expect("Simulated trades are valid")
+        });
+        RunReport {
+            snapshots: state.snapshots,
+            orders: state.orders.len(),
+            completed_orders: state
+                .orders
+                .iter()
+                .filter(|order| order.status == typed::OrderStatus::Completed)
+                .count(),
+            trades: state.trades.len(),
+            fill_rate: ratio(filled, placed),
+            slippage: ratio(slippage, filled),
+            balances,
+            pnl: pnl::calculate(fills, method),
+        }
+    }
+}
+
+impl exchange_api::ExchangeApi for Simulator {
+    fn get_order_book(
+        &self,
+        pair: coin::CoinPair,
+    ) -> exchange_api::ApiFuture<'_, models::Orders> {
+        let state = self.state();
+        let time = state.time.to_rfc3339();
+        let mut orders: Vec<models::Order> = state
+            .books
+            .get(&pair)
+            .map(|book| {
+                book.iter()
+                    .filter(|order| order.amount > Decimal::ZERO)
+                    .map(|order| models::Order {
+                        amount: format(order.amount),
+                        created_at: time.clone(),
+                        id: order.id,
+                        initial_amount: None,
+                        is_owner: None,
+                        pair: String::from(&pair),
+                        rate: format(order.rate),
+                        status: typed::OrderStatus::Active.to_string(),
+                        updated_at: time.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut own: Vec<&Order> = state
+            .orders
+            .iter()
+            .filter(|order| {
+                order.pair == pair && order.status == typed::OrderStatus::Active
+            })
+            .collect();
+        own.sort_by_key(|order| (order.rate, order.sequence));
+        orders.extend(own.into_iter().map(Order::to_model));
+        Box::pin(futures::future::ready(Ok(orders)))
+    }
+
+    fn get_all_my_orders(
+        &self,
+        pair: Option<coin::CoinPair>,
+    ) -> exchange_api::ApiFuture<'_, models::Orders> {
+        let orders = self
+            .state()
+            .orders
+            .iter()
+            .filter(|order| pair.as_ref().is_none_or(|pair| order.pair == *pair))
+            .map(Order::to_model)
+            .collect();
+        Box::pin(futures::future::ready(Ok(orders)))
+    }
+
+    fn get_trades(
+        &self,
+        order_id: Option<u32>,
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> exchange_api::ApiFuture<'_, models::Trades> {
+        let trades = self
+            .state()
+            .trades
+            .iter()
+            .rev()
+            .filter(|trade| order_id.is_none_or(|id| trade.trade.order.id == id))
+            .skip(offset.unwrap_or(0) as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .map(|trade| trade.trade.clone())
+            .collect();
+        Box::pin(futures::future::ready(Ok(trades)))
+    }
+
+    fn create_order_raw<'a>(
+        &'a self,
+        pair: coin::CoinPair,
+        amount: &'a str,
+        rate: &'a str,
+    ) -> exchange_api::ApiFuture<'a, models::Order> {
+        let result = self.state().create(pair, amount, rate, &self.fees);
+        Box::pin(futures::future::ready(result))
+    }
+
+    fn update_order_by_id<'a>(
+        &'a self,
+        id: &'a str,
+        order: &'a models::UpdateOrder,
+    ) -> exchange_api::ApiFuture<'a, models::Order> {
+        let result = self.state().update(id, order, &self.fees);
+        Box::pin(futures::future::ready(result))
+    }
+
+    fn delete_order_by_id<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> exchange_api::ApiFuture<'a, models::Order> {
+        let result = self.state().delete(id);
+        Box::pin(futures::future::ready(result))
+    }
+
+    fn get_balance_summary(&self) -> exchange_api::ApiFuture<'_, models::Balance> {
+        let state = self.state();
+        let mut balance: models::Balance = state
+            .balances
+            .iter()
+            .map(|(coin, holdings)| models::Currency {
+                amount: format(holdings.amount),
+                coin: coin.to_string(),
+                held: format(holdings.held),
+            })
+            .collect();
+        balance.sort_by(|left, right| left.coin.cmp(&right.coin));
+        Box::pin(futures::future::ready(Ok(balance)))
+    }
+}
+
+/// Ticks the engine after every moment of the snapshots, then shuts it down
+/// and reports the run.
+pub async fn backtest<TStrategy>(
+    engine: &mut strategy::Engine<std::sync::Arc<Simulator>, TStrategy>,
+    method: pnl::Method,
+) -> Result<RunReport, error::Error>
+where
+    TStrategy: strategy::Strategy,
+{
+    while engine.exchange().advance() {
+        engine.tick().await?;
+    }
+    engine.shutdown().await?;
+    Ok(engine.exchange().report(method))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::exchange_api::ExchangeApi;
+
+    fn time(minute: u32) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339(&format!("2021-03-01T10:{:02}:00Z", minute))
+            .unwrap()
+            .with_timezone(&chrono::Utc)
+    }
+
+    fn snapshot(
+        minute: u32,
+        pair: &coin::CoinPair,
+        orders: &[(u32, &str, &str)],
+    ) -> Snapshot {
+        Snapshot {
+            time: time(minute),
+            pair: pair.clone(),
+            orders: orders
+                .iter()
+                .map(|(id, amount, rate)| models::Order {
+                    amount: amount.to_string(),
+                    created_at: time(0).to_rfc3339(),
+                    id: *id,
+                    pair: String::from(pair),
+                    rate: rate.to_string(),
+                    status: "ACTIVE".to_owned(),
+                    updated_at: time(0).to_rfc3339(),
+                    ..models::Order::default()
+                })
+                .collect(),
+        }
+    }
+
+    /// Sells one BTC at 80 once.
+    #[derive(Default)]
+    struct SellOnce {
+        books: usize,
+        fills: usize,
+    }
+
+    impl strategy::Strategy for SellOnce {
+        fn on_book(&mut self, _book: &strategy::Book) -> Vec<strategy::DesiredOrder> {
+            self.books += 1;
+            if self.books == 1 {
+                vec![strategy::DesiredOrder {
+                    amount: Decimal::ONE,
+                    rate: Decimal::from(80),
+                }]
+            } else {
+                Vec::new()
+            }
+        }
+
+        fn on_fill(&mut self, _trade: &typed::TypedTrade) {
+            self.fills += 1;
+        }
+    }
+
+    #[test]
+    fn backtest_fills_and_reports() {
+        let pair = coin::CoinPair::new(coin::Coin::BTC, coin::Coin::USDT);
+        let reversed = pair.reversed();
+        let snapshots = vec![
+            // Bid of 100 USDT per BTC for 50 USDT.
+            snapshot(2, &reversed, &[(201, "100", "0.008")]),
+            snapshot(1, &pair, &[(100, "1", "110")]),
+            snapshot(1, &reversed, &[(200, "50", "0.01")]),
+        ];
+        let balances = vec![(
+            coin::Coin::BTC,
+            balance_watcher::Holdings {
+                amount: Decimal::from(2),
+                held: Decimal::ZERO,
+            },
+        )]
+        .into_iter()
+        .collect();
+        let invalid = vec![snapshot(1, &reversed, &[(202, "10", "0")])];
+        assert_eq!(
+            Simulator::new(invalid, Default::default()).err(),
+            Some(typed::ModelError {
+                field: "rate",
+                value: "0".to_owned(),
+            })
+        );
+        let invalid = vec![snapshot(1, &reversed, &[(202, "-10", "0.01")])];
+        assert_eq!(
+            Simulator::new(invalid, Default::default())
+                .err()
+                .map(|error| error.field),
+            Some("amount")
+        );
+        let simulator = Simulator::new(snapshots, balances).unwrap().fees(Fees {
+            maker: Decimal::new(1, 2),
+            taker: Decimal::new(2, 2),
+        });
+        let simulator = std::sync::Arc::new(simulator);
+        assert!(matches!(
+            tokio_test::block_on(simulator.create_order_raw(pair.clone(), "3", "80")),
+            Err(error::Error::UnprocessableEntityError)
+        ));
+        let mut engine = strategy::Engine::new(
+            simulator.clone(),
+            SellOnce::default(),
+            vec![pair.clone()],
+        );
+        let report =
+            tokio_test::block_on(backtest(&mut engine, pnl::Method::Fifo)).unwrap();
+        // Half is taken from the first bid at its rate, the rest rests at 80
+        // until the second bid crosses it.
+        assert_eq!(engine.strategy().fills, 2);
+        assert_eq!(report.snapshots, 3);
+        assert_eq!(report.orders, 1);
+        assert_eq!(report.completed_orders, 1);
+        assert_eq!(report.trades, 2);
+        assert_eq!(report.fill_rate, Some(Decimal::ONE));
+        assert_eq!(report.slippage, Some(Decimal::new(125, 3)));
+        assert_eq!(
+            report.balances,
+            vec![
+                BalanceChange {
+                    coin: "btc".to_owned(),
+                    start: Decimal::from(2),
+                    end: Decimal::ONE,
+                    change: -Decimal::ONE,
+                },
+                BalanceChange {
+                    coin: "usdt_erc20".to_owned(),
+                    start: Decimal::ZERO,
+                    end: Decimal::new(886, 1),
+                    change: Decimal::new(886, 1),
+                },
+            ]
+        );
+        assert_eq!(report.pnl.pairs[0].realized, Decimal::new(886, 1));
+        assert_eq!(report.pnl.pairs[0].fees, Decimal::new(14, 1));
+        let book = tokio_test::block_on(simulator.get_order_book(reversed)).unwrap();
+        assert_eq!(book.len(), 1);
+        assert_eq!(book[0].amount, "60");
+        let trades =
+            tokio_test::block_on(simulator.get_trades(None, None, None)).unwrap();
+        assert_eq!(trades[0].created_at, time(2).to_rfc3339());
+        assert_eq!(trades[1].received_amount, "49");
+    }
+}
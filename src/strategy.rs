@@ -0,0 +1,525 @@
+//! Runtime for trading bots.
+//!
+//! Every tick the engine feeds the balance, the new fills and the order book
+//! of each pair to the `Strategy`, then brings the open orders of the account
+//! in line with the orders the strategy wants with as few calls as possible.
+use super::backend;
+use super::balance_watcher;
+use super::bulk;
+use super::chatex_client;
+use super::coin;
+use super::error;
+use super::exchange_api;
+use super::exchange_api::ExchangeApi;
+use super::models;
+use super::models::typed;
+use rust_decimal::Decimal;
+use std::convert::TryFrom;
+
+pub const DEFAULT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+const TRADES_PAGE_SIZE: u32 = 100;
+
+/// Every order of a pair, the ones of the account included.
+#[derive(Clone, Debug)]
+pub struct Book {
+    pub pair: coin::CoinPair,
+    pub orders: Vec<typed::TypedOrder>,
+}
+
+/// An order the strategy wants to have open, selling `amount` of
+/// `pair.left` at `rate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DesiredOrder {
+    pub amount: Decimal,
+    pub rate: Decimal,
+}
+
+pub trait Strategy {
+    /// Orders to keep open on `book.pair`. Open orders of the pair which are
+    /// not returned are cancelled.
+    fn on_book(&mut self, book: &Book) -> Vec<DesiredOrder>;
+
+    /// Called once for every trade made since the engine started, oldest
+    /// first.
+    fn on_fill(&mut self, _trade: &typed::TypedTrade) {}
+
+    /// Called every tick before the books.
+    fn on_balance(&mut self, _balances: &balance_watcher::Balances) {}
+}
+
+/// A call made to reconcile the open orders.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Create {
+        pair: coin::CoinPair,
+        order: DesiredOrder,
+    },
+    Update {
+        id: u32,
+        order: DesiredOrder,
+    },
+    Cancel {
+        id: u32,
+    },
+}
+
+impl Action {
+    async fn execute<TApi>(self, exchange: &TApi) -> Result<models::Order, error::Error>
+    where
+        TApi: ExchangeApi,
+    {
+        match self {
+            Action::Create { pair, order } => {
+                exchange
+                    .create_order_raw(
+                        pair,
+                        &order.amount.to_string(),
+                        &order.rate.to_string(),
+                    )
+                    .await
+            }
+            Action::Update { id, order } => {
+                let update = models::UpdateOrder {
+                    amount: order.amount.to_string(),
+                    rate: order.rate.to_string(),
+                };
+                exchange.update_order_by_id(&id.to_string(), &update).await
+            }
+            Action::Cancel { id } => exchange.delete_order_by_id(&id.to_string()).await,
+        }
+    }
+}
+
+/// Calls turning the active orders of `open` into `desired`.
+///
+/// Orders already matching a desired one are kept, the rest are updated to
+/// the remaining desired orders by rate, and only the surplus is cancelled or
+/// created. Inactive orders are left alone.
+pub fn reconcile(
+    pair: &coin::CoinPair,
+    open: &[typed::TypedOrder],
+    desired: &[DesiredOrder],
+) -> Vec<Action> {
+    let mut open: Vec<&typed::TypedOrder> = open
+        .iter()
+        .filter(|order| order.pair == *pair && order.status == typed::OrderStatus::Active)
+        .collect();
+    let mut missing: Vec<DesiredOrder> = Vec::new();
+    for order in desired.iter() {
+        let kept = open
+            .iter()
+            .position(|open| open.amount == order.amount && open.rate == order.rate);
+        match kept {
+            Some(index) => {
+                open.remove(index);
+            }
+            None => missing.push(*order),
+        }
+    }
+    open.sort_by_key(|order| (order.rate, order.id));
+    missing.sort_by_key(|order| order.rate);
+    let updated = open.len().min(missing.len());
+    let mut actions: Vec<Action> = open[updated..]
+        .iter()
+        .map(|order| Action::Cancel { id: order.id })
+        .collect();
+    actions.extend(
+        open.iter()
+            .zip(missing.iter())
+            .map(|(open, order)| Action::Update {
+                id: open.id,
+                order: *order,
+            }),
+    );
+    actions.extend(missing[updated..].iter().map(|order| Action::Create {
+        pair: pair.clone(),
+        order: *order,
+    }));
+    actions
+}
+
+fn to_typed<TFrom, TTyped>(values: Vec<TFrom>) -> Vec<TTyped>
+where
+    TTyped: TryFrom<TFrom, Error = typed::ModelError>,
+{
+    values
+        .into_iter()
+        .filter_map(|value| match TTyped::try_from(value) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                log::warn!("Skipped: {}", error);
+                None
+            }
+        })
+        .collect()
+}
+
+pub struct Engine<TApi, TStrategy> {
+    exchange: TApi,
+    strategy: TStrategy,
+    pairs: Vec<coin::CoinPair>,
+    interval: std::time::Duration,
+    concurrency: usize,
+    last_trade_id: Option<u32>,
+}
+
+impl<TBackend, TStrategy> Engine<exchange_api::LiveExchange<TBackend>, TStrategy>
+where
+    TBackend: backend::HttpBackend,
+    TStrategy: Strategy,
+{
+    pub fn from_client(
+        client: &chatex_client::ChatexClient<TBackend>,
+        strategy: TStrategy,
+        pairs: Vec<coin::CoinPair>,
+    ) -> Engine<exchange_api::LiveExchange<TBackend>, TStrategy> {
+        Engine::new(
+            exchange_api::LiveExchange::from_client(client),
+            strategy,
+            pairs,
+        )
+    }
+}
+
+impl<TApi, TStrategy> Engine<TApi, TStrategy>
+where
+    TApi: ExchangeApi,
+    TStrategy: Strategy,
+{
+    pub fn new(
+        exchange: TApi,
+        strategy: TStrategy,
+        pairs: Vec<coin::CoinPair>,
+    ) -> Engine<TApi, TStrategy> {
+        Engine {
+            exchange,
+            strategy,
+            pairs,
+            interval: DEFAULT_INTERVAL,
+            concurrency: bulk::DEFAULT_CONCURRENCY,
+            last_trade_id: None,
+        }
+    }
+
+    pub fn interval(mut self, interval: std::time::Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn exchange(&self) -> &TApi {
+        &self.exchange
+    }
+
+    pub fn strategy(&self) -> &TStrategy {
+        &self.strategy
+    }
+
+    pub fn strategy_mut(&mut self) -> &mut TStrategy {
+        &mut self.strategy
+    }
+
+    /// Runs a single round of the loop and returns the calls made.
+    ///
+    /// Fails only when the balance, the trades or the orders cannot be
+    /// fetched, errors of the single calls end up in the report.
+    pub async fn tick(
+        &mut self,
+    ) -> Result<bulk::BulkReport<Action, models::Order>, error::Error> {
+        let balance = self.exchange.get_balance_summary().await?;
+        self.strategy
+            .on_balance(&balance_watcher::to_balances(&balance));
+        for trade in self.poll_fills().await? {
+            self.strategy.on_fill(&trade);
+        }
+        let mut actions = Vec::new();
+        for pair in self.pairs.iter() {
+            let book = Book {
+                pair: pair.clone(),
+                orders: to_typed(self.exchange.get_order_book(pair.clone()).await?),
+            };
+            let desired = self.strategy.on_book(&book);
+            let open: Vec<typed::TypedOrder> =
+                to_typed(self.exchange.get_all_my_orders(Some(pair.clone())).await?);
+            actions.extend(reconcile(pair, &open, &desired));
+        }
+        let exchange = &self.exchange;
+        Ok(bulk::run(actions, self.concurrency, |action| {
+            action.clone().execute(exchange)
+        })
+        .await)
+    }
+
+    /// Ticks every `interval` until `shutdown` completes, then cancels every
+    /// order of the pairs. `shutdown` is only checked between ticks, so the
+    /// calls of a running tick land before the orders are cancelled.
+    ///
+    /// Failed ticks are logged and the loop goes on.
+    pub async fn run<F>(
+        &mut self,
+        shutdown: F,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error>
+    where
+        F: futures::Future<Output = ()>,
+    {
+        futures::pin_mut!(shutdown);
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            let next = interval.tick();
+            futures::pin_mut!(next);
+            if let futures::future::Either::Left(_) =
+                futures::future::select(shutdown.as_mut(), next).await
+            {
+                break;
+            }
+            match self.tick().await {
+                Err(error) => log::warn!("Strategy tick failed: {}", error),
+                Ok(report) => {
+                    for (action, error) in report.failed() {
+                        log::warn!("Failed to {:?}: {}", action, error);
+                    }
+                }
+            }
+        }
+        self.shutdown().await
+    }
+
+    /// Cancels every open order of the pairs.
+    pub async fn shutdown(
+        &mut self,
+    ) -> Result<bulk::BulkReport<u32, models::Order>, error::Error> {
+        let mut ids = Vec::new();
+        for pair in self.pairs.iter() {
+            let orders = self.exchange.get_all_my_orders(Some(pair.clone())).await?;
+            ids.extend(
+                orders
+                    .into_iter()
+                    .filter(|order| {
+                        matches!(
+                            typed::OrderStatus::from(order.status.as_str()),
+                            typed::OrderStatus::Active | typed::OrderStatus::Inactive
+                        )
+                    })
+                    .map(|order| order.id),
+            );
+        }
+        let exchange = &self.exchange;
+        Ok(bulk::run(ids, self.concurrency, |id| {
+            let id = id.to_string();
+            async move { exchange.delete_order_by_id(&id).await }
+        })
+        .await)
+    }
+
+    /// Trades newer than the newest one seen, oldest first. Trade ids are
+    /// expected to grow, trades made before the first call are skipped.
+    async fn poll_fills(&mut self) -> Result<Vec<typed::TypedTrade>, error::Error> {
+        let last_trade_id = match self.last_trade_id {
+            Some(last_trade_id) => last_trade_id,
+            None => {
+                let newest = self.exchange.get_trades(None, Some(0), Some(1)).await?;
+                self.last_trade_id =
+                    Some(newest.first().map(|trade| trade.id).unwrap_or(0));
+                return Ok(Vec::new());
+            }
+        };
+        let mut trades = models::Trades::new();
+        let mut offset = 0;
+        loop {
+            let page = self
+                .exchange
+                .get_trades(None, Some(offset), Some(TRADES_PAGE_SIZE))
+                .await?;
+            offset += page.len() as u32;
+            let is_last = page.len() < TRADES_PAGE_SIZE as usize
+                || page.iter().any(|trade| trade.id <= last_trade_id);
+            trades.extend(page.into_iter().filter(|trade| trade.id > last_trade_id));
+            if is_last {
+                break;
+            }
+        }
+        if let Some(newest) = trades.iter().map(|trade| trade.id).max() {
+            self.last_trade_id = Some(newest);
+        }
+        trades.sort_by_key(|trade| trade.id);
+        Ok(to_typed(trades))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    fn pair() -> coin::CoinPair {
+        coin::CoinPair::new(coin::Coin::BTC, coin::Coin::USDT)
+    }
+
+    fn order(id: u32, status: &str, amount: &str, rate: &str) -> models::Order {
+        models::Order {
+            amount: amount.to_owned(),
+            created_at: "2021-03-01T10:00:00Z".to_owned(),
+            id,
+            is_owner: Some(true),
+            pair: String::from(pair()),
+            rate: rate.to_owned(),
+            status: status.to_owned(),
+            updated_at: "2021-03-01T10:00:00Z".to_owned(),
+            ..models::Order::default()
+        }
+    }
+
+    fn desired(amount: i64, rate: i64) -> DesiredOrder {
+        DesiredOrder {
+            amount: Decimal::from(amount),
+            rate: Decimal::from(rate),
+        }
+    }
+
+    #[test]
+    fn reconcile_with_minimal_calls() {
+        let open: Vec<typed::TypedOrder> = to_typed(vec![
+            order(1, "ACTIVE", "1", "100"),
+            order(2, "ACTIVE", "1", "90"),
+            order(3, "ACTIVE", "2", "80"),
+            order(4, "INACTIVE", "1", "70"),
+        ]);
+        let actions = reconcile(&pair(), &open, &[desired(1, 100), desired(3, 95)]);
+        assert_eq!(
+            actions,
+            vec![
+                Action::Cancel { id: 2 },
+                Action::Update {
+                    id: 3,
+                    order: desired(3, 95)
+                },
+            ]
+        );
+        let actions = reconcile(&pair(), &open[..1], &[desired(1, 100), desired(2, 110)]);
+        assert_eq!(
+            actions,
+            vec![Action::Create {
+                pair: pair(),
+                order: desired(2, 110)
+            }]
+        );
+        assert!(reconcile(&pair(), &open[3..], &[]).is_empty());
+    }
+
+    struct Fixed {
+        balances: usize,
+        /// Set by every book, while the tick still has calls to make.
+        shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl Strategy for Fixed {
+        fn on_book(&mut self, book: &Book) -> Vec<DesiredOrder> {
+            assert_eq!(book.orders.len(), 1);
+            self.shutdown
+                .store(true, std::sync::atomic::Ordering::SeqCst);
+            vec![desired(1, 100), desired(2, 110)]
+        }
+
+        fn on_balance(&mut self, _balances: &balance_watcher::Balances) {
+            self.balances += 1;
+        }
+    }
+
+    fn body<T: serde::Serialize>(value: &T) -> String {
+        serde_json::to_string(value).expect(SERDE_ERROR)
+    }
+
+    #[test]
+    fn tick_and_shutdown() {
+        let case = TestCase::new();
+        let _access_token_mock = case.mock_access_token();
+        let balance_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/me/balance");
+            default_then_content_type(then).status(200).body("[]");
+        });
+        let trades_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/exchange/orders/trades");
+            default_then_content_type(then).status(200).body("[]");
+        });
+        let book_mock = case.server.mock(|when, then| {
+            default_get_when(when)
+                .path("/exchange/orders")
+                .query_param("pair", "btc/usdt_erc20");
+            default_then_content_type(then)
+                .status(200)
+                .body(body(&vec![order(9, "ACTIVE", "5", "120")]));
+        });
+        let my_orders_mock = case.server.mock(|when, then| {
+            default_get_when(when).path("/exchange/orders/my");
+            default_then_content_type(then).status(200).body(body(&vec![
+                order(1, "ACTIVE", "1", "100"),
+                order(2, "ACTIVE", "1", "90"),
+            ]));
+        });
+        let update_mock = case.server.mock(|when, then| {
+            when.method(httpmock::Method::PUT)
+                .path("/exchange/orders/2")
+                .body(r#"{"amount":"2","rate":"110"}"#);
+            default_then_content_type(then)
+                .status(200)
+                .body(body(&order(2, "ACTIVE", "2", "110")));
+        });
+        let delete_mock = case.server.mock(|when, then| {
+            when.method(httpmock::Method::DELETE);
+            default_then_content_type(then)
+                .status(200)
+                .body(body(&order(1, "CANCELED", "1", "100")));
+        });
+        let shutdown = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut engine = Engine::new(
+            exchange_api::LiveExchange::new(
+                crate::exchange_client::ExchangeClient::new(
+                    case.client_base.clone(),
+                    std::sync::Arc::new(crate::endpoint::Exchange::new(
+                        &case.base_context,
+                    )),
+                ),
+                crate::profile_client::ProfileClient::new(
+                    case.client_base.clone(),
+                    std::sync::Arc::new(crate::endpoint::Profile::new(
+                        &case.base_context,
+                    )),
+                ),
+            ),
+            Fixed {
+                balances: 0,
+                shutdown: shutdown.clone(),
+            },
+            vec![pair()],
+        );
+        let report = tokio_test::block_on(engine.tick()).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.len(), 1);
+        assert_eq!(engine.strategy().balances, 1);
+        update_mock.assert();
+        // Shutdown is requested in the middle of the next tick, which still
+        // makes its update before the orders are cancelled.
+        shutdown.store(false, std::sync::atomic::Ordering::SeqCst);
+        let requested = futures::future::poll_fn(|_| {
+            if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+                std::task::Poll::Ready(())
+            } else {
+                std::task::Poll::Pending
+            }
+        });
+        let report = tokio_test::block_on(engine.run(requested)).unwrap();
+        let cancelled: Vec<u32> = report.succeeded().map(|(id, _)| *id).collect();
+        assert_eq!(cancelled, vec![1, 2]);
+        update_mock.assert_hits(2);
+        balance_mock.assert_hits(2);
+        trades_mock.assert_hits(2);
+        book_mock.assert_hits(2);
+        my_orders_mock.assert_hits(3);
+        delete_mock.assert_hits(2);
+    }
+}
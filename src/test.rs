@@ -1,30 +1,214 @@
 pub const SECRET: &'static str = "SECRET";
 pub const SERDE_ERROR: &'static str = "Failed to serialize something.";
 
-pub type Connector = hyper::client::HttpConnector;
+pub type Backend = crate::backend::HyperBackend<hyper::client::HttpConnector>;
+
+/// Keeps every log record emitted by this crate.
+struct CapturingLogger {
+    records: std::sync::Mutex<Vec<String>>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with(module_path!().split("::").next().unwrap())
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.records
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", record.target(), record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger {
+    records: std::sync::Mutex::new(Vec::new()),
+};
+
+pub fn install_capturing_logger() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).expect("Failed to install the test logger");
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+}
+
+/// Log records captured so far by all the tests of this crate.
+pub fn captured_logs() -> Vec<String> {
+    LOGGER.records.lock().unwrap().clone()
+}
+
+/// Span with the fields recorded on it so far.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Debug)]
+pub struct CapturedSpan {
+    pub name: &'static str,
+    pub fields: std::collections::BTreeMap<&'static str, String>,
+    metadata: &'static tracing::Metadata<'static>,
+}
+
+#[cfg(feature = "tracing")]
+struct FieldVisitor<'a>(&'a mut std::collections::BTreeMap<&'static str, String>);
+
+#[cfg(feature = "tracing")]
+impl tracing::field::Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+/// Keeps every span and event emitted by this crate, for a single thread.
+/// Install it with `tracing::subscriber::with_default`.
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+pub struct CapturingSubscriber {
+    spans: std::sync::Mutex<Vec<CapturedSpan>>,
+    events: std::sync::Mutex<Vec<std::collections::BTreeMap<&'static str, String>>>,
+    entered: std::sync::Mutex<Vec<tracing::span::Id>>,
+}
+
+#[cfg(feature = "tracing")]
+impl CapturingSubscriber {
+    pub fn spans(&self) -> Vec<CapturedSpan> {
+        self.spans.lock().unwrap().clone()
+    }
+
+    /// Every value of every span and event.
+    pub fn values(&self) -> Vec<String> {
+        let spans = self.spans();
+        let events = self.events.lock().unwrap();
+        spans
+            .iter()
+            .map(|span| &span.fields)
+            .chain(events.iter())
+            .flat_map(|fields| fields.values().cloned())
+            .collect()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for CapturingSubscriber {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+        metadata.target().starts_with(module_path!().split("::").next().unwrap())
+    }
+
+    fn new_span(&self, attributes: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut span = CapturedSpan {
+            name: attributes.metadata().name(),
+            fields: Default::default(),
+            metadata: attributes.metadata(),
+        };
+        attributes.record(&mut FieldVisitor(&mut span.fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span);
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        let span = &mut spans[span.into_u64() as usize - 1];
+        values.record(&mut FieldVisitor(&mut span.fields));
+    }
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        let mut fields = Default::default();
+        event.record(&mut FieldVisitor(&mut fields));
+        self.events.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, span: &tracing::span::Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _span: &tracing::span::Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> tracing_core::span::Current {
+        match self.entered.lock().unwrap().last() {
+            Some(id) => {
+                let metadata = self.spans.lock().unwrap()[id.into_u64() as usize - 1].metadata;
+                tracing_core::span::Current::new(id.clone(), metadata)
+            }
+            None => tracing_core::span::Current::none(),
+        }
+    }
+}
 
 pub struct TestCase {
     pub server: httpmock::MockServer,
-    pub client_base: std::sync::Arc<crate::client_base::ClientBase<Connector>>,
+    pub client_base: std::sync::Arc<crate::client_base::ClientBase<Backend>>,
     pub base_context: crate::context::BaseContext,
 }
 
 impl TestCase {
     pub fn new() -> Self {
+        TestCase::with_middleware(crate::middleware::MiddlewareChain::new())
+    }
+
+    pub fn with_middleware(middleware: crate::middleware::MiddlewareChain) -> Self {
+        TestCase::with_transport(|transport| transport.middleware = middleware)
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(recorder: std::sync::Arc<dyn crate::metrics::Recorder>) -> Self {
+        TestCase::with_transport(|transport| transport.metrics = Some(recorder))
+    }
+
+    pub fn with_token_store(
+        token_store: std::sync::Arc<dyn crate::token_store::TokenStore>,
+    ) -> Self {
+        TestCase::create(
+            |_| {},
+            |profile| crate::access_controller::AccessController::with_token_store(
+                profile,
+                token_store))
+    }
+
+    pub fn with_transport<Configure>(configure: Configure) -> Self
+    where
+        Configure: FnOnce(&mut crate::transport::Transport<Backend>),
+    {
+        TestCase::create(configure, crate::access_controller::AccessController::new)
+    }
+
+    pub fn create<Configure, CreateAccessController>(
+        configure: Configure,
+        create_access_controller: CreateAccessController,
+    ) -> Self
+    where
+        Configure: FnOnce(&mut crate::transport::Transport<Backend>),
+        CreateAccessController: FnOnce(
+            std::sync::Arc<crate::endpoint::Profile>,
+        ) -> crate::access_controller::AccessController,
+    {
         let server = httpmock::MockServer::start();
         let base_url = url::Url::parse(&server.base_url()).unwrap();
-        let hyper_client = hyper::Client::builder()
-            .build_http::<hyper::Body>();
+        let backend = crate::backend::HyperBackend::with_client(
+            hyper::Client::builder().build_http::<hyper::Body>());
         let base_context = crate::context::BaseContext::new(base_url);
         let api_context = crate::context::ApiContext::new(
             base_context.clone(),
-            SECRET.to_owned());
+            SECRET.into());
         let profile = crate::endpoint::Profile::new(&base_context);
         let profile= std::sync::Arc::new(profile);
-        let access_controller = crate::access_controller::AccessController::new(
-            profile.clone());
+        let access_controller = create_access_controller(profile.clone());
+        let mut transport = crate::transport::Transport::new(
+            std::sync::Arc::new(backend),
+            crate::middleware::MiddlewareChain::new());
+        configure(&mut transport);
         let client_base = std::sync::Arc::new(crate::client_base::ClientBase::new(
-            hyper_client,
+            transport,
             api_context,
             access_controller));
         TestCase {
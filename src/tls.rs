@@ -0,0 +1,133 @@
+//! HTTPS backends built on `native-tls` or `rustls`.
+//!
+//! The `native-tls` feature uses the system TLS library, e.g. OpenSSL. The
+//! `rustls` feature needs no system libraries and trusts the Mozilla roots
+//! bundled with `webpki-roots`, which suits static builds. `rustls` is
+//! used when both features are enabled.
+use super::backend;
+
+/// Connector of the enabled TLS implementation.
+#[cfg(feature = "rustls")]
+pub type HttpsConnector = hyper_rustls::HttpsConnector<hyper::client::HttpConnector>;
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub type HttpsConnector = hyper_tls::HttpsConnector<hyper::client::HttpConnector>;
+
+pub type HttpsBackend = backend::HyperBackend<HttpsConnector>;
+
+#[derive(Debug)]
+pub enum TlsError {
+    Certificate(String),
+    Backend(String),
+}
+
+impl std::fmt::Display for TlsError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::Certificate(error) => {
+                write!(formatter, "Invalid certificate: {}", error)
+            }
+            TlsError::Backend(error) => {
+                write!(formatter, "Failed to set up TLS: {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+#[derive(Clone, Debug, Default)]
+pub struct TlsOptions {
+    root_certificates: Vec<Vec<u8>>,
+}
+
+impl TlsOptions {
+    pub fn new() -> TlsOptions {
+        Default::default()
+    }
+
+    /// Trusts an additional PEM encoded root certificate, e.g. the
+    /// self-signed certificate of a local fake server.
+    pub fn root_certificate_pem<TPem: Into<Vec<u8>>>(mut self, pem: TPem) -> TlsOptions {
+        self.root_certificates.push(pem.into());
+        self
+    }
+
+    pub fn root_certificate_file<TPath: AsRef<std::path::Path>>(
+        self,
+        path: TPath,
+    ) -> std::io::Result<TlsOptions> {
+        Ok(self.root_certificate_pem(std::fs::read(path)?))
+    }
+}
+
+fn http_connector() -> hyper::client::HttpConnector {
+    let mut http = hyper::client::HttpConnector::new();
+    http.enforce_http(false);
+    http
+}
+
+/// Creates the HTTPS backend of the enabled TLS implementation.
+#[cfg(feature = "rustls")]
+pub fn https_backend(options: &TlsOptions) -> Result<HttpsBackend, TlsError> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    for pem in options.root_certificates.iter() {
+        let certificates = rustls_pemfile::certs(&mut pem.as_slice())
+            .map_err(|error| TlsError::Certificate(error.to_string()))?;
+        if certificates.is_empty() {
+            return Err(TlsError::Certificate("no PEM certificate found".to_owned()));
+        }
+        for certificate in certificates {
+            roots
+                .add(&rustls::Certificate(certificate))
+                .map_err(|error| TlsError::Certificate(error.to_string()))?;
+        }
+    }
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(config)
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(http_connector());
+    Ok(backend::HyperBackend::new(connector))
+}
+
+/// Creates the HTTPS backend of the enabled TLS implementation.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub fn https_backend(options: &TlsOptions) -> Result<HttpsBackend, TlsError> {
+    let mut builder = native_tls::TlsConnector::builder();
+    for pem in options.root_certificates.iter() {
+        let certificate = native_tls::Certificate::from_pem(pem)
+            .map_err(|error| TlsError::Certificate(error.to_string()))?;
+        builder.add_root_certificate(certificate);
+    }
+    let tls = builder
+        .build()
+        .map_err(|error| TlsError::Backend(error.to_string()))?;
+    let connector = hyper_tls::HttpsConnector::from((http_connector(), tls.into()));
+    Ok(backend::HyperBackend::new(connector))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_root_certificate() {
+        let options = TlsOptions::new().root_certificate_pem("not a certificate");
+        assert!(matches!(
+            https_backend(&options),
+            Err(TlsError::Certificate(_))
+        ));
+        assert!(https_backend(&TlsOptions::new()).is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+use super::backend;
+use super::backoff;
+use super::client_base;
+use super::clock;
+
+/// Settings of the background access token renewal.
+#[derive(Clone, Debug)]
+pub struct RefreshConfig {
+    /// Part of the token lifetime after which it is renewed, e.g. `0.75`.
+    pub lifetime_fraction: f64,
+    /// Delay after the first failed renewal, doubled on every next failure.
+    pub min_backoff: std::time::Duration,
+    pub max_backoff: std::time::Duration,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        RefreshConfig {
+            lifetime_fraction: 0.75,
+            min_backoff: std::time::Duration::from_secs(1),
+            max_backoff: std::time::Duration::from_secs(60),
+        }
+    }
+}
+
+/// Stops the background task when dropped.
+pub struct RefresherHandle {
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for RefresherHandle {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Spawns the refresher on the current Tokio runtime.
+///
+/// The task holds only a weak reference to the client base and ends as soon
+/// as the handle is dropped.
+pub fn spawn<TBackend>(
+    base: std::sync::Weak<client_base::ClientBase<TBackend>>,
+    config: RefreshConfig,
+) -> RefresherHandle
+where
+    TBackend: backend::HttpBackend,
+{
+    RefresherHandle {
+        handle: tokio::spawn(run(base, config)),
+    }
+}
+
+async fn run<TBackend>(
+    base: std::sync::Weak<client_base::ClientBase<TBackend>>,
+    config: RefreshConfig,
+) where
+    TBackend: backend::HttpBackend,
+{
+    let mut backoff = backoff::Backoff::new(config.min_backoff, config.max_backoff);
+    let mut retry_delay = None;
+    loop {
+        let delay = match retry_delay.take() {
+            Some(delay) => delay,
+            None => match base.upgrade() {
+                Some(base) => time_until_refresh(base.as_ref(), &config),
+                None => return,
+            },
+        };
+        tokio::time::sleep(delay).await;
+        let result = match base.upgrade() {
+            Some(base) => base.refresh_access_token().await,
+            None => return,
+        };
+        match result {
+            Ok(()) => {
+                log::debug!("Access token renewed in background.");
+                backoff.reset();
+            }
+            Err(error) => {
+                let delay = backoff.next_delay();
+                log::warn!(
+                    "Failed to renew access token: {}. Retrying in {:?}.",
+                    error,
+                    delay
+                );
+                retry_delay = Some(delay);
+            }
+        }
+    }
+}
+
+/// Zero when there is no token yet, otherwise at least `min_backoff`, so a
+/// short-lived token can't make the task spin.
+fn time_until_refresh<TBackend>(
+    base: &client_base::ClientBase<TBackend>,
+    config: &RefreshConfig,
+) -> std::time::Duration
+where
+    TBackend: backend::HttpBackend,
+{
+    match base.next_token_refresh_at(config.lifetime_fraction) {
+        Some(refresh_at) => {
+            let delay = refresh_at
+                .signed_duration_since(clock::Clock::now(base.transport.clock.as_ref()))
+                .to_std()
+                .unwrap_or_default();
+            std::cmp::max(delay, config.min_backoff)
+        }
+        None => std::time::Duration::from_secs(0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn renews_until_client_is_dropped() {
+        let server = httpmock::MockServer::start();
+        let access_token = crate::models::AccessToken {
+            access_token: "TOKEN".into(),
+            expires_at: chrono::Utc::now().timestamp() + 2,
+        };
+        let access_token = serde_json::to_string(&access_token).expect(SERDE_ERROR);
+        let access_token_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST)
+                .path("/auth/access-token");
+            default_then_content_type(then)
+                .status(200)
+                .body(access_token);
+        });
+        let config = RefreshConfig {
+            lifetime_fraction: 0.1,
+            min_backoff: std::time::Duration::from_millis(10),
+            max_backoff: std::time::Duration::from_millis(50),
+        };
+        tokio_test::block_on(async {
+            let client = crate::ChatexClient::builder(
+                crate::backend::HyperBackend::new(hyper::client::HttpConnector::new()),
+                url::Url::parse(&server.base_url()).unwrap(),
+                SECRET.to_owned(),
+            )
+            .expiration_tolerance(chrono::Duration::zero())
+            .background_refresh(config)
+            .build();
+            for _ in 0..100 {
+                if access_token_mock.hits_async().await >= 2 {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            assert!(access_token_mock.hits_async().await >= 2);
+            drop(client);
+            let hits = access_token_mock.hits_async().await;
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            assert_eq!(access_token_mock.hits_async().await, hits);
+        });
+    }
+}
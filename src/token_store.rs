@@ -0,0 +1,257 @@
+use super::models;
+use super::secret;
+use fs2::FileExt;
+use serde_json;
+use sha2::Digest;
+
+/// Keeps access tokens between `AccessController` instances and processes.
+///
+/// Tokens are keyed by `key_for(api_key)`, so the API key itself is never
+/// stored.
+pub trait TokenStore: Send + Sync {
+    fn load(&self, key: &str) -> Option<models::AccessToken>;
+
+    fn store(
+        &self,
+        key: &str,
+        access_token: &models::AccessToken,
+    ) -> Result<(), std::io::Error>;
+}
+
+/// SHA-256 of the API key as a lowercase hex string.
+pub fn key_for(api_key: &secret::Secret) -> String {
+    let digest = sha2::Sha256::digest(api_key.expose().as_bytes());
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Default)]
+pub struct MemoryTokenStore {
+    tokens: std::sync::Mutex<std::collections::HashMap<String, models::AccessToken>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> MemoryTokenStore {
+        Default::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self, key: &str) -> Option<models::AccessToken> {
+        self.tokens.lock().unwrap().get(key).cloned()
+    }
+
+    fn store(
+        &self,
+        key: &str,
+        access_token: &models::AccessToken,
+    ) -> Result<(), std::io::Error> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), access_token.clone());
+        Ok(())
+    }
+}
+
+type StoredTokens = std::collections::BTreeMap<String, models::AccessToken>;
+
+/// Stores tokens in a JSON file readable only by the owner.
+///
+/// Every access holds an advisory lock on a `.lock` file next to it and the
+/// file is rewritten atomically on every `store`, so several stores and
+/// processes can share it.
+pub struct FileTokenStore {
+    path: std::path::PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new<TPath: Into<std::path::PathBuf>>(path: TPath) -> FileTokenStore {
+        FileTokenStore { path: path.into() }
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// The lock is released when the returned file is dropped.
+    fn lock(&self, exclusive: bool) -> Result<std::fs::File, std::io::Error> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        let mut path = self.path.clone().into_os_string();
+        path.push(".lock");
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(path)?;
+        if exclusive {
+            file.lock_exclusive()?;
+        } else {
+            file.lock_shared()?;
+        }
+        Ok(file)
+    }
+
+    fn read(&self) -> Result<StoredTokens, std::io::Error> {
+        match std::fs::read(&self.path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|error| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+            }),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                Ok(StoredTokens::new())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Callers hold the exclusive lock, so the temporary file is not shared.
+    fn write(&self, tokens: &StoredTokens) -> Result<(), std::io::Error> {
+        use std::io::Write;
+        let content = serde_json::to_vec(tokens).map_err(|error| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+        })?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(format!(".{}.tmp", std::process::id()));
+        let temporary = std::path::PathBuf::from(temporary);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&temporary)?;
+        file.write_all(&content)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, key: &str) -> Option<models::AccessToken> {
+        match self.lock(false).and_then(|_lock| self.read()) {
+            Ok(mut tokens) => tokens.remove(key),
+            Err(error) => {
+                log::warn!("Failed to read {}: {}", self.path.display(), error);
+                None
+            }
+        }
+    }
+
+    fn store(
+        &self,
+        key: &str,
+        access_token: &models::AccessToken,
+    ) -> Result<(), std::io::Error> {
+        let _lock = self.lock(true)?;
+        let mut tokens = self.read().unwrap_or_default();
+        tokens.insert(key.to_owned(), access_token.clone());
+        self.write(&tokens)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    fn create_valid_token() -> models::AccessToken {
+        models::AccessToken {
+            access_token: "TOKEN".into(),
+            expires_at: chrono::Utc::now().timestamp() + 3600,
+        }
+    }
+
+    fn temporary_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir()
+            .join(format!("chatex-sdk-{}-{}", name, std::process::id()))
+            .join("tokens.json")
+    }
+
+    #[test]
+    fn key_does_not_contain_api_key() {
+        let key = key_for(&SECRET.into());
+        assert_eq!(key.len(), 64);
+        assert!(!key.contains(SECRET));
+        assert_eq!(key, key_for(&SECRET.into()));
+    }
+
+    #[test]
+    fn file_store_round_trip() {
+        let path = temporary_path("round-trip");
+        let store = FileTokenStore::new(&path);
+        assert!(store.load("key").is_none());
+        let token = create_valid_token();
+        store.store("key", &token).unwrap();
+        store
+            .store("other", &models::AccessToken::default())
+            .unwrap();
+        let loaded = FileTokenStore::new(&path).load("key").unwrap();
+        assert_eq!(loaded.access_token.expose(), "TOKEN");
+        assert_eq!(loaded.expires_at, token.expires_at);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn file_stores_share_path() {
+        let path = temporary_path("shared");
+        let threads: Vec<_> = (0..4)
+            .map(|thread| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let store = FileTokenStore::new(&path);
+                    for index in 0..10 {
+                        let key = format!("{}-{}", thread, index);
+                        store.store(&key, &create_valid_token()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let store = FileTokenStore::new(&path);
+        for thread in 0..4 {
+            for index in 0..10 {
+                assert!(store.load(&format!("{}-{}", thread, index)).is_some());
+            }
+        }
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn stored_token_skips_auth_request() {
+        let store = std::sync::Arc::new(MemoryTokenStore::new());
+        store
+            .store(&key_for(&SECRET.into()), &create_valid_token())
+            .unwrap();
+        let case = TestCase::with_token_store(store);
+        let access_token_mock = case.mock_access_token();
+        let access_token = tokio_test::block_on(case.client_base.get_access_token());
+        assert_eq!(access_token.unwrap().expose(), "TOKEN");
+        access_token_mock.assert_hits(0);
+    }
+
+    #[test]
+    fn refreshed_token_is_stored() {
+        let store = std::sync::Arc::new(MemoryTokenStore::new());
+        let case = TestCase::with_token_store(store.clone());
+        let access_token_mock = case.mock_access_token();
+        tokio_test::block_on(case.client_base.get_access_token()).unwrap();
+        let stored = store.load(&key_for(&SECRET.into())).unwrap();
+        assert_eq!(stored.access_token.expose(), "TOKEN");
+        access_token_mock.assert();
+    }
+}
@@ -0,0 +1,282 @@
+use super::backend;
+use super::clock;
+use super::error;
+#[cfg(feature = "journal")]
+use super::journal;
+#[cfg(feature = "metrics")]
+use super::metrics;
+use super::middleware;
+use super::rate_limiter;
+use super::retry;
+
+/// Sends requests through the middleware chain to the HTTP backend.
+///
+/// Shared by every sub-client and by the `AccessController`, so all the
+/// hooks see every request made by the SDK.
+pub struct Transport<TBackend> {
+    pub backend: std::sync::Arc<TBackend>,
+    pub middleware: middleware::MiddlewareChain,
+    /// Server time estimated from the `Date` header of every response.
+    pub clock: std::sync::Arc<clock::ServerClock>,
+    pub retry: retry::RetryPolicy,
+    pub rate_limiter: Option<std::sync::Arc<rate_limiter::RateLimiter>>,
+    /// Limit for a single attempt, from sending the request to reading the
+    /// whole response.
+    pub timeout: Option<std::time::Duration>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<std::sync::Arc<dyn metrics::Recorder>>,
+    #[cfg(feature = "journal")]
+    pub journal: Option<std::sync::Arc<journal::Journal>>,
+}
+
+impl<TBackend> Transport<TBackend>
+where
+    TBackend: backend::HttpBackend,
+{
+    pub fn new(
+        backend: std::sync::Arc<TBackend>,
+        middleware: middleware::MiddlewareChain,
+    ) -> Transport<TBackend> {
+        Transport {
+            backend,
+            middleware,
+            clock: Default::default(),
+            retry: retry::RetryPolicy::none(),
+            rate_limiter: None,
+            timeout: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "journal")]
+            journal: None,
+        }
+    }
+
+    /// Sends the request and converts error status codes into `error::Error`.
+    ///
+    /// Failed attempts are repeated according to the `retry` policy. With
+    /// the `tracing` feature the status, the latency and the number of
+    /// retries are recorded into the current span.
+    pub async fn send(
+        &self,
+        request: http::Request<backend::Body>,
+    ) -> Result<(http::response::Parts, backend::Body), error::Error> {
+        if !self.retry.allows(request.method()) {
+            return self.send_once(request).await.map_err(|(error, _)| error);
+        }
+        let (parts, body) = request.into_parts();
+        let mut backoff = self.retry.backoff();
+        let mut retries = 0;
+        loop {
+            let mut request = http::Request::new(body.clone());
+            *request.method_mut() = parts.method.clone();
+            *request.uri_mut() = parts.uri.clone();
+            *request.version_mut() = parts.version;
+            *request.headers_mut() = parts.headers.clone();
+            let (error, retryable) = match self.send_once(request).await {
+                Ok(response) => return Ok(response),
+                Err(failure) => failure,
+            };
+            match self.retry.delay(retries, retryable, &error, &mut backoff) {
+                Some(delay) => {
+                    log::debug!("Retrying {} {} in {:?}", parts.method, parts.uri, delay);
+                    tokio::time::sleep(delay).await;
+                    retries += 1;
+                    #[cfg(feature = "tracing")]
+                    tracing::Span::current().record("retries", retries);
+                }
+                None => return Err(error),
+            }
+        }
+    }
+
+    /// Makes a single attempt. The error comes with whether it is worth
+    /// retrying.
+    async fn send_once(
+        &self,
+        mut request: http::Request<backend::Body>,
+    ) -> Result<(http::response::Parts, backend::Body), (error::Error, bool)> {
+        if let Some(rate_limiter) = self.rate_limiter.as_ref() {
+            rate_limiter.acquire().await;
+        }
+        self.middleware.before_send(&mut request);
+        let request_info = middleware::RequestInfo::new(&request);
+        let started_at = std::time::Instant::now();
+        let response = self.backend.send(request);
+        let response = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, response).await {
+                Ok(response) => response,
+                Err(_) => Err(backend::BackendError::new(format!(
+                    "timed out after {:?}",
+                    timeout
+                ))),
+            },
+            None => response.await,
+        };
+        let (header, body) = match response {
+            Ok(response) => response.into_parts(),
+            Err(error) => {
+                log::error!("{}", error);
+                #[cfg(feature = "tracing")]
+                tracing::error!(error = %error, "request failed");
+                let retryable = error.is_retryable();
+                let error = error::Error::InternalServerError;
+                self.middleware.on_error(&request_info, &error);
+                return Err((error, retryable));
+            }
+        };
+        let latency = started_at.elapsed();
+        self.clock.observe(&header.headers);
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("status", header.status.as_u16());
+            span.record("latency_ms", latency.as_millis() as u64);
+        }
+        self.middleware.after_receive(
+            &request_info,
+            &middleware::ResponseInfo {
+                status: header.status,
+                headers: &header.headers,
+                latency,
+            },
+        );
+        if error::Error::is_error_code(header.status) {
+            let error = error::Error::to_error(header.status, body).await;
+            self.middleware.on_error(&request_info, &error);
+            Err((error, retry::is_retryable_status(header.status)))
+        } else {
+            Ok((header, body))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::*;
+
+    #[test]
+    fn retries_idempotent_requests_only() {
+        let case = TestCase::with_transport(|transport| {
+            transport.retry = retry::RetryPolicy {
+                max_retries: 2,
+                initial_backoff: std::time::Duration::from_millis(1),
+                max_backoff: std::time::Duration::from_millis(1),
+                retry_non_idempotent: false,
+            };
+        });
+        let unavailable_mock = case.server.mock(|when, then| {
+            when.path("/unavailable");
+            then.status(503);
+        });
+        let missing_mock = case.server.mock(|when, then| {
+            when.path("/missing");
+            then.status(404);
+        });
+        let transport = &case.client_base.transport;
+        let request = |method: http::Method, path: &str| {
+            http::Request::builder()
+                .method(method)
+                .uri(format!("{}{}", case.server.base_url(), path))
+                .body(backend::Body::new())
+                .unwrap()
+        };
+        let result = tokio_test::block_on(
+            transport.send(request(http::Method::GET, "/unavailable")),
+        );
+        assert!(matches!(result, Err(error::Error::InternalServerError)));
+        unavailable_mock.assert_hits(3);
+        let result = tokio_test::block_on(
+            transport.send(request(http::Method::POST, "/unavailable")),
+        );
+        assert!(matches!(result, Err(error::Error::InternalServerError)));
+        unavailable_mock.assert_hits(4);
+        let result =
+            tokio_test::block_on(transport.send(request(http::Method::GET, "/missing")));
+        assert!(matches!(result, Err(error::Error::NotFoundError)));
+        missing_mock.assert_hits(1);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn spans_carry_call_fields() {
+        let case = TestCase::with_transport(|transport| {
+            transport.retry = retry::RetryPolicy {
+                max_retries: 2,
+                initial_backoff: std::time::Duration::from_millis(1),
+                max_backoff: std::time::Duration::from_millis(1),
+                retry_non_idempotent: false,
+            };
+        });
+        let _access_token_mock = case.mock_access_token();
+        case.server.mock(|when, then| {
+            default_get_when(when).path("/exchange/orders");
+            default_then_content_type(then).status(200).body("[]");
+        });
+        case.server.mock(|when, then| {
+            default_get_when(when).path("/exchange/orders/7");
+            then.status(503);
+        });
+        let client = crate::ExchangeClient::new(
+            case.client_base.clone(),
+            std::sync::Arc::new(crate::endpoint::Exchange::new(&case.base_context)),
+        );
+        let pair =
+            crate::coin::CoinPair::new(crate::coin::Coin::BTC, crate::coin::Coin::USDT);
+        let subscriber = std::sync::Arc::new(CapturingSubscriber::default());
+        tracing::subscriber::with_default(subscriber.clone(), || {
+            tokio_test::block_on(client.get_all_orders(pair, None, None)).unwrap();
+            tokio_test::block_on(client.get_order_by_id("7")).unwrap_err();
+        });
+
+        let spans = subscriber.spans();
+        let field = |index: usize, name: &str| spans[index].fields.get(name).cloned();
+        let names: Vec<&str> = spans.iter().map(|span| span.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "chatex.token_refresh",
+                "chatex.call",
+                "chatex.token_refresh",
+                "chatex.call"
+            ]
+        );
+        for refresh in [0, 2] {
+            assert_eq!(
+                field(refresh, "endpoint").as_deref(),
+                Some("profile.get_access_token")
+            );
+            assert_eq!(field(refresh, "method").as_deref(), Some("POST"));
+            assert_eq!(
+                field(refresh, "path").as_deref(),
+                Some("/auth/access-token")
+            );
+            assert_eq!(field(refresh, "status").as_deref(), Some("200"));
+            assert_eq!(field(refresh, "retries").as_deref(), Some("0"));
+            assert!(field(refresh, "latency_ms").is_some());
+        }
+        assert_eq!(
+            field(1, "endpoint").as_deref(),
+            Some("exchange.get_all_orders")
+        );
+        assert_eq!(field(1, "method").as_deref(), Some("GET"));
+        assert_eq!(field(1, "path").as_deref(), Some("/exchange/orders"));
+        assert_eq!(field(1, "pair").as_deref(), Some("btc/usdt_erc20"));
+        assert_eq!(field(1, "order_id"), None);
+        assert_eq!(field(1, "status").as_deref(), Some("200"));
+        assert_eq!(field(1, "retries").as_deref(), Some("0"));
+        assert!(field(1, "latency_ms").is_some());
+        assert_eq!(
+            field(3, "endpoint").as_deref(),
+            Some("exchange.get_order_by_id")
+        );
+        assert_eq!(field(3, "path").as_deref(), Some("/exchange/orders/7"));
+        assert_eq!(field(3, "order_id").as_deref(), Some("7"));
+        assert_eq!(field(3, "status").as_deref(), Some("503"));
+        assert_eq!(field(3, "retries").as_deref(), Some("2"));
+        for value in subscriber.values() {
+            assert!(!value.contains("TOKEN"), "Token leaked in {}", value);
+            assert!(!value.contains(SECRET), "API key leaked in {}", value);
+        }
+    }
+}